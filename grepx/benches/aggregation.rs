@@ -0,0 +1,161 @@
+//! Microbenchmark for result aggregation over a tree of many tiny files.
+//!
+//! Compares the engine's per-thread totals, merged with `SearchResult::merge`,
+//! against adding every file's statistics to one `Mutex<SearchResult>`: first
+//! for the aggregation step alone, using the statistics of the files in the
+//! tree, then for full searches of the tree with and without that lock.
+//!
+//! Run with `cargo bench --bench aggregation`. The number of files defaults to
+//! one million and can be changed with `GREPX_BENCH_FILES`. The tree is created
+//! once in the system temp directory and reused by later runs.
+
+use grepx::{FileStats, SearchResult, SearcherBuilder, Sink};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const FILES_PER_DIR: usize = 1000;
+const RUNS: usize = 5;
+
+/// Sink that ignores everything, leaving the engine's own totals
+struct Discard;
+
+impl Sink for Discard {}
+
+/// Sink that adds every file's statistics to a result behind one lock, the
+/// way results were aggregated before per-thread totals
+#[derive(Default)]
+struct Locked(Mutex<SearchResult>);
+
+impl Sink for Locked {
+    fn on_file_end(&self, _path: Option<&Path>, stats: &FileStats) {
+        self.0.lock().unwrap().add(stats);
+    }
+}
+
+/// Sink that keeps the statistics of every file
+#[derive(Default)]
+struct Recorder(Mutex<Vec<FileStats>>);
+
+impl Sink for Recorder {
+    fn on_file_end(&self, _path: Option<&Path>, stats: &FileStats) {
+        self.0.lock().unwrap().push(stats.clone());
+    }
+}
+
+/// Create the benchmark tree unless it already exists
+fn create_tree(files: usize) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("grepx-bench-aggregation-{}", files));
+    let marker = root.with_extension("complete");
+    if marker.exists() && root.exists() {
+        return root;
+    }
+    
+    println!("Creating {} files in {}", files, root.display());
+    (0..files.div_ceil(FILES_PER_DIR)).into_par_iter().for_each(|dir| {
+        let dir_path = root.join(format!("d{:04}", dir));
+        fs::create_dir_all(&dir_path).expect("Failed to create benchmark directory");
+        
+        let end = ((dir + 1) * FILES_PER_DIR).min(files);
+        for file in dir * FILES_PER_DIR..end {
+            let content = if file % 10 == 0 { "needle\n" } else { "hay\n" };
+            fs::write(dir_path.join(format!("f{}.txt", file)), content)
+                .expect("Failed to create benchmark file");
+        }
+    });
+    fs::write(&marker, "").expect("Failed to create benchmark marker");
+    root
+}
+
+/// Median of the durations of several runs of `f`
+fn median<F: FnMut()>(mut f: F) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+/// Compare a shared mutex against per-thread totals for the aggregation
+/// step alone, using the statistics of the files in the tree
+fn bench_strategies(stats: &[FileStats]) {
+    let mutex = median(|| {
+        let result = Mutex::new(SearchResult::default());
+        stats.iter().par_bridge().for_each(|stats| {
+            result.lock().unwrap().add(stats);
+        });
+        assert_eq!(result.into_inner().unwrap().files_searched, stats.len());
+    });
+    
+    let fold = median(|| {
+        let result = stats.iter()
+            .par_bridge()
+            .fold(SearchResult::default, |mut result, stats| {
+                result.add(stats);
+                result
+            })
+            .reduce(SearchResult::default, |mut result, other| {
+                result.merge(other);
+                result
+            });
+        assert_eq!(result.files_searched, stats.len());
+    });
+    
+    println!("aggregation, Mutex<SearchResult>: {:>10.2?}", mutex);
+    println!("aggregation, fold/reduce:         {:>10.2?} ({:.1}x)",
+             fold, mutex.as_secs_f64() / fold.as_secs_f64());
+}
+
+/// Time full recursive searches of the tree, with the engine's totals alone
+/// and with every file also added to a shared mutex
+fn bench_search(root: &Path, files: usize) {
+    let searcher = SearcherBuilder::new("needle")
+        .recursive(true)
+        .ordered(false)
+        .build()
+        .expect("Failed to build searcher");
+    
+    let mutex = median(|| {
+        let sink = Locked::default();
+        searcher.search_path_with(root, &sink).expect("Search failed");
+        assert_eq!(sink.0.into_inner().unwrap().files_searched, files);
+    });
+    let fold = median(|| {
+        let result = searcher.search_path_with(root, &Discard).expect("Search failed");
+        assert_eq!(result.files_searched, files);
+    });
+    
+    println!("search of {} files, Mutex<SearchResult>: {:>10.2?} ({:.0} files/s)",
+             files, mutex, files as f64 / mutex.as_secs_f64());
+    println!("search of {} files, fold/reduce:         {:>10.2?} ({:.0} files/s, {:.1}x)",
+             files, fold, files as f64 / fold.as_secs_f64(), mutex.as_secs_f64() / fold.as_secs_f64());
+}
+
+/// Statistics of every file in the tree, as the engine reports them
+fn file_stats(root: &Path) -> Vec<FileStats> {
+    let searcher = SearcherBuilder::new("needle")
+        .recursive(true)
+        .ordered(false)
+        .build()
+        .expect("Failed to build searcher");
+    let recorder = Recorder::default();
+    searcher.search_path_with(root, &recorder).expect("Search failed");
+    recorder.0.into_inner().unwrap()
+}
+
+fn main() {
+    let files = std::env::var("GREPX_BENCH_FILES")
+        .ok()
+        .and_then(|files| files.parse().ok())
+        .unwrap_or(1_000_000);
+    
+    let root = create_tree(files);
+    bench_strategies(&file_stats(&root));
+    bench_search(&root, files);
+}
@@ -0,0 +1,129 @@
+//! Cold-cache benchmark for I/O prefetching.
+//!
+//! Run with `cargo bench --bench prefetch`. The corpus holds many medium files
+//! and a few large ones, 1 GB in total by default (set `GREPX_BENCH_MB` to
+//! change it). Before each run the corpus is evicted from the page cache with
+//! `posix_fadvise(POSIX_FADV_DONTNEED)`, so no special privileges are needed.
+//! The corpus must be on a disk-backed file system, not tmpfs. Set
+//! `GREPX_BENCH_THREADS` to change the number of search threads.
+
+#[cfg(target_os = "linux")]
+fn main() {
+    linux::main();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    println!("The prefetch benchmark requires Linux");
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use grepx::SearcherBuilder;
+    use std::fs::{self, File};
+    use std::io::{BufWriter, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+    
+    const RUNS: usize = 11;
+    const MEDIUM_FILE: usize = 256 * 1024;
+    const LARGE_FILES: usize = 4;
+    
+    /// Create the corpus unless it already exists, returning its root and file count
+    fn create_corpus(megabytes: usize) -> (PathBuf, usize) {
+        let root = std::env::temp_dir().join(format!("grepx-bench-prefetch-{}", megabytes));
+        let marker = root.with_extension("complete");
+        
+        // Half the corpus is in medium files and half in a few large files
+        let total = megabytes * 1024 * 1024;
+        let medium_files = total / 2 / MEDIUM_FILE;
+        let large_file = total / 2 / LARGE_FILES;
+        
+        if !marker.exists() {
+            println!("Creating {} MB corpus in {}", megabytes, root.display());
+            let line = b"the quick brown fox jumps over the lazy dog while the cache is cold\n";
+            let write_file = |path: &Path, size: usize| {
+                let mut out = BufWriter::new(File::create(path).expect("Failed to create benchmark file"));
+                for _ in 0..size / line.len() {
+                    out.write_all(line).expect("Failed to write benchmark file");
+                }
+                out.write_all(b"needle\n").expect("Failed to write benchmark file");
+            };
+            
+            fs::create_dir_all(&root).expect("Failed to create benchmark directory");
+            for index in 0..medium_files {
+                write_file(&root.join(format!("medium_{:05}.txt", index)), MEDIUM_FILE);
+            }
+            for index in 0..LARGE_FILES {
+                write_file(&root.join(format!("large_{}.txt", index)), large_file);
+            }
+            fs::write(&marker, "").expect("Failed to create benchmark marker");
+        }
+        
+        (root, medium_files + LARGE_FILES)
+    }
+    
+    /// Drop the corpus from the page cache
+    fn evict(root: &Path) {
+        for entry in fs::read_dir(root).expect("Failed to read benchmark directory") {
+            let file = File::open(entry.expect("Failed to read benchmark directory").path())
+                .expect("Failed to open benchmark file");
+            file.sync_all().expect("Failed to sync benchmark file");
+            unsafe {
+                libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+            }
+        }
+    }
+    
+    /// Median times to search the corpus without and with prefetching. Runs
+    /// alternate between the two so that drift in disk speed affects both
+    /// equally. The corpus is evicted before each run when `cold` is set.
+    fn bench(root: &Path, files: usize, cold: bool) -> (Duration, Duration) {
+        let threads = std::env::var("GREPX_BENCH_THREADS")
+            .ok()
+            .and_then(|threads| threads.parse().ok())
+            .unwrap_or(0);
+        let searchers = [false, true].map(|prefetch| {
+            SearcherBuilder::new("needle")
+                .threads(threads)
+                .prefetch(prefetch)
+                .build()
+                .expect("Failed to build searcher")
+        });
+        
+        let mut times = [Vec::new(), Vec::new()];
+        for _ in 0..RUNS {
+            for (searcher, times) in searchers.iter().zip(&mut times) {
+                if cold {
+                    evict(root);
+                }
+                let start = Instant::now();
+                let result = searcher.search_path(root).expect("Search failed");
+                times.push(start.elapsed());
+                assert_eq!(result.total_matches, files);
+            }
+        }
+        
+        let [mut without, mut with] = times;
+        without.sort();
+        with.sort();
+        (without[RUNS / 2], with[RUNS / 2])
+    }
+    
+    pub fn main() {
+        let megabytes = std::env::var("GREPX_BENCH_MB")
+            .ok()
+            .and_then(|megabytes| megabytes.parse().ok())
+            .unwrap_or(1024);
+        let (root, files) = create_corpus(megabytes);
+        
+        for cold in [true, false] {
+            let (without, with) = bench(&root, files, cold);
+            let cache = if cold { "cold" } else { "warm" };
+            println!("{} cache, no prefetch: {:>10.2?}", cache, without);
+            println!("{} cache, prefetch:    {:>10.2?} ({:.2}x)",
+                     cache, with, without.as_secs_f64() / with.as_secs_f64());
+        }
+    }
+}
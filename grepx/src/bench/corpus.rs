@@ -0,0 +1,236 @@
+use anyhow::{Result, Context};
+use clap::ValueEnum;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Words that generated text is made of. The benchmark patterns are chosen to
+/// match some of them.
+const WORDS: &[&str] = &[
+    "request", "response", "connection", "timeout", "retry", "session", "user",
+    "cache", "miss", "hit", "query", "database", "handler", "worker", "queue",
+    "ERROR", "WARN", "INFO", "DEBUG", "failed", "completed", "started", "closed",
+    "NullPointerException", "IllegalStateException", "IOException", "buffer",
+    "stream", "socket", "payload", "config", "token", "expired", "refresh",
+];
+
+const LEVELS: &[&str] = &["INFO", "INFO", "INFO", "DEBUG", "DEBUG", "WARN", "ERROR"];
+
+const KEYWORDS: &[&str] = &["fn", "let", "if", "else", "return", "match", "impl", "struct", "for", "while"];
+
+/// Synthetic corpus that the benchmark searches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Corpus {
+    /// Many small text files spread over a directory tree
+    SmallFiles,
+    /// A single large log file
+    LargeLog,
+    /// A source code tree with nested modules
+    CodeTree,
+    /// Text files mixed with binary files
+    BinaryMix,
+}
+
+impl Corpus {
+    pub const ALL: [Corpus; 4] = [Corpus::SmallFiles, Corpus::LargeLog, Corpus::CodeTree, Corpus::BinaryMix];
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            Corpus::SmallFiles => "small-files",
+            Corpus::LargeLog => "large-log",
+            Corpus::CodeTree => "code-tree",
+            Corpus::BinaryMix => "binary-mix",
+        }
+    }
+}
+
+/// Sizes of the generated corpora
+#[derive(Debug, Clone, Copy)]
+pub struct CorpusSizes {
+    pub small_files: usize,
+    pub log_size: u64,
+}
+
+/// Total size and number of files of a generated corpus
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CorpusInfo {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Generate a corpus under `dir` unless an identical one was generated before,
+/// returning the corpus root
+pub fn prepare(corpus: Corpus, dir: &Path, sizes: CorpusSizes) -> Result<(PathBuf, CorpusInfo)> {
+    let root = dir.join(corpus.name());
+    
+    // The marker records the sizes the corpus was generated with
+    let marker = dir.join(format!("{}.complete", corpus.name()));
+    let signature = format!("{} {}", sizes.small_files, sizes.log_size);
+    if fs::read_to_string(&marker).is_ok_and(|existing| existing == signature) {
+        return Ok((root.clone(), measure(&root)));
+    }
+    
+    if root.exists() {
+        fs::remove_dir_all(&root)
+            .with_context(|| format!("Failed to remove old corpus: {}", root.display()))?;
+    }
+    fs::create_dir_all(&root)
+        .with_context(|| format!("Failed to create corpus directory: {}", root.display()))?;
+    
+    eprintln!("Generating {} corpus in {}", corpus.name(), root.display());
+    let mut rng = Rng::new(corpus as u64 + 1);
+    match corpus {
+        Corpus::SmallFiles => generate_small_files(&root, sizes.small_files, &mut rng)?,
+        Corpus::LargeLog => generate_log(&root.join("app.log"), sizes.log_size, &mut rng)?,
+        Corpus::CodeTree => generate_code_tree(&root, &mut rng)?,
+        Corpus::BinaryMix => generate_binary_mix(&root, &mut rng)?,
+    }
+    
+    fs::write(&marker, signature)
+        .with_context(|| format!("Failed to write corpus marker: {}", marker.display()))?;
+    Ok((root.clone(), measure(&root)))
+}
+
+/// Count the files and bytes of a corpus
+fn measure(root: &Path) -> CorpusInfo {
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .fold(CorpusInfo::default(), |info, metadata| CorpusInfo {
+            files: info.files + 1,
+            bytes: info.bytes + metadata.len(),
+        })
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create file: {}", path.display()))?;
+    Ok(BufWriter::with_capacity(256 * 1024, file))
+}
+
+/// Many files of roughly 1KB, 1000 per directory
+fn generate_small_files(root: &Path, files: usize, rng: &mut Rng) -> Result<()> {
+    for index in 0..files {
+        let path = root.join(format!("d{:04}", index / 1000)).join(format!("f{:06}.txt", index));
+        let mut out = create_file(&path)?;
+        for _ in 0..12 {
+            write_sentence(&mut out, rng, 12)?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// A log file of about `size` bytes
+fn generate_log(path: &Path, size: u64, rng: &mut Rng) -> Result<()> {
+    let mut out = create_file(path)?;
+    let mut written = 0u64;
+    let mut line = Vec::with_capacity(256);
+    let mut seconds = 0u64;
+    
+    while written < size {
+        line.clear();
+        seconds += rng.below(3) as u64;
+        write!(line, "2024-01-{:02}T{:02}:{:02}:{:02}Z {} [{}] ",
+               1 + seconds / 86400 % 28, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60,
+               rng.pick(LEVELS), rng.pick(&["api", "db", "auth", "scheduler"]))?;
+        write_sentence(&mut line, rng, 10)?;
+        
+        out.write_all(&line)?;
+        written += line.len() as u64;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Source files in nested module directories
+fn generate_code_tree(root: &Path, rng: &mut Rng) -> Result<()> {
+    for module in 0..50 {
+        for file in 0..100 {
+            let path = root.join(format!("module_{:02}", module))
+                .join(format!("sub_{}", file % 5))
+                .join(format!("file_{:03}.rs", file));
+            let mut out = create_file(&path)?;
+            
+            for function in 0..20 {
+                writeln!(out, "fn {}_{}(input: &str) -> Result<(), Error> {{",
+                         rng.pick(WORDS).to_lowercase(), function)?;
+                for _ in 0..rng.below(8) + 2 {
+                    write!(out, "    {} ", rng.pick(KEYWORDS))?;
+                    write_sentence(&mut out, rng, 6)?;
+                }
+                writeln!(out, "}}")?;
+                writeln!(out)?;
+            }
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Text files interleaved with binary files containing NUL bytes and
+/// invalid UTF-8, some of which also contain words the patterns match
+fn generate_binary_mix(root: &Path, rng: &mut Rng) -> Result<()> {
+    for index in 0..2000 {
+        if index % 2 == 0 {
+            let mut out = create_file(&root.join(format!("text_{:04}.txt", index)))?;
+            for _ in 0..200 {
+                write_sentence(&mut out, rng, 12)?;
+            }
+            out.flush()?;
+        } else {
+            let mut out = create_file(&root.join(format!("blob_{:04}.bin", index)))?;
+            let mut bytes = vec![0u8; 16 * 1024];
+            for (offset, byte) in bytes.iter_mut().enumerate() {
+                *byte = if offset % 64 == 0 { 0 } else { rng.next() as u8 };
+            }
+            out.write_all(&bytes)?;
+            write_sentence(&mut out, rng, 12)?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a line of `words` random words
+fn write_sentence<W: Write>(out: &mut W, rng: &mut Rng, words: usize) -> Result<()> {
+    for word in 0..words {
+        if word > 0 {
+            out.write_all(b" ")?;
+        }
+        out.write_all(rng.pick(WORDS).as_bytes())?;
+    }
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Small deterministic pseudo-random generator (xorshift64*), so that
+/// corpora are identical across runs and machines
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+    
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
@@ -0,0 +1,305 @@
+//! `grepx bench`: compares grepx against other search tools on generated corpora
+
+mod corpus;
+
+use anyhow::{Result, Context};
+use clap::{Parser, ValueEnum};
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use corpus::{Corpus, CorpusInfo, CorpusSizes};
+use grepx::utils::{calculate_speed, format_duration, format_size, format_speed, parse_size};
+
+/// Benchmark grepx against grep, ripgrep, ag and ugrep on synthetic corpora
+#[derive(Parser, Debug)]
+#[command(bin_name = "grepx bench")]
+pub struct BenchArgs {
+    /// Corpora to benchmark, comma separated (default: all)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub corpus: Vec<Corpus>,
+    
+    /// Directory where corpora are generated and reused between runs
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+    
+    /// Number of files in the small-files corpus
+    #[arg(long, value_name = "NUM", default_value_t = 100_000)]
+    pub small_files: usize,
+    
+    /// Size of the large-log corpus (e.g. 500M, 10G)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, default_value = "10G")]
+    pub log_size: u64,
+    
+    /// Timed runs per tool and pattern, after one warm-up run
+    #[arg(long, value_name = "NUM", default_value_t = 5)]
+    pub runs: usize,
+    
+    /// Tools to compare, comma separated (default: all that are installed)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub tools: Vec<Tool>,
+    
+    /// Report format
+    #[arg(short, long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+/// Search tool that can be benchmarked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Tool {
+    Grepx,
+    Grep,
+    Rg,
+    Ag,
+    Ugrep,
+}
+
+impl Tool {
+    const ALL: [Tool; 5] = [Tool::Grepx, Tool::Grep, Tool::Rg, Tool::Ag, Tool::Ugrep];
+    
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Grepx => "grepx",
+            Tool::Grep => "grep",
+            Tool::Rg => "rg",
+            Tool::Ag => "ag",
+            Tool::Ugrep => "ugrep",
+        }
+    }
+    
+    /// Locate the tool's executable; grepx benchmarks the running binary
+    fn locate(self) -> Option<PathBuf> {
+        match self {
+            Tool::Grepx => std::env::current_exe().ok(),
+            tool => find_in_path(tool.name()),
+        }
+    }
+    
+    /// Arguments for a recursive search printing one line per matching line.
+    /// Binary files are skipped by every tool.
+    fn args(self, pattern: &BenchPattern, root: &Path) -> Vec<String> {
+        let mut args: Vec<String> = match self {
+            Tool::Grepx => vec!["-r", "-f", "grep", "--no-messages"],
+            Tool::Grep | Tool::Ugrep => vec!["-r", "-I", "-E"],
+            Tool::Rg => vec!["--no-config", "--no-heading", "--no-line-number", "--color", "never", "-uu"],
+            Tool::Ag => vec!["--nocolor", "--noheading", "--nobreak", "--nonumbers", "--hidden"],
+        }
+        .into_iter()
+        .map(String::from)
+        .collect();
+        
+        if pattern.ignore_case {
+            args.push("-i".to_string());
+        }
+        match self {
+            Tool::Grepx | Tool::Ag => args.push("--".to_string()),
+            _ => args.push("-e".to_string()),
+        }
+        args.push(pattern.pattern.to_string());
+        args.push(root.to_string_lossy().into_owned());
+        args
+    }
+}
+
+/// A pattern in the benchmark suite
+struct BenchPattern {
+    name: &'static str,
+    pattern: &'static str,
+    ignore_case: bool,
+}
+
+const PATTERNS: &[BenchPattern] = &[
+    BenchPattern { name: "literal", pattern: "timeout", ignore_case: false },
+    BenchPattern { name: "ignore-case", pattern: "error", ignore_case: true },
+    BenchPattern { name: "alternation", pattern: "ERROR|WARN|expired", ignore_case: false },
+    BenchPattern { name: "class", pattern: "[A-Z][a-z]+Exception", ignore_case: false },
+    BenchPattern { name: "no-match", pattern: "zqxjvkw", ignore_case: false },
+];
+
+/// Timings of one tool searching one corpus for one pattern
+struct Measurement {
+    pattern: &'static str,
+    tool: Tool,
+    median: Duration,
+    p95: Duration,
+    /// Bytes of corpus searched per second, based on the median time
+    throughput: f64,
+    /// Number of matching lines printed
+    lines: usize,
+    /// Whether the matching lines agree with grepx, `None` for grepx itself
+    agrees: Option<bool>,
+}
+
+/// Generate the requested corpora, run the pattern suite with every
+/// installed tool and print a report
+pub fn run(args: &BenchArgs) -> Result<()> {
+    let dir = args.dir.clone()
+        .unwrap_or_else(|| std::env::temp_dir().join("grepx-bench"));
+    let sizes = CorpusSizes {
+        small_files: args.small_files,
+        log_size: args.log_size,
+    };
+    let corpora = if args.corpus.is_empty() { Corpus::ALL.to_vec() } else { args.corpus.clone() };
+    let requested = if args.tools.is_empty() { Tool::ALL.to_vec() } else { args.tools.clone() };
+    
+    // grepx is always run, as the reference the other tools are checked against
+    let mut tools = vec![(Tool::Grepx, Tool::Grepx.locate().context("Failed to locate the grepx executable")?)];
+    for tool in requested.into_iter().filter(|&tool| tool != Tool::Grepx) {
+        match tool.locate() {
+            Some(path) => tools.push((tool, path)),
+            None => eprintln!("Skipping {}: not installed", tool.name()),
+        }
+    }
+    
+    let mut report = Vec::new();
+    for corpus in corpora {
+        let (root, info) = corpus::prepare(corpus, &dir, sizes)?;
+        let mut measurements = Vec::new();
+        
+        for pattern in PATTERNS {
+            let mut reference = None;
+            for (tool, program) in &tools {
+                eprintln!("Running {} on {} ({})", tool.name(), corpus.name(), pattern.name);
+                let mut measurement = measure(*tool, program, pattern, &root, &info, args.runs)?;
+                match reference {
+                    None => reference = Some(measurement.lines),
+                    Some(lines) => measurement.agrees = Some(measurement.lines == lines),
+                }
+                measurements.push(measurement);
+            }
+        }
+        report.push((corpus, info, measurements));
+    }
+    
+    match args.format {
+        ReportFormat::Text => print_text(&report),
+        ReportFormat::Json => print_json(&tools, &report)?,
+    }
+    Ok(())
+}
+
+/// Run one tool repeatedly and summarize its timings
+fn measure(tool: Tool, program: &Path, pattern: &BenchPattern, root: &Path, info: &CorpusInfo, runs: usize) -> Result<Measurement> {
+    let args = tool.args(pattern, root);
+    
+    // The warm-up run also fills the page cache and counts the matching lines
+    let (_, lines) = run_tool(program, &args)?;
+    let mut times = Vec::with_capacity(runs);
+    for _ in 0..runs.max(1) {
+        times.push(run_tool(program, &args)?.0);
+    }
+    times.sort();
+    
+    let median = times[times.len() / 2];
+    let p95 = times[(times.len() * 95).div_ceil(100) - 1];
+    
+    Ok(Measurement {
+        pattern: pattern.name,
+        tool,
+        median,
+        p95,
+        throughput: calculate_speed(info.bytes as usize, median.as_secs_f64()),
+        lines,
+        agrees: None,
+    })
+}
+
+/// Run a tool to completion, returning how long it took and how many lines it printed
+fn run_tool(program: &Path, args: &[String]) -> Result<(Duration, usize)> {
+    let start = Instant::now();
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program.display()))?;
+    
+    // Count lines as they are produced instead of holding the whole output
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut buffer = vec![0; 64 * 1024];
+    let mut lines = 0;
+    loop {
+        let read = stdout.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        lines += memchr::memchr_iter(b'\n', &buffer[..read]).count();
+    }
+    child.wait()?;
+    
+    Ok((start.elapsed(), lines))
+}
+
+fn print_text(report: &[(Corpus, CorpusInfo, Vec<Measurement>)]) {
+    for (corpus, info, measurements) in report {
+        println!("{}: {} files, {}", corpus.name(), info.files, format_size(info.bytes as usize));
+        println!("  {:<12} {:<6} {:>12} {:>12} {:>14} {:>10}  agrees",
+                 "pattern", "tool", "median", "p95", "throughput", "lines");
+        
+        for m in measurements {
+            let agrees = match m.agrees {
+                None => "-",
+                Some(true) => "yes",
+                Some(false) => "NO",
+            };
+            println!("  {:<12} {:<6} {:>12} {:>12} {:>14} {:>10}  {}",
+                     m.pattern,
+                     m.tool.name(),
+                     format_duration(m.median.as_secs_f64()),
+                     format_duration(m.p95.as_secs_f64()),
+                     format_speed(m.throughput),
+                     m.lines,
+                     agrees);
+        }
+        println!();
+    }
+}
+
+fn print_json(tools: &[(Tool, PathBuf)], report: &[(Corpus, CorpusInfo, Vec<Measurement>)]) -> Result<()> {
+    let tools: Vec<Value> = tools.iter()
+        .map(|(tool, path)| json!({ "name": tool.name(), "path": path.to_string_lossy() }))
+        .collect();
+    
+    let corpora: Vec<Value> = report.iter()
+        .map(|(corpus, info, measurements)| {
+            let results: Vec<Value> = measurements.iter()
+                .map(|m| json!({
+                    "pattern": m.pattern,
+                    "tool": m.tool.name(),
+                    "median": m.median.as_secs_f64(),
+                    "p95": m.p95.as_secs_f64(),
+                    "bytes_per_second": m.throughput,
+                    "lines": m.lines,
+                    "agrees": m.agrees,
+                }))
+                .collect();
+            json!({
+                "name": corpus.name(),
+                "files": info.files,
+                "bytes": info.bytes,
+                "results": results,
+            })
+        })
+        .collect();
+    
+    let report = json!({ "tools": tools, "corpora": corpora });
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Find an executable on the `PATH`
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
@@ -1,30 +1,55 @@
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use grepx::printer::{GrepPrinter, JsonPrinter, OutputMode, PrinterOptions, QuietPrinter, TextPrinter};
+use grepx::utils::{parse_duration, parse_size};
+use grepx::{CaseMode, ReadStrategy, SearcherBuilder, Sink, SortBy};
+
+use crate::bench::BenchArgs;
 
 /// GrepX - A distributed, multi-threaded regex search engine
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(after_help = "Run `grepx bench --help` for the benchmark suite, `grepx serve --help` \
+                       to start a worker for distributed searches, `grepx server --help` to \
+                       answer searches over HTTP and `grepx index --help` to index directories \
+                       for faster repeated searches.")]
 pub struct Args {
-    /// Regex pattern to search for
+    /// Regex pattern to search for, matched one line at a time, so it may not contain a literal `\n`
     #[arg(index = 1, required = true)]
     pub pattern: String,
-
+    
     /// File paths to search (supports glob patterns)
     #[arg(index = 2, default_value = ".")]
     pub path: Vec<String>,
     
-    /// Number of threads to use (0 = auto)
+    /// Number of threads searching file contents (0 = auto)
     #[arg(short, long, default_value_t = 0)]
     pub threads: usize,
     
+    /// Number of threads walking directories and reading file metadata (0 = auto)
+    #[arg(long, default_value_t = 0)]
+    pub io_threads: usize,
+    
     /// Recursively search directories
     #[arg(short = 'r', long)]
     pub recursive: bool,
     
-    /// Case-sensitive matching
-    #[arg(short = 's', long)]
+    /// Case-insensitive matching
+    #[arg(short = 'i', long, overrides_with_all = ["case_sensitive", "smart_case"])]
+    pub ignore_case: bool,
+    
+    /// Case-sensitive matching (default)
+    #[arg(short = 's', long, overrides_with_all = ["ignore_case", "smart_case"])]
     pub case_sensitive: bool,
     
+    /// Case-insensitive unless the pattern contains an uppercase literal
+    #[arg(short = 'S', long, overrides_with_all = ["ignore_case", "case_sensitive"])]
+    pub smart_case: bool,
+    
     /// Show line numbers
     #[arg(short = 'n', long)]
     pub line_numbers: bool,
@@ -37,13 +62,109 @@ pub struct Args {
     #[arg(short = 'c', long)]
     pub count: bool,
     
+    /// Stop searching a file after NUM matching lines
+    #[arg(short = 'm', long, value_name = "NUM")]
+    pub max_count: Option<usize>,
+    
+    /// Stop the search after NUM matching lines across all files
+    #[arg(long, value_name = "NUM")]
+    pub max_total: Option<usize>,
+    
+    /// Stop the search once NUM files have matches
+    #[arg(long, value_name = "NUM")]
+    pub max_files: Option<usize>,
+    
+    /// Stop the search after DURATION (e.g. 500ms, 30s, 5m)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub timeout: Option<Duration>,
+    
+    /// Abandon a file after searching it for DURATION, reporting it as partially searched
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub file_timeout: Option<Duration>,
+    
+    /// Skip files larger than SIZE (e.g. 512K, 100M, 2G)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_filesize: Option<u64>,
+    
+    /// Print nothing and exit as soon as a match is found
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
+    
+    /// Suppress error messages about paths that could not be searched
+    #[arg(long)]
+    pub no_messages: bool,
+    
+    /// Print NUM lines of context after each match
+    #[arg(short = 'A', long, value_name = "NUM")]
+    pub after_context: Option<usize>,
+    
+    /// Print NUM lines of context before each match
+    #[arg(short = 'B', long, value_name = "NUM")]
+    pub before_context: Option<usize>,
+    
+    /// Print NUM lines of context before and after each match
+    #[arg(short = 'C', long, value_name = "NUM")]
+    pub context: Option<usize>,
+    
+    /// Sort files before searching and report results in that order
+    #[arg(long, value_enum, value_name = "SORTBY", conflicts_with = "unordered")]
+    pub sort: Option<SortArg>,
+    
+    /// Print results as soon as each file is searched, in no particular order
+    #[arg(long)]
+    pub unordered: bool,
+    
+    /// Print detailed search statistics once the search completes
+    #[arg(long)]
+    pub stats: bool,
+    
     /// Display progress bar
     #[arg(short = 'p', long)]
     pub progress: bool,
     
-    /// Chunk size in KB for parallel processing
-    #[arg(long, default_value_t = 64)]
-    pub chunk_size: usize,
+    /// Chunk size in KB for processing large files (default: chosen per file)
+    #[arg(long, value_name = "KB")]
+    pub chunk_size: Option<usize>,
+    
+    /// Do not prefetch upcoming files and chunks while searching
+    #[arg(long)]
+    pub no_prefetch: bool,
+    
+    /// How file contents are read
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = ReadArg::Auto)]
+    pub read_strategy: ReadArg,
+    
+    /// Search on these workers instead of locally, comma separated (host:port)
+    #[arg(long, value_name = "ADDRS", value_delimiter = ',')]
+    pub workers: Vec<String>,
+    
+    /// Treat a worker as failed after DURATION without hearing from it
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "10s")]
+    pub worker_timeout: Duration,
+    
+    /// Shared secret for authenticating with workers, read from FILE (default: $GREPX_TOKEN)
+    #[arg(long, value_name = "FILE")]
+    pub token_file: Option<PathBuf>,
+    
+    /// Connect to workers over TLS, trusting the CA certificates in FILE
+    #[arg(long, value_name = "FILE")]
+    pub tls_ca: Option<PathBuf>,
+    
+    /// Only scan files that may match according to the index in FILE (see `grepx index build`)
+    #[arg(long, value_name = "FILE")]
+    pub index: Option<PathBuf>,
+    
+    /// Keep files open after searching them and search the lines appended to them, following rotated and truncated files
+    #[arg(short = 'F', long, conflicts_with_all = ["watch", "workers", "index"])]
+    pub follow: bool,
+    
+    /// Keep watching the paths after searching them, searching the new content of files as they are created or modified
+    #[arg(long, conflicts_with_all = ["quiet", "workers"])]
+    pub watch: bool,
+    
+    /// Wait until a changed file has been quiet for DURATION before searching it again
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "200ms", requires = "watch")]
+    pub debounce: Duration,
     
     /// Output format
     #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
@@ -54,6 +175,209 @@ pub struct Args {
     pub log_level: LogLevel,
 }
 
+impl Args {
+    /// Resolve the case matching mode from the case flags
+    pub fn case_mode(&self) -> CaseMode {
+        if self.ignore_case {
+            CaseMode::Insensitive
+        } else if self.smart_case {
+            CaseMode::Smart
+        } else {
+            CaseMode::Sensitive
+        }
+    }
+    
+    /// Create a searcher builder configured from the command line options
+    pub fn searcher_builder(&self) -> SearcherBuilder {
+        SearcherBuilder::new(&self.pattern)
+            .case_mode(self.case_mode())
+            .recursive(self.recursive)
+            .threads(self.threads)
+            .io_threads(self.io_threads)
+            .chunk_size(self.chunk_size)
+            .read_strategy(self.read_strategy())
+            .prefetch(!self.no_prefetch)
+            .progress(self.progress)
+            .before_context(self.before_context.or(self.context).unwrap_or(0))
+            .after_context(self.after_context.or(self.context).unwrap_or(0))
+            .sort(self.sort_by())
+            .ordered(!self.unordered)
+            .quit_after_match(self.quiet)
+            .max_count(self.max_count)
+            .max_total(self.max_total)
+            .max_files(self.max_files)
+            .timeout(self.timeout)
+            .file_timeout(self.file_timeout)
+            .max_filesize(self.max_filesize)
+            .workers(&self.workers)
+            .worker_timeout(self.worker_timeout)
+            .worker_tls(self.tls_ca.clone())
+    }
+    
+    /// Resolve the file order from the sort option
+    pub fn sort_by(&self) -> SortBy {
+        match self.sort {
+            None => SortBy::Discovery,
+            Some(SortArg::Path) => SortBy::Path,
+            Some(SortArg::Modified) => SortBy::Modified,
+            Some(SortArg::Size) => SortBy::Size,
+        }
+    }
+    
+    /// Resolve the read strategy, where `None` lets the engine choose per file
+    pub fn read_strategy(&self) -> Option<ReadStrategy> {
+        match self.read_strategy {
+            ReadArg::Auto => None,
+            ReadArg::Buffered => Some(ReadStrategy::Buffered),
+            ReadArg::Mmap => Some(ReadStrategy::Mmap),
+            ReadArg::Sequential => Some(ReadStrategy::Sequential),
+        }
+    }
+    
+    /// Create the printer for the selected output format
+    pub fn printer(&self) -> Box<dyn Sink> {
+        let mode = if self.files_with_matches {
+            OutputMode::FilesWithMatches
+        } else if self.count {
+            OutputMode::Count
+        } else {
+            OutputMode::Lines
+        };
+        let options = PrinterOptions {
+            mode,
+            line_numbers: self.line_numbers,
+            no_messages: self.no_messages,
+            stats: self.stats,
+        };
+        
+        if self.quiet {
+            return Box::new(QuietPrinter::new(options));
+        }
+        
+        match self.format {
+            OutputFormat::Text => Box::new(TextPrinter::new(options)),
+            OutputFormat::Json => Box::new(JsonPrinter::new(options)),
+            OutputFormat::Grep => Box::new(GrepPrinter::new(options)),
+        }
+    }
+}
+
+/// Run a worker that searches this machine's files for distributed searches
+#[derive(Parser, Debug)]
+#[command(bin_name = "grepx serve")]
+pub struct ServeArgs {
+    /// Address to listen on for coordinators
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7878")]
+    pub listen: String,
+    
+    /// Number of threads searching file contents for each request (0 = auto)
+    #[arg(short, long, default_value_t = 0)]
+    pub threads: usize,
+    
+    /// Number of threads walking directories and reading file metadata for each request (0 = auto)
+    #[arg(long, default_value_t = 0)]
+    pub io_threads: usize,
+    
+    /// Shared secret coordinators must prove they know, read from FILE (default: $GREPX_TOKEN)
+    #[arg(long, value_name = "FILE")]
+    pub token_file: Option<PathBuf>,
+    
+    /// Encrypt connections with TLS using the certificate chain in FILE
+    #[arg(long, value_name = "FILE", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    
+    /// Private key for the TLS certificate
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    
+    /// Only allow searches under DIR; may be repeated (default: the current directory)
+    #[arg(long = "root", value_name = "DIR")]
+    pub roots: Vec<PathBuf>,
+}
+
+/// Run an HTTP server that answers searches of this machine's files
+#[derive(Parser, Debug)]
+#[command(bin_name = "grepx server")]
+pub struct ServerArgs {
+    /// Port to listen on
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+    
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1")]
+    pub host: String,
+    
+    /// Number of threads searching file contents for each search (0 = auto)
+    #[arg(short, long, default_value_t = 0)]
+    pub threads: usize,
+    
+    /// Number of threads walking directories and reading file metadata for each search (0 = auto)
+    #[arg(long, default_value_t = 0)]
+    pub io_threads: usize,
+    
+    /// Bearer token requests must carry, read from FILE (default: $GREPX_TOKEN)
+    #[arg(long, value_name = "FILE")]
+    pub token_file: Option<PathBuf>,
+    
+    /// Only allow searches under DIR; may be repeated (default: the current directory)
+    #[arg(long = "root", value_name = "DIR")]
+    pub roots: Vec<PathBuf>,
+    
+    /// Also accept requests for host NAME, besides localhost, IP addresses and --host; may be repeated
+    #[arg(long = "allow-host", value_name = "NAME")]
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Build and update trigram indexes of directories for faster repeated searches
+#[derive(Parser, Debug)]
+#[command(bin_name = "grepx index")]
+pub struct IndexArgs {
+    #[command(subcommand)]
+    pub command: IndexCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexCommand {
+    /// Index every file under a directory, replacing any existing index
+    Build(IndexBuildArgs),
+    /// Re-index the files added, changed or removed since an index was written
+    Update(IndexUpdateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct IndexBuildArgs {
+    /// Directory to index
+    pub path: PathBuf,
+    
+    /// Where to write the index (default: PATH/.grepx-index)
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    
+    /// Number of threads reading and indexing files (0 = auto)
+    #[arg(short, long, default_value_t = 0)]
+    pub threads: usize,
+    
+    /// Record content hashes, so updates skip files that were touched but not changed
+    #[arg(long)]
+    pub hash: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct IndexUpdateArgs {
+    /// Index file, or the directory holding it as .grepx-index
+    #[arg(default_value = ".")]
+    pub index: PathBuf,
+    
+    /// Number of threads reading and indexing files (0 = auto)
+    #[arg(short, long, default_value_t = 0)]
+    pub threads: usize,
+    
+    /// Also hash files whose size and modification time are unchanged, to
+    /// catch changes that kept both (reads every file; adds hashes to the index)
+    #[arg(long)]
+    pub verify: bool,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum OutputFormat {
     Text,
@@ -61,6 +385,25 @@ pub enum OutputFormat {
     Grep,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SortArg {
+    Path,
+    Modified,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReadArg {
+    /// Choose per file from its size and the measured read throughput
+    Auto,
+    /// Read whole files with a single read
+    Buffered,
+    /// Memory map files
+    Mmap,
+    /// Read files chunk by chunk in order
+    Sequential,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum LogLevel {
     Off,
@@ -71,8 +414,42 @@ pub enum LogLevel {
     Trace,
 }
 
-pub fn parse_args() -> Result<Args> {
-    let args = Args::parse();
+/// What grepx was asked to do
+pub enum Command {
+    /// Search for a pattern
+    Search(Box<Args>),
+    /// Run the benchmark suite
+    Bench(BenchArgs),
+    /// Serve distributed searches
+    Serve(ServeArgs),
+    /// Answer searches over HTTP
+    Server(ServerArgs),
+    /// Build or update a trigram index
+    Index(IndexArgs),
+}
+
+pub fn parse_args() -> Result<Command> {
+    // Subcommands are recognised before the search arguments so they are not
+    // taken as a pattern; `grepx -- bench` searches for the word itself
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    if argv.get(1).is_some_and(|arg| arg == "bench") {
+        argv.remove(0);
+        return Ok(Command::Bench(BenchArgs::parse_from(argv)));
+    }
+    if argv.get(1).is_some_and(|arg| arg == "serve") {
+        argv.remove(0);
+        return Ok(Command::Serve(ServeArgs::parse_from(argv)));
+    }
+    if argv.get(1).is_some_and(|arg| arg == "server") {
+        argv.remove(0);
+        return Ok(Command::Server(ServerArgs::parse_from(argv)));
+    }
+    if argv.get(1).is_some_and(|arg| arg == "index") {
+        argv.remove(0);
+        return Ok(Command::Index(IndexArgs::parse_from(argv)));
+    }
+    
+    let args = Args::parse_from(argv);
     
     // Set log level based on argument
     match args.log_level {
@@ -84,5 +461,5 @@ pub fn parse_args() -> Result<Args> {
         LogLevel::Trace => std::env::set_var("RUST_LOG", "trace"),
     }
     
-    Ok(args)
+    Ok(Command::Search(Box::new(args)))
 } 
\ No newline at end of file
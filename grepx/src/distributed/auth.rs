@@ -0,0 +1,150 @@
+use anyhow::{anyhow, bail, Result, Context};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::path::Path;
+
+/// Bytes of the random challenge each side sends
+const NONCE_LEN: usize = 32;
+
+/// Who is proving that it knows the shared secret. The role is part of each
+/// proof, so a proof cannot be sent back to the side that made it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Role {
+    Coordinator,
+    Worker,
+}
+
+impl Role {
+    fn label(self) -> &'static [u8] {
+        match self {
+            Role::Coordinator => b"grepx coordinator",
+            Role::Worker => b"grepx worker",
+        }
+    }
+}
+
+/// Read a shared secret from a token file, ignoring surrounding whitespace
+pub fn read_token_file(path: &Path) -> Result<Vec<u8>> {
+    let token = std::fs::read(path)
+        .with_context(|| format!("Failed to read token file {}", path.display()))?;
+    let token = token.trim_ascii();
+    if token.is_empty() {
+        bail!("Token file {} is empty", path.display());
+    }
+    Ok(token.to_vec())
+}
+
+/// A fresh random challenge
+pub(crate) fn nonce() -> Result<Vec<u8>> {
+    let mut nonce = vec![0; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce)
+        .map_err(|_| anyhow!("Failed to generate a random challenge"))?;
+    Ok(nonce)
+}
+
+/// Check that a challenge received from the other side is well formed
+pub(crate) fn check_nonce(nonce: &[u8]) -> Result<()> {
+    if nonce.len() != NONCE_LEN {
+        bail!("Invalid challenge of {} bytes", nonce.len());
+    }
+    Ok(())
+}
+
+/// Prove knowledge of the secret in answer to the other side's challenge.
+/// The prover's own challenge is signed too, so the proof is only valid for
+/// this connection.
+pub(crate) fn prove(secret: &[u8], role: Role, challenge: &[u8], nonce: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    hmac::sign(&key, &message(role, challenge, nonce)).as_ref().to_vec()
+}
+
+/// Check a proof made with [`prove`] from the same arguments, in constant time
+pub(crate) fn verify(secret: &[u8], role: Role, challenge: &[u8], nonce: &[u8], proof: &[u8]) -> bool {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    hmac::verify(&key, &message(role, challenge, nonce), proof).is_ok()
+}
+
+/// Check a bearer token against the secret, in constant time
+pub(crate) fn tokens_match(secret: &[u8], token: &[u8]) -> bool {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    hmac::verify(&key, token, hmac::sign(&key, secret).as_ref()).is_ok()
+}
+
+fn message(role: Role, challenge: &[u8], nonce: &[u8]) -> Vec<u8> {
+    [role.label(), challenge, nonce].concat()
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        bail!("Invalid hex string");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn proofs_verify_with_the_same_secret() {
+        let (challenge, nonce) = (nonce().unwrap(), nonce().unwrap());
+        let proof = prove(b"secret", Role::Worker, &challenge, &nonce);
+        assert!(verify(b"secret", Role::Worker, &challenge, &nonce, &proof));
+        assert!(!verify(b"other", Role::Worker, &challenge, &nonce, &proof));
+    }
+    
+    #[test]
+    fn proofs_are_bound_to_role_and_challenges() {
+        let (challenge, nonce) = (nonce().unwrap(), nonce().unwrap());
+        let proof = prove(b"secret", Role::Worker, &challenge, &nonce);
+        // A worker's proof cannot be sent back to it as the coordinator's
+        assert!(!verify(b"secret", Role::Coordinator, &challenge, &nonce, &proof));
+        assert!(!verify(b"secret", Role::Worker, &nonce, &challenge, &proof));
+        assert!(!verify(b"secret", Role::Worker, &super::nonce().unwrap(), &nonce, &proof));
+        assert!(!verify(b"secret", Role::Worker, &challenge, &nonce, &proof[1..]));
+    }
+    
+    #[test]
+    fn nonces_are_fresh_and_checked() {
+        let nonce = nonce().unwrap();
+        assert!(check_nonce(&nonce).is_ok());
+        assert_ne!(nonce, super::nonce().unwrap());
+        assert!(check_nonce(&nonce[1..]).is_err());
+        assert!(check_nonce(&[]).is_err());
+    }
+    
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(tokens_match(b"secret", b"secret"));
+        assert!(!tokens_match(b"secret", b"secre"));
+        assert!(!tokens_match(b"secret", b"secret "));
+        assert!(!tokens_match(b"secret", b""));
+    }
+    
+    #[test]
+    fn hex_round_trips() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(from_hex(&to_hex(&bytes)).unwrap(), bytes);
+        assert_eq!(to_hex(&[0x00, 0xab, 0x7f]), "00ab7f");
+        for invalid in ["abc", "zz", "é0"] {
+            assert!(from_hex(invalid).is_err(), "{} was accepted", invalid);
+        }
+    }
+    
+    #[test]
+    fn token_files_are_trimmed_and_must_not_be_empty() {
+        let path = std::env::temp_dir().join(format!("grepx-token-{}", std::process::id()));
+        std::fs::write(&path, "  secret\n").unwrap();
+        assert_eq!(read_token_file(&path).unwrap(), b"secret");
+        std::fs::write(&path, "\n").unwrap();
+        assert!(read_token_file(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
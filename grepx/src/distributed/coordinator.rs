@@ -0,0 +1,542 @@
+use anyhow::{anyhow, bail, Result, Context};
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use rustls::ClientConfig;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::engine::{FileStats, SearchControl, SearchResult};
+use crate::error::{ErrorKind, SearchError};
+use crate::io::file_discovery::SortBy;
+use crate::reorder::FileBuffer;
+use crate::searcher::SearchOptions;
+use crate::sink::Sink;
+use crate::utils::format_duration;
+
+use super::{NodeState, NodeStatus};
+use super::auth::{self, Role};
+use super::protocol::{self, Hello, Manifest, Message, Request, RequestKind};
+use super::shard::{ShardProgress, ShardQueue};
+use super::tls::{self, Stream};
+
+/// Run a search on every worker, streaming their results to the sink.
+///
+/// The coordinator first gathers the manifest of files each worker would
+/// search, then splits the files into shards by bytes and hands them out to
+/// the workers, which steal shards from each other when their files are on
+/// shared storage. Results of each shard arrive in file order, while files of
+/// different shards are interleaved. Paths are reported prefixed with the
+/// address of the worker that searched them.
+///
+/// The match limits of the whole search are enforced as files arrive: lines
+/// beyond them are dropped, and once they are reached the workers still
+/// searching are disconnected, which cancels their searches.
+///
+/// Workers send heartbeats while searching, and a worker that sends nothing
+/// for the worker timeout is treated as failed. The unfinished shards of a
+/// failed worker are handed to other workers holding the same files, or else
+/// reported as unsearched, and each worker is reported as complete, partial
+/// or failed in the result.
+///
+/// With a shared secret, the coordinator and each worker prove to each other
+/// that they know it before any request is sent. With a CA certificate,
+/// connections use TLS and workers must present a certificate it signed.
+pub(crate) fn execute_search<S: Sink + ?Sized>(workers: &[String], request: &Request, sink: &S) -> Result<SearchResult> {
+    info!("Searching on {} workers", workers.len());
+    let start = Instant::now();
+    let deadline = request.options.timeout.map(|timeout| start + timeout);
+    let security = Security {
+        secret: request.options.worker_secret.as_deref(),
+        tls: request.options.worker_tls_ca.as_deref().map(tls::client_config).transpose()?,
+    };
+    
+    // Gather the manifests of all workers
+    let manifest_request = Request {
+        kind: RequestKind::Manifest,
+        pattern: request.pattern.clone(),
+        case_mode: request.case_mode,
+        paths: request.paths.clone(),
+        options: request.options.clone(),
+    };
+    let connected: Vec<Result<(Connection, Manifest)>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = workers.iter()
+            .map(|worker| scope.spawn(|| {
+                let mut connection = Connection::open(worker, &security, request.options.worker_timeout)?;
+                connection.send(&manifest_request.to_json())?;
+                let manifest = connection.manifest()?;
+                Ok((connection, manifest))
+            }))
+            .collect();
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err(anyhow!("worker thread panicked"))))
+            .collect()
+    });
+    
+    let mut result = SearchResult::default();
+    let mut connections = Vec::new();
+    let mut manifests = Vec::new();
+    let mut nodes = Vec::new();
+    for (worker, outcome) in workers.iter().zip(connected) {
+        let mut node = NodeStatus {
+            address: worker.clone(),
+            state: NodeState::Complete,
+            shards: 0,
+            files_searched: 0,
+            bytes_processed: 0,
+            error: None,
+        };
+        match outcome {
+            Ok((connection, mut manifest)) => {
+                for error in std::mem::take(&mut manifest.errors) {
+                    let error = prefix_error(worker, error);
+                    sink.on_error(&error);
+                    result.errors.push(error);
+                }
+                result.skipped_files.extend(manifest.skipped_files.iter().map(|path| prefix(worker, path)));
+                connections.push(Some(connection));
+                manifests.push(Some(manifest));
+            }
+            Err(e) => {
+                // None of the worker's files are known, so none can be searched
+                let error = SearchError::new(ErrorKind::Io, None, format!("worker {}: {:#}", worker, e));
+                sink.on_error(&error);
+                result.errors.push(error);
+                node.state = NodeState::Failed;
+                node.error = Some(format!("{:#}", e));
+                connections.push(None);
+                manifests.push(None);
+            }
+        }
+        nodes.push(node);
+    }
+    
+    let queue = ShardQueue::new(&manifests);
+    drop(manifests);
+    result.discovery_time = start.elapsed();
+    info!("Split {} bytes into {} shards in {:?}", queue.bytes(), queue.sizes().len(), result.discovery_time);
+    
+    // Search the shards, each worker taking the next shard once it is done
+    let search = ShardSearch {
+        request,
+        deadline,
+        progress: ShardProgress::new(&queue, request.options.progress)?,
+        queue,
+        control: SearchControl::new(&request.options),
+        totals: Mutex::new(result),
+        sink,
+    };
+    std::thread::scope(|scope| {
+        for (index, (connection, node)) in connections.into_iter().zip(&mut nodes).enumerate() {
+            if let Some(connection) = connection {
+                let search = &search;
+                scope.spawn(move || search.run(index, connection, node));
+            }
+        }
+    });
+    search.progress.finish();
+    
+    let mut result = search.totals.into_inner().unwrap();
+    result.truncated |= search.control.truncated();
+    result.nodes = nodes;
+    result.elapsed = start.elapsed();
+    result.search_time = result.elapsed - result.discovery_time;
+    info!("Search completed. Found {} matches in {} files ({} had matches)",
+          result.total_matches, result.files_searched, result.files_with_matches);
+    
+    sink.on_finish(&result);
+    Ok(result)
+}
+
+/// State shared by the threads searching shards on each worker
+struct ShardSearch<'a, S: Sink + ?Sized> {
+    request: &'a Request,
+    deadline: Option<Instant>,
+    queue: ShardQueue,
+    progress: ShardProgress,
+    /// Match limits of the whole search, counted as files are delivered
+    control: SearchControl,
+    totals: Mutex<SearchResult>,
+    sink: &'a S,
+}
+
+impl<S: Sink + ?Sized> ShardSearch<'_, S> {
+    /// Search shards on one worker until none are left for it. If the worker
+    /// fails, the results it already sent are kept and its unfinished shards
+    /// are handed to other workers or recorded as unsearched.
+    fn run(&self, index: usize, mut connection: Connection, node: &mut NodeStatus) {
+        let (request, queue, progress, totals, sink) = (self.request, &self.queue, &self.progress, &self.totals, self.sink);
+        while let Some(shard) = queue.next(index) {
+            let Some(options) = shard_options(&request.options, self.deadline, &self.control, &mut totals.lock().unwrap()) else {
+                queue.stop();
+                break;
+            };
+            let shard_request = Request {
+                kind: RequestKind::Search,
+                pattern: request.pattern.clone(),
+                case_mode: request.case_mode,
+                paths: shard.files.clone(),
+                options,
+            };
+            
+            let started = Instant::now();
+            let mut run = ShardRun::default();
+            let outcome = connection.send(&shard_request.to_json())
+                .and_then(|()| connection.search(sink, &self.control, request.options.quit_after_match, &mut run, |stats| {
+                    progress.advance(&shard, stats.bytes_processed as u64)
+                }));
+            match outcome {
+                Ok(None) => {
+                    // Dropping the connection cancels the search on the worker,
+                    // and the results of the files it completed are kept
+                    debug!("Search limits reached, disconnecting from {}", node.address);
+                    node.files_searched += run.result.files_searched;
+                    node.bytes_processed += run.result.bytes_processed;
+                    totals.lock().unwrap().merge(run.result);
+                    queue.stop();
+                    break;
+                }
+                Ok(Some(shard_result)) => {
+                    queue.complete(index);
+                    progress.complete(&shard);
+                    debug!("Shard {} ({} files, {} bytes) searched by {} in {:?}, {:.0}% complete",
+                           shard.id, shard.files.len(), shard.bytes, node.address,
+                           started.elapsed(), progress.fraction() * 100.0);
+                    node.shards += 1;
+                    node.files_searched += shard_result.files_searched;
+                    node.bytes_processed += shard_result.bytes_processed;
+                    totals.lock().unwrap().merge(shard_result);
+                }
+                Err(e) => {
+                    warn!("Worker {} failed: {:#}", node.address, e);
+                    let remaining = shard.files.iter()
+                        .filter(|path| !run.completed.contains(*path))
+                        .cloned()
+                        .collect();
+                    let unsearched = queue.fail(index, remaining);
+                    node.state = NodeState::Partial;
+                    node.error = Some(format!("{:#}", e));
+                    node.files_searched += run.result.files_searched;
+                    node.bytes_processed += run.result.bytes_processed;
+                    
+                    // Results of the files completed before the failure were
+                    // already reported and are kept
+                    let mut totals = totals.lock().unwrap();
+                    totals.merge(run.result);
+                    if !unsearched.is_empty() {
+                        let error = SearchError::new(ErrorKind::Io, None, format!("worker {}: {:#}; {} files left unsearched",
+                                                                                  node.address, e, unsearched.len()));
+                        sink.on_error(&error);
+                        totals.errors.push(error);
+                        totals.unsearched_files.extend(unsearched.iter().map(|path| prefix(&node.address, path)));
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Options for searching the next shard, with the limits of the whole search
+/// reduced by what earlier shards used. Returns `None`, marking the result as
+/// truncated or timed out, once a limit has been reached, or when the search
+/// was cancelled.
+fn shard_options(options: &SearchOptions, deadline: Option<Instant>, control: &SearchControl, totals: &mut SearchResult) -> Option<SearchOptions> {
+    if options.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+        return None;
+    }
+    
+    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    if remaining.is_some_and(|remaining| remaining.is_zero()) {
+        totals.timed_out = true;
+        return None;
+    }
+    
+    if limit_reached(control) {
+        totals.truncated = true;
+        return None;
+    }
+    
+    // Shards list the files to search in order, and were discovered with the
+    // size limit already applied. The remaining limits only spare workers
+    // searching for lines that would be dropped, as shards searched at the
+    // same time share them.
+    let mut options = options.clone();
+    options.recursive = false;
+    options.sort_by = SortBy::Discovery;
+    options.max_filesize = None;
+    options.max_total = options.max_total.map(|max_total| max_total - totals.matched_lines);
+    options.max_files = options.max_files.map(|max_files| max_files - totals.files_with_matches);
+    options.timeout = remaining;
+    Some(options)
+}
+
+/// Files of a shard that a worker completed, and their totals, kept in case
+/// the worker fails before finishing the shard
+#[derive(Default)]
+struct ShardRun {
+    completed: HashSet<PathBuf>,
+    result: SearchResult,
+}
+
+/// How connections to workers are authenticated and encrypted
+struct Security<'a> {
+    secret: Option<&'a [u8]>,
+    tls: Option<Arc<ClientConfig>>,
+}
+
+/// A connection to a worker, which serves one request at a time
+struct Connection {
+    worker: String,
+    stream: BufReader<Box<dyn Stream>>,
+    timeout: Duration,
+}
+
+impl Connection {
+    /// Connect to a worker and complete the handshake. The worker is treated
+    /// as failed when nothing has been received from it for `timeout`.
+    fn open(worker: &str, security: &Security, timeout: Duration) -> Result<Self> {
+        let stream = connect(worker, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let stream: Box<dyn Stream> = match &security.tls {
+            Some(config) => tls::connect(config, worker, stream)?,
+            None => Box::new(stream),
+        };
+        
+        let mut connection = Self {
+            worker: worker.to_string(),
+            stream: BufReader::new(stream),
+            timeout,
+        };
+        connection.handshake(security.secret).context("Handshake failed")?;
+        Ok(connection)
+    }
+    
+    /// Check that the worker knows the shared secret, if any, and prove to
+    /// it that the coordinator does too
+    fn handshake(&mut self, secret: Option<&[u8]>) -> Result<()> {
+        let nonce = auth::nonce()?;
+        self.send(&Hello { version: protocol::VERSION, nonce: nonce.clone(), proof: None }.to_json())?;
+        // A worker using TLS answers with a TLS record rather than a hello
+        if let Ok([0x15 | 0x16, ..]) = self.stream.fill_buf() {
+            bail!("Worker requires TLS");
+        }
+        let hello = match self.receive()? {
+            Message::Hello(hello) => hello,
+            _ => bail!("Expected a hello message"),
+        };
+        if hello.version != protocol::VERSION {
+            bail!("Worker uses protocol version {} (expected {})", hello.version, protocol::VERSION);
+        }
+        auth::check_nonce(&hello.nonce)?;
+        
+        match (secret, &hello.proof) {
+            (Some(secret), Some(proof)) => {
+                if !auth::verify(secret, Role::Worker, &nonce, &hello.nonce, proof) {
+                    bail!("Worker has a different shared secret");
+                }
+            }
+            (Some(_), None) => bail!("Worker does not use a shared secret"),
+            (None, Some(_)) => bail!("Worker requires a shared secret"),
+            (None, None) => {}
+        }
+        let proof = secret.map(|secret| auth::prove(secret, Role::Coordinator, &hello.nonce, &nonce));
+        self.send(&protocol::auth_json(proof.as_deref()))?;
+        
+        match self.receive()? {
+            Message::Ready => Ok(()),
+            Message::Error(error) => bail!("{}", error),
+            _ => bail!("Expected the worker to be ready"),
+        }
+    }
+    
+    fn send(&mut self, request: &Value) -> Result<()> {
+        let stream = self.stream.get_mut();
+        stream.write_all(protocol::to_line(request).as_bytes())
+            .and_then(|()| stream.flush())
+            .context("Failed to send request")
+    }
+    
+    /// Receive the next message other than a heartbeat
+    fn receive(&mut self) -> Result<Message> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.stream.read_line(&mut line) {
+                Ok(0) => bail!("Connection closed before the request finished"),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    bail!("No heartbeat for {}", format_duration(self.timeout.as_secs_f64()));
+                }
+                Err(e) => return Err(e).context("Failed to read results"),
+            }
+            
+            let message = protocol::parse_message(&line)
+                .with_context(|| format!("Invalid message: {}", line.trim_end()))?;
+            if !matches!(message, Message::Heartbeat) {
+                return Ok(message);
+            }
+        }
+    }
+    
+    /// Receive the manifest sent in answer to a manifest request
+    fn manifest(&mut self) -> Result<Manifest> {
+        loop {
+            match self.receive()? {
+                Message::Manifest(manifest) => return Ok(manifest),
+                Message::Finish(result) => {
+                    let errors: Vec<String> = result.errors.iter().map(ToString::to_string).collect();
+                    bail!("{}", errors.join("; "));
+                }
+                _ => {}
+            }
+        }
+    }
+    
+    /// Receive the results of a search request, replaying them into the sink
+    /// within the limits of `control`, recording completed files in `run` and
+    /// reporting the statistics of each file to `on_file`. Returns `None`
+    /// without waiting for the rest once the limits have been reached.
+    fn search<S: Sink + ?Sized>(&mut self, sink: &S, control: &SearchControl, quit_after_match: bool,
+                                run: &mut ShardRun, mut on_file: impl FnMut(&FileStats)) -> Result<Option<SearchResult>> {
+        let worker = self.worker.clone();
+        
+        // The events of a file are buffered until the file is complete, so a
+        // worker that fails part way through a file leaves no partial output
+        let mut file: Option<(PathBuf, FileBuffer)> = None;
+        let mut file_errors = Vec::new();
+        loop {
+            if limit_reached(control) {
+                control.truncate();
+                return Ok(None);
+            }
+            match self.receive()? {
+                Message::Begin(path) => {
+                    let path = path.ok_or_else(|| anyhow!("File without a path"))?;
+                    let prefixed = prefix(&worker, &path);
+                    let buffer = FileBuffer::new(Some(&prefixed));
+                    buffer.on_file_begin(Some(&prefixed));
+                    file = Some((path, buffer));
+                }
+                Message::Match(line) => current(&file)?.on_match(None, &line),
+                Message::Context(line) => current(&file)?.on_context(None, &line),
+                Message::ContextBreak => current(&file)?.on_context_break(None),
+                Message::Error(error) => {
+                    let error = prefix_error(&worker, error);
+                    match &file {
+                        Some((_, buffer)) => {
+                            buffer.on_error(&error);
+                            file_errors.push(error);
+                        }
+                        None => {
+                            sink.on_error(&error);
+                            run.result.errors.push(error);
+                        }
+                    }
+                }
+                Message::End(stats) => {
+                    let (path, buffer) = file.take().ok_or_else(|| anyhow!("File ended before it began"))?;
+                    let reported = stats.matched_lines;
+                    let mut accepted = 0;
+                    let stats = buffer.replay_limited(sink, stats, || accept_line(control, quit_after_match, &mut accepted));
+                    if stats.matched_lines < reported {
+                        control.truncate();
+                    }
+                    on_file(&stats);
+                    
+                    // Files with errors could not be searched
+                    if file_errors.is_empty() {
+                        run.result.add(&stats);
+                        if stats.timed_out {
+                            run.result.timed_out_files.push(prefix(&worker, &path));
+                        }
+                    }
+                    run.result.errors.append(&mut file_errors);
+                    run.completed.insert(path);
+                }
+                Message::Finish(mut result) => {
+                    result.errors = result.errors.into_iter()
+                        .map(|error| prefix_error(&worker, error))
+                        .collect();
+                    for path in result.timed_out_files.iter_mut().chain(&mut result.skipped_files) {
+                        *path = prefix(&worker, path);
+                    }
+                    // Lines beyond the limits were dropped, so the counts are
+                    // those of the files as delivered
+                    result.total_matches = run.result.total_matches;
+                    result.matched_lines = run.result.matched_lines;
+                    result.files_with_matches = run.result.files_with_matches;
+                    return Ok(Some(result));
+                }
+                Message::Manifest(_) | Message::Heartbeat | Message::Hello(_) | Message::Ready => bail!("Unexpected message"),
+            }
+        }
+    }
+}
+
+/// Whether the search was cancelled or its match limits were reached, so
+/// that workers still searching can be abandoned
+fn limit_reached(control: &SearchControl) -> bool {
+    (control.stopped() && !control.timed_out()) || !control.accepting_files()
+}
+
+/// Check the limits of the whole search before delivering a matching line of
+/// a file that already had `accepted` lines delivered
+fn accept_line(control: &SearchControl, quit_after_match: bool, accepted: &mut usize) -> bool {
+    if *accepted == 0 && !control.accept_file() {
+        return false;
+    }
+    if !control.accept_line() {
+        return false;
+    }
+    if quit_after_match {
+        control.stop();
+    }
+    *accepted += 1;
+    true
+}
+
+fn current(file: &Option<(PathBuf, FileBuffer)>) -> Result<&FileBuffer> {
+    file.as_ref()
+        .map(|(_, buffer)| buffer)
+        .ok_or_else(|| anyhow!("Result received outside of a file"))
+}
+
+/// Connect to the first reachable address of a worker
+fn connect(worker: &str, timeout: Duration) -> Result<TcpStream> {
+    let mut last_error = None;
+    for addr in worker.to_socket_addrs().context("Failed to resolve address")? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => Err(e).context("Failed to connect"),
+        None => bail!("Address resolved to nothing"),
+    }
+}
+
+/// Path of a file on a worker, as reported by the coordinator
+fn prefix(worker: &str, path: &Path) -> PathBuf {
+    let mut prefixed = OsString::from(format!("{}:", worker));
+    prefixed.push(path);
+    PathBuf::from(prefixed)
+}
+
+fn prefix_error(worker: &str, mut error: SearchError) -> SearchError {
+    error.path = match error.path {
+        Some(path) => Some(prefix(worker, &path)),
+        None => {
+            error.message = format!("worker {}: {}", worker, error.message);
+            None
+        }
+    };
+    error
+}
@@ -0,0 +1,60 @@
+//! Distributed search over TCP.
+//!
+//! Workers started with `grepx serve` search their local file systems on
+//! behalf of a coordinator. The coordinator gathers the files each worker
+//! would search, splits them into shards by bytes, hands the shards out and
+//! merges the results the workers stream back. A searcher becomes a
+//! coordinator when it is given worker addresses with
+//! [`SearcherBuilder::workers`](crate::SearcherBuilder::workers).
+//!
+//! Connections can be authenticated with a shared secret and encrypted with
+//! TLS, and workers can be limited to a set of root directories.
+
+pub(crate) mod auth;
+pub(crate) mod coordinator;
+pub(crate) mod protocol;
+mod shard;
+mod tls;
+mod worker;
+
+pub use auth::read_token_file;
+pub use worker::{Worker, WorkerBuilder};
+
+
+/// How a worker's part of a distributed search ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeState {
+    /// Every shard the worker took was searched
+    Complete,
+    /// The worker failed part way; results it sent before failing are kept
+    /// and its unfinished shards were handed to other workers where possible
+    Partial,
+    /// The worker could not be reached or could not list its files, so none
+    /// of its files were searched
+    Failed,
+}
+
+impl NodeState {
+    /// Identifier used for the state in output
+    pub fn name(self) -> &'static str {
+        match self {
+            NodeState::Complete => "complete",
+            NodeState::Partial => "partial",
+            NodeState::Failed => "failed",
+        }
+    }
+}
+
+/// Outcome of one worker's part of a distributed search
+#[derive(Debug, Clone)]
+pub struct NodeStatus {
+    /// Address of the worker
+    pub address: String,
+    pub state: NodeState,
+    /// Shards searched completely
+    pub shards: usize,
+    pub files_searched: usize,
+    pub bytes_processed: usize,
+    /// Why the worker failed
+    pub error: Option<String>,
+}
@@ -0,0 +1,635 @@
+//! Messages exchanged between a coordinator and its workers.
+//!
+//! Each message is a JSON object on its own line, over plain TCP or TLS. A
+//! connection starts with a handshake: the coordinator sends a `hello` with a
+//! random challenge, the worker answers with a `hello` holding its own
+//! challenge, and the coordinator sends an `auth` message. When a shared
+//! secret is configured, each side proves it knows the secret by signing the
+//! other's challenge, and the worker answers `ready` or an `error`.
+//!
+//! The coordinator then sends a `manifest` request, which the worker answers with the files it would
+//! search and their sizes. It then sends `search` requests for shards of
+//! those files, one at a time on the same connection. The worker answers each
+//! with the events of the search followed by a `finish` message holding its
+//! totals. The events of a file are always sent together, from `begin` to
+//! `end`. While a request is running, the worker also sends a `heartbeat`
+//! message every [`HEARTBEAT_INTERVAL`] so the coordinator can tell a slow
+//! worker from a dead one.
+//!
+//! Paths are sent as strings, or as `{"bytes": "<hex>"}` when they are not
+//! valid UTF-8, so every path reaches the other side unchanged.
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::engine::{ContextKind, ContextLine, FileStats, LineMatch, SearchResult};
+use crate::error::{ErrorKind, SearchError};
+use crate::io::file_discovery::SortBy;
+use crate::io::strategy::ReadStrategy;
+use crate::regex::CaseMode;
+use crate::searcher::{SearchOptions, SearcherBuilder};
+
+use super::auth::{from_hex, to_hex};
+
+/// Version of the protocol, checked by workers before searching
+pub(crate) const VERSION: u64 = 2;
+
+/// How often a worker tells the coordinator it is alive while it works on a
+/// request
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a coordinator asks of a worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RequestKind {
+    /// List the files that would be searched, with their sizes
+    Manifest,
+    /// Search the given files
+    Search,
+}
+
+/// Opening message of each side of a connection
+#[derive(Debug)]
+pub(crate) struct Hello {
+    pub version: u64,
+    /// Random challenge for the other side to sign
+    pub nonce: Vec<u8>,
+    /// The worker's signature of the coordinator's challenge, when it has a
+    /// shared secret
+    pub proof: Option<Vec<u8>>,
+}
+
+impl Hello {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "type": "hello",
+            "version": self.version,
+            "nonce": to_hex(&self.nonce),
+            "proof": self.proof.as_deref().map(to_hex),
+        })
+    }
+    
+    pub fn parse(value: &Value) -> Result<Self> {
+        if value["type"] != "hello" {
+            bail!("Expected a hello message");
+        }
+        Ok(Self {
+            version: value["version"].as_u64().unwrap_or(0),
+            nonce: from_hex(str_field(value, "nonce")?)?,
+            proof: value["proof"].as_str().map(from_hex).transpose()?,
+        })
+    }
+}
+
+/// Encode the coordinator's signature of the worker's challenge
+pub(crate) fn auth_json(proof: Option<&[u8]>) -> Value {
+    json!({ "type": "auth", "proof": proof.map(to_hex) })
+}
+
+/// Decode the coordinator's signature of the worker's challenge
+pub(crate) fn parse_auth(value: &Value) -> Result<Option<Vec<u8>>> {
+    if value["type"] != "auth" {
+        bail!("Expected an auth message");
+    }
+    value["proof"].as_str().map(from_hex).transpose()
+}
+
+/// Encode the worker's acceptance of a coordinator
+pub(crate) fn ready_json() -> Value {
+    json!({ "type": "ready" })
+}
+
+/// A request sent by the coordinator
+pub(crate) struct Request {
+    pub kind: RequestKind,
+    pub pattern: String,
+    pub case_mode: CaseMode,
+    pub paths: Vec<PathBuf>,
+    pub options: SearchOptions,
+}
+
+impl Request {
+    pub fn to_json(&self) -> Value {
+        let o = &self.options;
+        json!({
+            "type": match self.kind {
+                RequestKind::Manifest => "manifest",
+                RequestKind::Search => "search",
+            },
+            "version": VERSION,
+            "pattern": self.pattern,
+            "case_mode": case_mode_name(self.case_mode),
+            "paths": self.paths.iter().map(|path| path_json(path)).collect::<Vec<_>>(),
+            "options": {
+                "recursive": o.recursive,
+                "chunk_size": o.chunk_size,
+                "read_strategy": o.read_strategy.map(read_strategy_name),
+                "prefetch": o.prefetch,
+                "before_context": o.before_context,
+                "after_context": o.after_context,
+                "sort_by": sort_by_name(o.sort_by),
+                "ordered": o.ordered,
+                "quit_after_match": o.quit_after_match,
+                "max_count": o.max_count,
+                "max_total": o.max_total,
+                "max_files": o.max_files,
+                "timeout": o.timeout.map(|timeout| timeout.as_secs_f64()),
+                "file_timeout": o.file_timeout.map(|timeout| timeout.as_secs_f64()),
+                "max_filesize": o.max_filesize,
+            },
+        })
+    }
+    
+    /// Parse a request into its kind, a searcher builder configured with its
+    /// options, and the paths to search
+    pub fn parse(value: &Value) -> Result<(RequestKind, SearcherBuilder, Vec<PathBuf>)> {
+        let kind = match value["type"].as_str() {
+            Some("manifest") => RequestKind::Manifest,
+            Some("search") => RequestKind::Search,
+            _ => bail!("Expected a manifest or search request"),
+        };
+        let version = value["version"].as_u64().unwrap_or(0);
+        if version != VERSION {
+            bail!("Unsupported protocol version {} (expected {})", version, VERSION);
+        }
+        
+        let (builder, paths) = parse_search(value)?;
+        Ok((kind, builder, paths))
+    }
+}
+
+/// Parse the pattern, paths and options of a search into a searcher builder
+/// and the paths to search. The HTTP server accepts the same fields, so
+/// everything but the pattern and paths is optional.
+pub(crate) fn parse_search(value: &Value) -> Result<(SearcherBuilder, Vec<PathBuf>)> {
+    let pattern = str_field(value, "pattern")?;
+    let case_mode = match value["case_mode"].as_str().unwrap_or("sensitive") {
+        "sensitive" => CaseMode::Sensitive,
+        "insensitive" => CaseMode::Insensitive,
+        "smart" => CaseMode::Smart,
+        other => bail!("Unknown case mode: {}", other),
+    };
+    let paths = value["paths"].as_array()
+        .ok_or_else(|| anyhow!("Missing field: paths"))?
+        .iter()
+        .map(parse_path)
+        .collect::<Result<_>>()?;
+    
+    let o = &value["options"];
+    let read_strategy = match o["read_strategy"].as_str() {
+        None => None,
+        Some("buffered") => Some(ReadStrategy::Buffered),
+        Some("mmap") => Some(ReadStrategy::Mmap),
+        Some("sequential") => Some(ReadStrategy::Sequential),
+        Some(other) => bail!("Unknown read strategy: {}", other),
+    };
+    let sort_by = match o["sort_by"].as_str().unwrap_or("discovery") {
+        "discovery" => SortBy::Discovery,
+        "path" => SortBy::Path,
+        "modified" => SortBy::Modified,
+        "size" => SortBy::Size,
+        other => bail!("Unknown sort order: {}", other),
+    };
+    
+    let builder = SearcherBuilder::new(pattern)
+        .case_mode(case_mode)
+        .recursive(o["recursive"].as_bool().unwrap_or(false))
+        .chunk_size(usize_field(o, "chunk_size"))
+        .read_strategy(read_strategy)
+        .prefetch(o["prefetch"].as_bool().unwrap_or(true))
+        .before_context(usize_field(o, "before_context").unwrap_or(0))
+        .after_context(usize_field(o, "after_context").unwrap_or(0))
+        .sort(sort_by)
+        .ordered(o["ordered"].as_bool().unwrap_or(true))
+        .quit_after_match(o["quit_after_match"].as_bool().unwrap_or(false))
+        .max_count(usize_field(o, "max_count"))
+        .max_total(usize_field(o, "max_total"))
+        .max_files(usize_field(o, "max_files"))
+        .timeout(duration_field(o, "timeout")?)
+        .file_timeout(duration_field(o, "file_timeout")?)
+        .max_filesize(o["max_filesize"].as_u64());
+    
+    Ok((builder, paths))
+}
+
+/// A file a worker would search
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ManifestFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Last modification time in nanoseconds since the Unix epoch, or 0 if unknown
+    pub modified: u64,
+}
+
+/// Files a worker would search for a request, in search order
+#[derive(Debug, Default)]
+pub(crate) struct Manifest {
+    pub files: Vec<ManifestFile>,
+    /// Files left out because they exceed the size limit
+    pub skipped_files: Vec<PathBuf>,
+    /// Paths that could not be accessed
+    pub errors: Vec<SearchError>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "type": "manifest",
+            "files": self.files.iter()
+                .map(|file| json!([path_json(&file.path), file.size, file.modified]))
+                .collect::<Vec<_>>(),
+            "skipped_files": self.skipped_files.iter().map(|path| path_json(path)).collect::<Vec<_>>(),
+            "errors": self.errors.iter().map(error_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// A message sent by a worker. Events between `Begin` and `End` belong to
+/// the file named by `Begin`.
+#[derive(Debug)]
+pub(crate) enum Message {
+    Begin(Option<PathBuf>),
+    Match(LineMatch),
+    Context(ContextLine),
+    ContextBreak,
+    Error(SearchError),
+    End(FileStats),
+    Finish(SearchResult),
+    Manifest(Manifest),
+    Heartbeat,
+    Hello(Hello),
+    Ready,
+}
+
+/// Encode a heartbeat
+pub(crate) fn heartbeat_json() -> Value {
+    json!({ "type": "heartbeat" })
+}
+
+/// Encode a file begin event
+pub(crate) fn begin_json(path: Option<&Path>) -> Value {
+    json!({ "type": "begin", "path": path.map(path_json) })
+}
+
+/// Encode a matching line
+pub(crate) fn match_json(path: Option<&Path>, line: &LineMatch) -> Value {
+    json!({
+        "type": "match",
+        "path": path.map(path_json),
+        "line_number": line.line_number,
+        "byte_offset": line.byte_offset,
+        "line": line.line,
+        "matches": line.matches,
+    })
+}
+
+/// Encode a context line
+pub(crate) fn context_json(path: Option<&Path>, line: &ContextLine) -> Value {
+    json!({
+        "type": "context",
+        "path": path.map(path_json),
+        "line_number": line.line_number,
+        "byte_offset": line.byte_offset,
+        "line": line.line,
+        "kind": match line.kind {
+            ContextKind::Before => "before",
+            ContextKind::After => "after",
+        },
+    })
+}
+
+/// Encode a break between groups of context lines
+pub(crate) fn context_break_json(path: Option<&Path>) -> Value {
+    json!({ "type": "context_break", "path": path.map(path_json) })
+}
+
+/// Encode an error
+pub(crate) fn error_json(error: &SearchError) -> Value {
+    json!({
+        "type": "error",
+        "kind": error.kind.name(),
+        "path": error.path.as_deref().map(path_json),
+        "message": error.message,
+    })
+}
+
+/// Encode a file end event
+pub(crate) fn end_json(path: Option<&Path>, stats: &FileStats) -> Value {
+    json!({
+        "type": "end",
+        "path": path.map(path_json),
+        "matches": stats.matches,
+        "matched_lines": stats.matched_lines,
+        "bytes_processed": stats.bytes_processed,
+        "bytes_read": stats.bytes_read,
+        "bytes_mapped": stats.bytes_mapped,
+        "timed_out": stats.timed_out,
+    })
+}
+
+/// Encode the totals of a completed search
+pub(crate) fn finish_json(result: &SearchResult) -> Value {
+    json!({
+        "type": "finish",
+        "total_matches": result.total_matches,
+        "files_searched": result.files_searched,
+        "files_with_matches": result.files_with_matches,
+        "matched_lines": result.matched_lines,
+        "bytes_processed": result.bytes_processed,
+        "bytes_read": result.bytes_read,
+        "bytes_mapped": result.bytes_mapped,
+        "discovery_time": result.discovery_time.as_secs_f64(),
+        "search_time": result.search_time.as_secs_f64(),
+        "errors": result.errors.iter().map(error_json).collect::<Vec<_>>(),
+        "truncated": result.truncated,
+        "timed_out": result.timed_out,
+        "timed_out_files": result.timed_out_files.iter().map(|path| path_json(path)).collect::<Vec<_>>(),
+        "skipped_files": result.skipped_files.iter().map(|path| path_json(path)).collect::<Vec<_>>(),
+    })
+}
+
+/// Decode a message sent by a worker
+pub(crate) fn parse_message(line: &str) -> Result<Message> {
+    let value: Value = serde_json::from_str(line)?;
+    
+    let message = match str_field(&value, "type")? {
+        "begin" => Message::Begin(optional_path(&value["path"])?),
+        "match" => Message::Match(LineMatch {
+            line_number: usize_field(&value, "line_number").unwrap_or(0),
+            byte_offset: usize_field(&value, "byte_offset").unwrap_or(0),
+            line: str_field(&value, "line")?.to_string(),
+            matches: value["matches"].as_array()
+                .map(|matches| {
+                    matches.iter()
+                        .filter_map(|m| Some((m[0].as_u64()? as usize, m[1].as_u64()? as usize)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }),
+        "context" => Message::Context(ContextLine {
+            line_number: usize_field(&value, "line_number").unwrap_or(0),
+            byte_offset: usize_field(&value, "byte_offset").unwrap_or(0),
+            line: str_field(&value, "line")?.to_string(),
+            kind: if value["kind"] == "before" { ContextKind::Before } else { ContextKind::After },
+        }),
+        "context_break" => Message::ContextBreak,
+        "error" => Message::Error(parse_error(&value)?),
+        "end" => Message::End(FileStats {
+            matches: usize_field(&value, "matches").unwrap_or(0),
+            matched_lines: usize_field(&value, "matched_lines").unwrap_or(0),
+            bytes_processed: usize_field(&value, "bytes_processed").unwrap_or(0),
+            bytes_read: usize_field(&value, "bytes_read").unwrap_or(0),
+            bytes_mapped: usize_field(&value, "bytes_mapped").unwrap_or(0),
+            timed_out: value["timed_out"].as_bool().unwrap_or(false),
+        }),
+        "finish" => Message::Finish(SearchResult {
+            total_matches: usize_field(&value, "total_matches").unwrap_or(0),
+            files_searched: usize_field(&value, "files_searched").unwrap_or(0),
+            files_with_matches: usize_field(&value, "files_with_matches").unwrap_or(0),
+            matched_lines: usize_field(&value, "matched_lines").unwrap_or(0),
+            bytes_processed: usize_field(&value, "bytes_processed").unwrap_or(0),
+            bytes_read: usize_field(&value, "bytes_read").unwrap_or(0),
+            bytes_mapped: usize_field(&value, "bytes_mapped").unwrap_or(0),
+            discovery_time: duration_field(&value, "discovery_time")?.unwrap_or_default(),
+            search_time: duration_field(&value, "search_time")?.unwrap_or_default(),
+            errors: parse_errors(&value)?,
+            truncated: value["truncated"].as_bool().unwrap_or(false),
+            timed_out: value["timed_out"].as_bool().unwrap_or(false),
+            timed_out_files: paths_field(&value, "timed_out_files")?,
+            skipped_files: paths_field(&value, "skipped_files")?,
+            ..SearchResult::default()
+        }),
+        "manifest" => Message::Manifest(Manifest {
+            files: value["files"].as_array()
+                .ok_or_else(|| anyhow!("Missing field: files"))?
+                .iter()
+                .map(|file| Some(ManifestFile {
+                    path: parse_path(&file[0]).ok()?,
+                    size: file[1].as_u64()?,
+                    modified: file[2].as_u64()?,
+                }))
+                .collect::<Option<_>>()
+                .ok_or_else(|| anyhow!("Invalid manifest entry"))?,
+            skipped_files: paths_field(&value, "skipped_files")?,
+            errors: parse_errors(&value)?,
+        }),
+        "heartbeat" => Message::Heartbeat,
+        "hello" => Message::Hello(Hello::parse(&value)?),
+        "ready" => Message::Ready,
+        other => bail!("Unknown message type: {}", other),
+    };
+    Ok(message)
+}
+
+fn parse_errors(value: &Value) -> Result<Vec<SearchError>> {
+    value["errors"].as_array()
+        .map(|errors| errors.iter().map(parse_error).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+fn parse_error(value: &Value) -> Result<SearchError> {
+    let kind = ErrorKind::from_name(str_field(value, "kind")?)
+        .ok_or_else(|| anyhow!("Unknown error kind: {}", value["kind"]))?;
+    let path = optional_path(&value["path"])?;
+    Ok(SearchError::new(kind, path.as_deref(), str_field(value, "message")?))
+}
+
+fn case_mode_name(case_mode: CaseMode) -> &'static str {
+    match case_mode {
+        CaseMode::Sensitive => "sensitive",
+        CaseMode::Insensitive => "insensitive",
+        CaseMode::Smart => "smart",
+    }
+}
+
+fn read_strategy_name(strategy: ReadStrategy) -> &'static str {
+    match strategy {
+        ReadStrategy::Buffered => "buffered",
+        ReadStrategy::Mmap => "mmap",
+        ReadStrategy::Sequential => "sequential",
+    }
+}
+
+fn sort_by_name(sort_by: SortBy) -> &'static str {
+    match sort_by {
+        SortBy::Discovery => "discovery",
+        SortBy::Path => "path",
+        SortBy::Modified => "modified",
+        SortBy::Size => "size",
+    }
+}
+
+/// A path as a string, or as its bytes in hex when it is not valid UTF-8
+fn path_json(path: &Path) -> Value {
+    match path.to_str() {
+        Some(path) => Value::String(path.to_string()),
+        None => json!({ "bytes": to_hex(path.as_os_str().as_encoded_bytes()) }),
+    }
+}
+
+/// Decode a path sent by [`path_json`]
+fn parse_path(value: &Value) -> Result<PathBuf> {
+    if let Some(path) = value.as_str() {
+        return Ok(PathBuf::from(path));
+    }
+    let hex = value["bytes"].as_str().ok_or_else(|| anyhow!("Invalid path: {}", value))?;
+    path_from_bytes(from_hex(hex)?)
+}
+
+fn optional_path(value: &Value) -> Result<Option<PathBuf>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    parse_path(value).map(Some)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf> {
+    String::from_utf8(bytes)
+        .map(PathBuf::from)
+        .map_err(|_| anyhow!("Path is not valid on this platform"))
+}
+
+fn str_field<'a>(value: &'a Value, field: &str) -> Result<&'a str> {
+    value[field].as_str().ok_or_else(|| anyhow!("Missing field: {}", field))
+}
+
+fn usize_field(value: &Value, field: &str) -> Option<usize> {
+    value[field].as_u64().map(|n| n as usize)
+}
+
+/// Duration in seconds, rejecting negative and out of range values
+fn duration_field(value: &Value, field: &str) -> Result<Option<Duration>> {
+    value[field].as_f64()
+        .map(|secs| Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("Invalid {}: {}", field, secs)))
+        .transpose()
+}
+
+fn paths_field(value: &Value, field: &str) -> Result<Vec<PathBuf>> {
+    value[field].as_array()
+        .map(|paths| paths.iter().map(parse_path).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+/// Serialize a message as a single line
+pub(crate) fn to_line(value: &Value) -> String {
+    let mut line = value.to_string();
+    line.push('\n');
+    line
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[cfg(unix)]
+    fn non_utf8_path() -> PathBuf {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(b"dir/caf\xe9.txt"))
+    }
+    
+    fn round_trip(value: Value) -> Message {
+        parse_message(&to_line(&value)).unwrap()
+    }
+    
+    #[test]
+    fn file_events_round_trip() {
+        let path = Path::new("src/main.rs");
+        let Message::Begin(Some(begin)) = round_trip(begin_json(Some(path))) else { panic!("expected begin") };
+        assert_eq!(begin, path);
+        
+        let line = LineMatch {
+            line_number: 3,
+            byte_offset: 42,
+            line: "fn main() {".to_string(),
+            matches: vec![(3, 7)],
+        };
+        let Message::Match(parsed) = round_trip(match_json(Some(path), &line)) else { panic!("expected match") };
+        assert_eq!((parsed.line_number, parsed.byte_offset), (3, 42));
+        assert_eq!(parsed.line, line.line);
+        assert_eq!(parsed.matches, line.matches);
+        
+        let stats = FileStats { matches: 2, matched_lines: 1, bytes_processed: 100, ..FileStats::default() };
+        let Message::End(parsed) = round_trip(end_json(Some(path), &stats)) else { panic!("expected end") };
+        assert_eq!((parsed.matches, parsed.matched_lines, parsed.bytes_processed), (2, 1, 100));
+        
+        let error = SearchError::new(ErrorKind::PermissionDenied, Some(path), "denied");
+        let Message::Error(parsed) = round_trip(error_json(&error)) else { panic!("expected error") };
+        assert_eq!(parsed.kind, ErrorKind::PermissionDenied);
+        assert_eq!(parsed.path.as_deref(), Some(path));
+        assert_eq!(parsed.message, "denied");
+    }
+    
+    #[test]
+    fn finish_round_trips() {
+        let result = SearchResult {
+            total_matches: 5,
+            files_searched: 2,
+            search_time: Duration::from_millis(1500),
+            timed_out_files: vec![PathBuf::from("slow.log")],
+            ..SearchResult::default()
+        };
+        let Message::Finish(parsed) = round_trip(finish_json(&result)) else { panic!("expected finish") };
+        assert_eq!((parsed.total_matches, parsed.files_searched), (5, 2));
+        assert_eq!(parsed.search_time, Duration::from_millis(1500));
+        assert_eq!(parsed.timed_out_files, result.timed_out_files);
+    }
+    
+    #[test]
+    fn invalid_durations_are_rejected() {
+        for secs in ["-1.0", "1e300"] {
+            let line = format!(r#"{{"type":"finish","search_time":{}}}"#, secs);
+            assert!(parse_message(&line).is_err(), "{} was accepted", secs);
+        }
+    }
+    
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_paths_round_trip() {
+        let path = non_utf8_path();
+        let Message::Begin(Some(begin)) = round_trip(begin_json(Some(&path))) else { panic!("expected begin") };
+        assert_eq!(begin, path);
+        
+        let manifest = Manifest {
+            files: vec![ManifestFile { path: path.clone(), size: 10, modified: 20 }],
+            skipped_files: vec![path.clone()],
+            errors: Vec::new(),
+        };
+        let Message::Manifest(parsed) = round_trip(manifest.to_json()) else { panic!("expected manifest") };
+        assert_eq!(parsed.files, manifest.files);
+        assert_eq!(parsed.skipped_files, manifest.skipped_files);
+        
+        let request = json!({
+            "type": "search",
+            "version": VERSION,
+            "pattern": "needle",
+            "paths": [path_json(&path), path_json(Path::new("plain"))],
+            "options": {},
+        });
+        let (kind, _, paths) = Request::parse(&request).unwrap();
+        assert_eq!(kind, RequestKind::Search);
+        assert_eq!(paths, vec![path, PathBuf::from("plain")]);
+    }
+    
+    #[test]
+    fn malformed_paths_are_rejected() {
+        assert!(parse_message(r#"{"type":"begin","path":42}"#).is_err());
+        assert!(parse_message(r#"{"type":"begin","path":{"bytes":"zz"}}"#).is_err());
+        let request = json!({ "type": "search", "version": VERSION, "pattern": "x", "paths": [1], "options": {} });
+        assert!(Request::parse(&request).is_err());
+    }
+    
+    #[test]
+    fn other_versions_are_rejected() {
+        let request = json!({ "type": "search", "version": VERSION - 1, "pattern": "x", "paths": [], "options": {} });
+        assert!(Request::parse(&request).is_err());
+    }
+}
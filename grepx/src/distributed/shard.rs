@@ -0,0 +1,336 @@
+use anyhow::{Result, Context};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::debug;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::protocol::{Manifest, ManifestFile};
+
+/// Shards planned for each worker, so that workers that finish early can
+/// steal from the others
+const SHARDS_PER_WORKER: u64 = 8;
+
+/// Smallest and largest shards, by bytes
+const MIN_SHARD_BYTES: u64 = 4 * 1024 * 1024; // 4MB
+const MAX_SHARD_BYTES: u64 = 256 * 1024 * 1024; // 256MB
+
+/// Most files in a shard, so that shards of small files stay responsive
+const MAX_SHARD_FILES: usize = 10_000;
+
+/// A group of files searched together by one worker
+pub(crate) struct Shard {
+    pub id: usize,
+    pub files: Vec<PathBuf>,
+    pub bytes: u64,
+    /// Workers that hold the files, by index. Files on shared storage are
+    /// held by several workers, any of which may search them.
+    pub holders: Vec<usize>,
+}
+
+/// Splits the files of a distributed search into shards of similar size and
+/// hands them out to workers.
+///
+/// A file is on shared storage when several workers list it with the same
+/// path, size and modification time. Each shard holds files with the same
+/// holders and is first assigned to the holder with the fewest bytes planned.
+/// A worker that runs out of shards steals the last pending shard it holds
+/// from the worker with the most bytes left. When a worker fails, its
+/// unfinished shards go to the other holders, or are left unsearched if it
+/// was their only holder.
+pub(crate) struct ShardQueue {
+    state: Mutex<QueueState>,
+    /// Signalled when shards are completed or handed back after a failure
+    changed: Condvar,
+    /// Bytes of each shard, by id
+    sizes: Vec<u64>,
+}
+
+struct QueueState {
+    shards: Vec<Arc<Shard>>,
+    /// Pending shards of each worker, in search order
+    pending: Vec<VecDeque<usize>>,
+    /// Shard each worker is searching
+    running: Vec<Option<usize>>,
+    failed: Vec<bool>,
+    /// Whether the search was stopped by a limit
+    stopped: bool,
+}
+
+impl QueueState {
+    fn holds(&self, worker: usize, id: usize) -> bool {
+        self.shards[id].holders.contains(&worker)
+    }
+    
+    fn pending_bytes(&self, worker: usize) -> u64 {
+        self.pending[worker].iter().map(|&id| self.shards[id].bytes).sum()
+    }
+    
+    /// Take a worker's next pending shard, or steal one it holds from the
+    /// worker with the most bytes left
+    fn take(&mut self, worker: usize) -> Option<usize> {
+        if let Some(id) = self.pending[worker].pop_front() {
+            return Some(id);
+        }
+        
+        let victim = (0..self.pending.len())
+            .filter(|&victim| victim != worker)
+            .filter(|&victim| self.pending[victim].iter().any(|&id| self.holds(worker, id)))
+            .max_by_key(|&victim| self.pending_bytes(victim))?;
+        let position = self.pending[victim].iter()
+            .rposition(|&id| self.holds(worker, id))?;
+        let id = self.pending[victim].remove(position)?;
+        debug!("Worker {} stole shard {} from worker {}", worker, id, victim);
+        Some(id)
+    }
+}
+
+impl ShardQueue {
+    /// Plan shards from the manifest of each worker, or `None` for workers
+    /// that could not provide one
+    pub fn new(manifests: &[Option<Manifest>]) -> Self {
+        // Find the holders of every file, keeping the order of the manifests
+        let mut index: HashMap<&ManifestFile, usize> = HashMap::new();
+        let mut files: Vec<(&ManifestFile, Vec<usize>)> = Vec::new();
+        for (worker, manifest) in manifests.iter().enumerate() {
+            for file in manifest.iter().flat_map(|manifest| &manifest.files) {
+                match index.get(file) {
+                    Some(&position) => files[position].1.push(worker),
+                    None => {
+                        index.insert(file, files.len());
+                        files.push((file, vec![worker]));
+                    }
+                }
+            }
+        }
+        
+        // Group files by their holders, then cut each group into shards
+        let mut groups: Vec<(Vec<usize>, Vec<&ManifestFile>)> = Vec::new();
+        let mut group_index: HashMap<Vec<usize>, usize> = HashMap::new();
+        for (file, holders) in files {
+            let position = *group_index.entry(holders.clone()).or_insert_with(|| {
+                groups.push((holders, Vec::new()));
+                groups.len() - 1
+            });
+            groups[position].1.push(file);
+        }
+        
+        let workers = manifests.iter().filter(|manifest| manifest.is_some()).count() as u64;
+        let total: u64 = groups.iter().flat_map(|(_, files)| files).map(|file| file.size).sum();
+        let target = (total / (workers * SHARDS_PER_WORKER).max(1)).clamp(MIN_SHARD_BYTES, MAX_SHARD_BYTES);
+        
+        let mut shards = Vec::new();
+        for (holders, files) in groups {
+            let mut shard = Shard {
+                id: shards.len(),
+                files: Vec::new(),
+                bytes: 0,
+                holders: holders.clone(),
+            };
+            for file in files {
+                shard.files.push(file.path.clone());
+                shard.bytes += file.size;
+                if shard.bytes >= target || shard.files.len() >= MAX_SHARD_FILES {
+                    let id = shard.id + 1;
+                    shards.push(std::mem::replace(&mut shard, Shard {
+                        id,
+                        files: Vec::new(),
+                        bytes: 0,
+                        holders: holders.clone(),
+                    }));
+                }
+            }
+            if !shard.files.is_empty() {
+                shards.push(shard);
+            }
+        }
+        
+        // Assign each shard to the holder with the fewest bytes so far
+        let mut pending = vec![VecDeque::new(); manifests.len()];
+        let mut planned = vec![0u64; manifests.len()];
+        for shard in &shards {
+            let worker = *shard.holders.iter()
+                .min_by_key(|&&worker| planned[worker])
+                .expect("shards have at least one holder");
+            pending[worker].push_back(shard.id);
+            planned[worker] += shard.bytes;
+        }
+        debug!("Planned {} shards of about {} bytes, {:?} bytes per worker", shards.len(), target, planned);
+        
+        Self {
+            sizes: shards.iter().map(|shard| shard.bytes).collect(),
+            state: Mutex::new(QueueState {
+                shards: shards.into_iter().map(Arc::new).collect(),
+                pending,
+                running: vec![None; manifests.len()],
+                failed: vec![false; manifests.len()],
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+    
+    /// Bytes of each shard, by id
+    pub fn sizes(&self) -> &[u64] {
+        &self.sizes
+    }
+    
+    /// Total bytes of all shards
+    pub fn bytes(&self) -> u64 {
+        self.sizes.iter().sum()
+    }
+    
+    /// Take the next shard for a worker to search, stealing one from another
+    /// worker if it has none of its own left. While shards the worker holds
+    /// are being searched elsewhere, it waits in case they are handed back.
+    /// Returns `None` once the worker has nothing left to do.
+    pub fn next(&self, worker: usize) -> Option<Arc<Shard>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.stopped {
+                return None;
+            }
+            if let Some(id) = state.take(worker) {
+                state.running[worker] = Some(id);
+                return Some(state.shards[id].clone());
+            }
+            
+            let waiting = state.running.iter()
+                .enumerate()
+                .any(|(other, id)| other != worker && id.is_some_and(|id| state.holds(worker, id)));
+            if !waiting {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+    
+    /// Record that a worker has searched its current shard
+    pub fn complete(&self, worker: usize) {
+        self.state.lock().unwrap().running[worker] = None;
+        self.changed.notify_all();
+    }
+    
+    /// Stop handing out shards, because a limit of the search was reached
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+        state.pending.iter_mut().for_each(VecDeque::clear);
+        self.changed.notify_all();
+    }
+    
+    /// Record that a worker failed while searching its current shard, of
+    /// which `remaining` files were not searched. Its unfinished shards are
+    /// handed to other holders, and the files that no other worker holds are
+    /// returned as unsearched.
+    pub fn fail(&self, worker: usize, remaining: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        state.failed[worker] = true;
+        
+        let mut orphaned: Vec<usize> = state.pending[worker].drain(..).collect();
+        if let Some(id) = state.running[worker].take() {
+            let shard = &state.shards[id];
+            state.shards[id] = Arc::new(Shard {
+                id,
+                files: remaining,
+                bytes: shard.bytes,
+                holders: shard.holders.clone(),
+            });
+            orphaned.insert(0, id);
+        }
+        
+        let mut unsearched = Vec::new();
+        for id in orphaned {
+            let holder = state.shards[id].holders.iter()
+                .copied()
+                .filter(|&holder| !state.failed[holder])
+                .min_by_key(|&holder| state.pending_bytes(holder));
+            match holder {
+                Some(holder) => {
+                    debug!("Handing shard {} of failed worker {} to worker {}", id, worker, holder);
+                    state.pending[holder].push_back(id);
+                }
+                None if state.stopped => {}
+                None => unsearched.extend(state.shards[id].files.iter().cloned()),
+            }
+        }
+        
+        self.changed.notify_all();
+        unsearched
+    }
+}
+
+/// Tracks how much of each shard has been searched, and shows the overall
+/// completion of the search when a progress bar is enabled
+pub(crate) struct ShardProgress {
+    /// Bytes of each shard
+    sizes: Vec<u64>,
+    /// Bytes searched in each shard
+    searched: Vec<AtomicU64>,
+    completed: AtomicUsize,
+    bar: Option<ProgressBar>,
+}
+
+impl ShardProgress {
+    pub fn new(queue: &ShardQueue, show: bool) -> Result<Self> {
+        let bar = if show {
+            let bar = ProgressBar::new(queue.bytes());
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}")
+                    .context("Failed to set progress bar style")?
+            );
+            bar.set_message(format!("0/{} shards", queue.sizes().len()));
+            Some(bar)
+        } else {
+            None
+        };
+        
+        Ok(Self {
+            sizes: queue.sizes().to_vec(),
+            searched: queue.sizes().iter().map(|_| AtomicU64::new(0)).collect(),
+            completed: AtomicUsize::new(0),
+            bar,
+        })
+    }
+    
+    /// Record bytes searched in a shard
+    pub fn advance(&self, shard: &Shard, bytes: u64) {
+        // Files may have grown since they were listed
+        let searched = &self.searched[shard.id];
+        let before = searched.fetch_add(bytes, Ordering::Relaxed).min(shard.bytes);
+        let added = (before + bytes).min(shard.bytes) - before;
+        if let Some(bar) = &self.bar {
+            bar.inc(added);
+        }
+    }
+    
+    /// Record that a shard has been searched completely
+    pub fn complete(&self, shard: &Shard) {
+        let before = self.searched[shard.id].swap(shard.bytes, Ordering::Relaxed).min(shard.bytes);
+        let completed = self.completed.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(bar) = &self.bar {
+            bar.inc(shard.bytes - before);
+            bar.set_message(format!("{}/{} shards", completed, self.searched.len()));
+        }
+    }
+    
+    /// Fraction of all bytes that have been searched
+    pub fn fraction(&self) -> f64 {
+        let searched: u64 = self.searched.iter()
+            .zip(&self.sizes)
+            .map(|(searched, &size)| searched.load(Ordering::Relaxed).min(size))
+            .sum();
+        match self.sizes.iter().sum::<u64>() {
+            0 => 1.0,
+            total => searched as f64 / total as f64,
+        }
+    }
+    
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_with_message("Search complete");
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::{anyhow, Result, Context};
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A connection between a coordinator and a worker, either plain TCP or TLS
+pub(crate) trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// TLS settings of a worker, from its certificate chain and private key
+pub(crate) fn server_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>> {
+    let certs = read_certs(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("Failed to read private key from {}", key.display()))?;
+    
+    let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")?;
+    Ok(Arc::new(config))
+}
+
+/// TLS settings of a coordinator, trusting workers whose certificates are
+/// signed by the certificate authorities in `ca`
+pub(crate) fn client_config(ca: &Path) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(ca)? {
+        roots.add(cert)
+            .with_context(|| format!("Invalid CA certificate in {}", ca.display()))?;
+    }
+    
+    let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read certificates from {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// Open a TLS session with a worker over a connected socket, checking that
+/// its certificate is valid for the host of its address
+pub(crate) fn connect(config: &Arc<ClientConfig>, worker: &str, mut stream: TcpStream) -> Result<Box<dyn Stream>> {
+    let host = worker.rsplit_once(':').map_or(worker, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let name = ServerName::try_from(host.to_string())
+        .with_context(|| format!("Invalid worker host name {}", host))?;
+    
+    let mut connection = ClientConnection::new(config.clone(), name)?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream).context("TLS handshake failed")?;
+    }
+    Ok(Box::new(StreamOwned::new(connection, stream)))
+}
+
+/// Accept a TLS session from a coordinator over a connected socket
+pub(crate) fn accept(config: &Arc<ServerConfig>, mut stream: TcpStream) -> Result<Box<dyn Stream>> {
+    let mut connection = ServerConnection::new(config.clone())?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream).context("TLS handshake failed")?;
+    }
+    Ok(Box::new(StreamOwned::new(connection, stream)))
+}
@@ -0,0 +1,393 @@
+use anyhow::{bail, Result, Context};
+use log::{debug, info, warn};
+use rustls::ServerConfig;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::engine::{ContextLine, FileStats, LineMatch, SearchResult};
+use crate::error::{ErrorKind, SearchError};
+use crate::io::roots::Roots;
+use crate::sink::Sink;
+
+use super::auth::{self, Role};
+use super::protocol::{self, Hello, Request, RequestKind, HEARTBEAT_INTERVAL, VERSION};
+use super::tls::{self, Stream};
+
+/// How long a coordinator may take to complete the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builder for configuring a [`Worker`]
+#[derive(Clone, Default)]
+pub struct WorkerBuilder {
+    threads: usize,
+    io_threads: usize,
+    secret: Option<Vec<u8>>,
+    tls: Option<(PathBuf, PathBuf)>,
+    roots: Vec<PathBuf>,
+}
+
+impl WorkerBuilder {
+    /// Create a new builder with automatic thread counts
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Number of threads searching file contents for each request (0 = auto)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+    
+    /// Number of threads walking directories and reading file metadata for each request (0 = auto)
+    pub fn io_threads(mut self, threads: usize) -> Self {
+        self.io_threads = threads;
+        self
+    }
+    
+    /// Only serve coordinators that prove they know this shared secret, and
+    /// prove it to them in turn
+    pub fn secret(mut self, secret: Option<Vec<u8>>) -> Self {
+        self.secret = secret;
+        self
+    }
+    
+    /// Encrypt connections with TLS, using the certificate chain and private
+    /// key in these PEM files
+    pub fn tls<P: Into<PathBuf>>(mut self, cert: P, key: P) -> Self {
+        self.tls = Some((cert.into(), key.into()));
+        self
+    }
+    
+    /// Only allow searches of paths under these directories (default: the current directory)
+    pub fn roots<I, P>(mut self, roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.roots = roots.into_iter().map(Into::into).collect();
+        self
+    }
+    
+    /// Listen for coordinators on the given address
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> Result<Worker> {
+        let tls = match &self.tls {
+            Some((cert, key)) => Some(tls::server_config(cert, key)?),
+            None => None,
+        };
+        let roots = if self.roots.is_empty() {
+            Roots::new(&[std::env::current_dir().context("Failed to get the current directory")?])?
+        } else {
+            Roots::new(&self.roots)?
+        };
+        let listener = TcpListener::bind(addr)
+            .context("Failed to bind worker address")?;
+        
+        Ok(Worker {
+            listener,
+            config: Arc::new(WorkerConfig {
+                threads: self.threads,
+                io_threads: self.io_threads,
+                secret: self.secret,
+                tls,
+                roots,
+            }),
+        })
+    }
+}
+
+/// A node of a distributed search, which searches its local file system on
+/// behalf of coordinators.
+///
+/// Each connection serves one coordinator. The paths of its requests are
+/// searched on the worker, and the results are streamed back to the
+/// coordinator as they are found. With a shared secret, coordinators that
+/// cannot prove they know it are turned away before sending any request.
+/// Requests for paths outside the root directories, the current directory
+/// unless given, are rejected, and files reached through symbolic links that
+/// lead outside them are skipped.
+pub struct Worker {
+    listener: TcpListener,
+    config: Arc<WorkerConfig>,
+}
+
+struct WorkerConfig {
+    threads: usize,
+    io_threads: usize,
+    secret: Option<Vec<u8>>,
+    tls: Option<Arc<ServerConfig>>,
+    roots: Roots,
+}
+
+impl Worker {
+    /// Address the worker is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+    
+    /// Whether coordinators must prove they know a shared secret
+    pub fn is_authenticated(&self) -> bool {
+        self.config.secret.is_some()
+    }
+    
+    /// Serve coordinators until the process exits, handling each connection
+    /// on its own thread
+    pub fn serve(self) -> Result<()> {
+        info!("Worker listening on {}", self.local_addr()?);
+        
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+            
+            let config = self.config.clone();
+            std::thread::Builder::new()
+                .name("grepx-worker".to_string())
+                .spawn(move || {
+                    let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+                    if let Err(e) = handle_connection(stream, &config) {
+                        warn!("Request from {} failed: {:#}", peer, e);
+                    }
+                })
+                .context("Failed to spawn worker thread")?;
+        }
+        Ok(())
+    }
+}
+
+/// Serve the requests of a coordinator until it closes the connection
+fn handle_connection(stream: TcpStream, config: &WorkerConfig) -> Result<()> {
+    let socket = stream.try_clone()?;
+    socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let stream: Box<dyn Stream> = match &config.tls {
+        Some(tls) => tls::accept(tls, stream)?,
+        None => Box::new(stream),
+    };
+    let mut reader = BufReader::new(stream);
+    handshake(&mut reader, config.secret.as_deref())?;
+    
+    // Coordinators keep connections open while waiting for shards to search
+    socket.set_read_timeout(None)?;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).context("Failed to read request")? == 0 {
+            return Ok(());
+        }
+        handle_request(reader.get_mut(), &line, config)?;
+    }
+}
+
+/// Answer a coordinator's hello, proving that the worker knows the shared
+/// secret, and check the coordinator's proof in turn
+fn handshake(reader: &mut BufReader<Box<dyn Stream>>, secret: Option<&[u8]>) -> Result<()> {
+    let hello = Hello::parse(&read_message(reader)?)?;
+    auth::check_nonce(&hello.nonce)?;
+    let nonce = auth::nonce()?;
+    let proof = secret.map(|secret| auth::prove(secret, Role::Worker, &hello.nonce, &nonce));
+    send(reader.get_mut(), &Hello { version: VERSION, nonce: nonce.clone(), proof }.to_json())?;
+    
+    let proof = protocol::parse_auth(&read_message(reader)?)?;
+    if let Some(secret) = secret {
+        let verified = proof.is_some_and(|proof| auth::verify(secret, Role::Coordinator, &nonce, &hello.nonce, &proof));
+        if !verified {
+            let error = SearchError::new(ErrorKind::PermissionDenied, None, "coordinator failed to prove the shared secret");
+            send(reader.get_mut(), &protocol::error_json(&error))?;
+            bail!("Coordinator failed to authenticate");
+        }
+    }
+    send(reader.get_mut(), &protocol::ready_json())
+}
+
+fn read_message(reader: &mut BufReader<Box<dyn Stream>>) -> Result<Value> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).context("Failed to read handshake")? == 0 {
+        bail!("Connection closed during handshake");
+    }
+    serde_json::from_str(&line).context("Malformed handshake")
+}
+
+fn send(stream: &mut dyn Stream, message: &Value) -> Result<()> {
+    stream.write_all(protocol::to_line(message).as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Run a single request and send back its results, sending heartbeats until
+/// it completes
+fn handle_request(stream: &mut dyn Stream, line: &str, config: &WorkerConfig) -> Result<()> {
+    let sink = StreamSink::new(stream);
+    let (done, finished) = mpsc::channel::<()>();
+    std::thread::scope(|scope| {
+        let sink = &sink;
+        scope.spawn(move || {
+            let heartbeat = protocol::to_line(&protocol::heartbeat_json());
+            while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(HEARTBEAT_INTERVAL) {
+                sink.write(&heartbeat);
+            }
+        });
+        let outcome = run_request(sink, line, config);
+        drop(done);
+        outcome
+    })
+}
+
+/// Parse and run a request, sending its results to the sink
+fn run_request(sink: &StreamSink, line: &str, config: &WorkerConfig) -> Result<()> {
+    let request = serde_json::from_str::<Value>(line)
+        .context("Malformed request")
+        .and_then(|request| Request::parse(&request))
+        .and_then(|(kind, builder, paths)| {
+            let searcher = config.roots.restrict(builder, &paths)?
+                .threads(config.threads)
+                .io_threads(config.io_threads)
+                .cancel_flag(sink.cancel.clone())
+                .build()?;
+            Ok((kind, searcher, paths))
+        });
+    
+    match request {
+        Ok((RequestKind::Manifest, searcher, paths)) => {
+            debug!("Listing files of {:?}", paths);
+            match searcher.manifest(&paths) {
+                Ok(manifest) => sink.send(&manifest.to_json()),
+                Err(e) => fail(sink, e),
+            }
+        }
+        Ok((RequestKind::Search, searcher, paths)) => {
+            debug!("Searching {} paths", paths.len());
+            match searcher.search_paths_with(&paths, sink) {
+                Ok(result) => sink.send(&protocol::finish_json(&result)),
+                Err(e) => fail(sink, e),
+            }
+        }
+        Err(e) => fail(sink, e),
+    }
+}
+
+/// Report a request that could not be run as a finished search with a single
+/// error, so the coordinator always receives an answer
+fn fail(sink: &StreamSink, e: anyhow::Error) -> Result<()> {
+    let error = match e.downcast_ref::<SearchError>() {
+        Some(error) => error.clone(),
+        None => SearchError::new(ErrorKind::Io, None, format!("{:#}", e)),
+    };
+    sink.on_error(&error);
+    sink.send(&protocol::finish_json(&SearchResult {
+        errors: vec![error],
+        ..SearchResult::default()
+    }))
+}
+
+/// Sink that streams search events to a coordinator. The events of each file
+/// are collected and written together once the file is complete, so that the
+/// events of files searched in parallel are never interleaved.
+struct StreamSink<'a> {
+    output: Mutex<Output<'a>>,
+    pending: Mutex<HashMap<Option<PathBuf>, String>>,
+    /// Set when the coordinator can no longer be reached, which cancels the
+    /// search, e.g. once it has disconnected because its limits were reached
+    cancel: Arc<AtomicBool>,
+}
+
+struct Output<'a> {
+    stream: &'a mut dyn Stream,
+    /// First write error, after which nothing more is sent
+    failure: Option<io::Error>,
+}
+
+impl<'a> StreamSink<'a> {
+    fn new(stream: &'a mut dyn Stream) -> Self {
+        Self {
+            output: Mutex::new(Output {
+                stream,
+                failure: None,
+            }),
+            pending: Mutex::new(HashMap::new()),
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+    
+    /// Add a message to the pending events of a file
+    fn push(&self, path: Option<&Path>, message: &Value) {
+        self.pending.lock().unwrap()
+            .entry(path.map(Path::to_path_buf))
+            .or_default()
+            .push_str(&protocol::to_line(message));
+    }
+    
+    /// Send a message immediately
+    fn send(&self, message: &Value) -> Result<()> {
+        self.write(&protocol::to_line(message));
+        self.failure()
+    }
+    
+    fn write(&self, data: &str) {
+        let mut output = self.output.lock().unwrap();
+        if output.failure.is_none() {
+            let written = output.stream.write_all(data.as_bytes())
+                .and_then(|()| output.stream.flush());
+            if let Err(e) = written {
+                output.failure = Some(e);
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    
+    fn failure(&self) -> Result<()> {
+        match self.output.lock().unwrap().failure.take() {
+            Some(e) => Err(e).context("Failed to send results to coordinator"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Sink for StreamSink<'_> {
+    fn on_file_begin(&self, path: Option<&Path>) {
+        self.push(path, &protocol::begin_json(path));
+    }
+    
+    fn on_match(&self, path: Option<&Path>, line: &LineMatch) {
+        self.push(path, &protocol::match_json(path, line));
+    }
+    
+    fn on_context(&self, path: Option<&Path>, line: &ContextLine) {
+        self.push(path, &protocol::context_json(path, line));
+    }
+    
+    fn on_context_break(&self, path: Option<&Path>) {
+        self.push(path, &protocol::context_break_json(path));
+    }
+    
+    fn on_error(&self, error: &SearchError) {
+        // Errors for a file being searched are sent with the file, and errors
+        // found while discovering files are sent straight away
+        let path = error.path.clone();
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get_mut(&path) {
+            Some(events) => events.push_str(&protocol::to_line(&protocol::error_json(error))),
+            None => {
+                drop(pending);
+                self.write(&protocol::to_line(&protocol::error_json(error)));
+            }
+        }
+    }
+    
+    fn on_file_end(&self, path: Option<&Path>, stats: &FileStats) {
+        let mut events = self.pending.lock().unwrap()
+            .remove(&path.map(Path::to_path_buf))
+            .unwrap_or_default();
+        events.push_str(&protocol::to_line(&protocol::end_json(path, stats)));
+        self.write(&events);
+    }
+}
@@ -1,56 +1,393 @@
-use anyhow::{Result, Context};
+use anyhow::{bail, Result, Context};
 use log::{debug, info};
 use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::io::{BufRead, BufReader};
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use crate::cli::Args;
-use crate::io::{file_discovery, reader::FileReader};
+use crate::distributed::{NodeState, NodeStatus};
+use crate::error::SearchError;
+use crate::io::file_discovery;
+use crate::io::follow::{identity, FollowReader, Update};
+use crate::io::prefetch::Prefetcher;
+use crate::io::reader::FileReader;
+use crate::io::strategy::{ReadPlanner, ReadStrategy};
+use crate::pool::ThreadPools;
 use crate::regex::RegexMatcher;
+use crate::reorder::{FileBuffer, Reorder, Slot};
+use crate::searcher::SearchOptions;
+use crate::sink::Sink;
 
 /// Search result statistics
 #[derive(Debug, Default)]
 pub struct SearchResult {
+    /// Matching lines per file; only populated when results are collected
+    /// rather than streamed to a [`Sink`]
+    pub files: Vec<FileResult>,
     pub total_matches: usize,
     pub files_searched: usize,
     pub files_with_matches: usize,
+    pub matched_lines: usize,
     pub bytes_processed: usize,
+    /// Bytes read from files with buffered I/O
+    pub bytes_read: usize,
+    /// Bytes read from files through memory maps
+    pub bytes_mapped: usize,
+    /// Time spent finding the files to search
+    pub discovery_time: Duration,
+    /// Time spent searching files
+    pub search_time: Duration,
+    /// Total time taken by the search
+    pub elapsed: Duration,
+    /// Errors for paths that could not be fully searched
+    pub errors: Vec<SearchError>,
+    /// Whether a match limit stopped the search before all input was searched
+    pub truncated: bool,
+    /// Whether the search timeout expired before all files were searched
+    pub timed_out: bool,
+    /// Files that were only partially searched because a timeout expired
+    pub timed_out_files: Vec<PathBuf>,
+    /// Files that were not searched because they exceed the size limit
+    pub skipped_files: Vec<PathBuf>,
+    /// Files that were not searched because the workers holding them failed
+    pub unsearched_files: Vec<PathBuf>,
+    /// Outcome for each worker of a distributed search; empty for local searches
+    pub nodes: Vec<NodeStatus>,
 }
 
-/// Main search execution function
-pub fn execute_search(args: &Args) -> Result<SearchResult> {
-    info!("Initializing search engine");
+impl SearchResult {
+    /// Record the statistics of one searched file
+    pub fn add(&mut self, stats: &FileStats) {
+        self.total_matches += stats.matches;
+        self.files_searched += 1;
+        self.matched_lines += stats.matched_lines;
+        self.bytes_processed += stats.bytes_processed;
+        self.bytes_read += stats.bytes_read;
+        self.bytes_mapped += stats.bytes_mapped;
+        
+        if stats.matches > 0 {
+            self.files_with_matches += 1;
+        }
+    }
     
-    // Create regex matcher
-    let matcher = RegexMatcher::new(&args.pattern, args.case_sensitive)?;
+    /// Add the counters, flags and file lists of a result for other files,
+    /// such as those searched by another thread or worker
+    pub fn merge(&mut self, other: SearchResult) {
+        self.total_matches += other.total_matches;
+        self.files_searched += other.files_searched;
+        self.files_with_matches += other.files_with_matches;
+        self.matched_lines += other.matched_lines;
+        self.bytes_processed += other.bytes_processed;
+        self.bytes_read += other.bytes_read;
+        self.bytes_mapped += other.bytes_mapped;
+        self.files.extend(other.files);
+        self.errors.extend(other.errors);
+        self.truncated |= other.truncated;
+        self.timed_out |= other.timed_out;
+        self.timed_out_files.extend(other.timed_out_files);
+        self.skipped_files.extend(other.skipped_files);
+        self.unsearched_files.extend(other.unsearched_files);
+        self.nodes.extend(other.nodes);
+    }
     
-    // Configure thread pool size
-    let num_threads = if args.threads == 0 {
-        // Auto-detect number of threads
-        std::thread::available_parallelism()
-            .map(|n| n.get())
-            .unwrap_or(1)
-    } else {
-        args.threads
-    };
-    info!("Using {} threads for search", num_threads);
+    /// Whether files were left unsearched because workers of a distributed
+    /// search failed
+    pub fn is_partial(&self) -> bool {
+        !self.unsearched_files.is_empty() || self.nodes.iter().any(|node| node.state == NodeState::Failed)
+    }
+    
+    /// Number of distinct paths with errors
+    pub fn files_with_errors(&self) -> usize {
+        self.errors.iter()
+            .filter_map(|error| error.path.as_deref())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+/// Statistics accumulated by a single worker without synchronization. The
+/// totals of all workers are merged once every file has been searched.
+#[derive(Default)]
+struct Totals {
+    result: SearchResult,
+    /// Errors and partially searched files, keyed by their position in the
+    /// file order so they can be reported in that order after merging
+    errors: Vec<(usize, SearchError)>,
+    timed_out_files: Vec<(usize, PathBuf)>,
+}
+
+impl Totals {
+    fn merge(mut self, other: Totals) -> Totals {
+        self.result.merge(other.result);
+        self.errors.extend(other.errors);
+        self.timed_out_files.extend(other.timed_out_files);
+        self
+    }
+    
+    /// Produce the final result, with errors from file discovery first
+    fn into_result(mut self, discovery_errors: Vec<SearchError>) -> SearchResult {
+        self.errors.sort_by_key(|(index, _)| *index);
+        self.timed_out_files.sort_by_key(|(index, _)| *index);
+        
+        let mut result = self.result;
+        result.errors = discovery_errors;
+        result.errors.extend(self.errors.into_iter().map(|(_, error)| error));
+        result.timed_out_files = self.timed_out_files.into_iter().map(|(_, path)| path).collect();
+        result
+    }
+}
+
+/// Matches found in a single file or input
+#[derive(Debug, Clone, Default)]
+pub struct FileResult {
+    /// Path of the searched file, or `None` for in-memory input
+    pub path: Option<PathBuf>,
+    pub matches: usize,
+    pub lines: Vec<LineMatch>,
+    pub bytes_processed: usize,
+}
+
+/// Statistics for a single searched file
+#[derive(Debug, Clone, Default)]
+pub struct FileStats {
+    pub matches: usize,
+    pub matched_lines: usize,
+    pub bytes_processed: usize,
+    /// Bytes read from the file with buffered I/O, including overlap between chunks
+    pub bytes_read: usize,
+    /// Bytes read from the file through a memory map, including overlap between chunks
+    pub bytes_mapped: usize,
+    /// Whether a timeout expired before the whole file was searched
+    pub timed_out: bool,
+}
+
+impl FileStats {
+    /// Record bytes read from the file
+    fn add_read(&mut self, bytes: usize, mapped: bool) {
+        if mapped {
+            self.bytes_mapped += bytes;
+        } else {
+            self.bytes_read += bytes;
+        }
+    }
     
-    // Set global thread pool
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(num_threads)
-        .build_global()
-        .unwrap_or_else(|e| {
-            debug!("Failed to set global thread pool: {}", e);
-        });
+    /// Statistics of the part of the file searched since `earlier` was taken
+    fn since(&self, earlier: &FileStats) -> FileStats {
+        FileStats {
+            matches: self.matches - earlier.matches,
+            matched_lines: self.matched_lines - earlier.matched_lines,
+            bytes_processed: self.bytes_processed - earlier.bytes_processed,
+            bytes_read: self.bytes_read - earlier.bytes_read,
+            bytes_mapped: self.bytes_mapped - earlier.bytes_mapped,
+            timed_out: self.timed_out,
+        }
+    }
+}
+
+/// A line containing at least one match
+#[derive(Debug, Clone)]
+pub struct LineMatch {
+    /// 1-based line number
+    pub line_number: usize,
+    /// Byte offset of the start of the line within the input
+    pub byte_offset: usize,
+    /// Line content without the trailing newline
+    pub line: String,
+    /// Byte ranges of each match, relative to the start of the line
+    pub matches: Vec<(usize, usize)>,
+}
+
+/// Whether a context line comes before or after a match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextKind {
+    Before,
+    After,
+}
+
+/// A line reported around a match for context
+#[derive(Debug, Clone)]
+pub struct ContextLine {
+    /// 1-based line number
+    pub line_number: usize,
+    /// Byte offset of the start of the line within the input
+    pub byte_offset: usize,
+    /// Line content without the trailing newline
+    pub line: String,
+    pub kind: ContextKind,
+}
+
+/// State shared by all workers of a search, used to enforce match limits and
+/// to stop the search early
+pub(crate) struct SearchControl {
+    stop: AtomicBool,
+    truncated: AtomicBool,
+    timed_out: AtomicBool,
+    deadline: Option<Instant>,
+    matched_lines: AtomicUsize,
+    matched_files: AtomicUsize,
+    max_total: Option<usize>,
+    max_files: Option<usize>,
+    /// Set from outside the search to cancel it
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl SearchControl {
+    pub fn new(options: &SearchOptions) -> Self {
+        Self {
+            stop: AtomicBool::new(false),
+            truncated: AtomicBool::new(false),
+            timed_out: AtomicBool::new(false),
+            deadline: options.timeout.map(|timeout| Instant::now() + timeout),
+            matched_lines: AtomicUsize::new(0),
+            matched_files: AtomicUsize::new(0),
+            max_total: options.max_total,
+            max_files: options.max_files,
+            cancel: options.cancel.clone(),
+        }
+    }
+    
+    /// Whether all workers should stop searching, either because the search
+    /// was stopped or cancelled or because its timeout expired
+    pub fn stopped(&self) -> bool {
+        if self.stop.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            self.stop();
+            return true;
+        }
+        
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.timed_out.store(true, Ordering::Relaxed);
+            self.stop();
+            return true;
+        }
+        false
+    }
     
-    // Discover files to search
-    let files = file_discovery::find_files(&args.path, args.recursive)?;
-    info!("Found {} files to search", files.len());
+    /// Stop all workers
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+    
+    /// Record that input was left unsearched because of a match limit
+    pub fn truncate(&self) {
+        if !self.timed_out() {
+            self.truncated.store(true, Ordering::Relaxed);
+        }
+    }
+    
+    pub fn truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+    
+    pub fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+    
+    /// Claim a slot for a new file with matches, returning false once the
+    /// file limit has been reached
+    pub fn accept_file(&self) -> bool {
+        let Some(max_files) = self.max_files else {
+            return true;
+        };
+        
+        let previous = self.matched_files.fetch_add(1, Ordering::Relaxed);
+        if previous == max_files {
+            // Files that already have matches are finished, but no new ones are started
+            debug!("Found more than {} files with matches", max_files);
+        }
+        previous < max_files
+    }
+    
+    /// Claim a slot for a new matching line, returning false once the total
+    /// limit has been reached. The search only stops once a line is refused,
+    /// so it is not reported as truncated when nothing more would have matched.
+    pub fn accept_line(&self) -> bool {
+        let Some(max_total) = self.max_total else {
+            return true;
+        };
+        
+        let previous = self.matched_lines.fetch_add(1, Ordering::Relaxed);
+        if previous >= max_total {
+            self.stop();
+            return false;
+        }
+        true
+    }
+    
+    /// Whether as many lines as the total limit allows have been accepted
+    pub fn total_reached(&self) -> bool {
+        self.max_total
+            .is_some_and(|max_total| self.matched_lines.load(Ordering::Relaxed) >= max_total)
+    }
+    
+    /// Whether new files may still be started, which they may until a file
+    /// with matches has been refused
+    pub fn accepting_files(&self) -> bool {
+        self.max_files
+            .is_none_or(|max_files| self.matched_files.load(Ordering::Relaxed) <= max_files)
+    }
+}
+
+/// Files found for a search, after filtering, size limits and sorting
+pub(crate) struct Discovery {
+    pub files: Vec<PathBuf>,
+    /// Files left out because they exceed the size limit
+    pub skipped_files: Vec<PathBuf>,
+    /// Paths that could not be accessed
+    pub errors: Vec<SearchError>,
+}
+
+/// Discover the files to search on the I/O pool
+pub(crate) fn discover_files(paths: &[PathBuf], options: &SearchOptions, pools: &ThreadPools) -> Result<Discovery> {
+    let mut errors = Vec::new();
+    let (files, skipped_files) = pools.io.install(|| -> Result<_> {
+        let mut files = file_discovery::find_files(paths, options.recursive, &mut errors)?;
+        if let Some(filter) = &options.filter {
+            files.retain(|file| filter(file));
+            errors.retain(|error: &SearchError| error.path.as_deref().is_none_or(|path| filter(path)));
+        }
+        if let Some(candidates) = options.candidates.as_ref().filter(|c| c.is_selective()) {
+            let may_match: Vec<bool> = files.par_iter().map(|file| candidates.may_match(file)).collect();
+            let before = files.len();
+            let mut may_match = may_match.into_iter();
+            files.retain(|_| may_match.next().unwrap_or(true));
+            debug!("Index ruled out {} of {} files", before - files.len(), before);
+        }
+        let skipped_files = match options.max_filesize {
+            Some(max_filesize) => file_discovery::remove_large_files(&mut files, max_filesize),
+            None => Vec::new(),
+        };
+        file_discovery::sort_files(&mut files, options.sort_by);
+        Ok((files, skipped_files))
+    })?;
+    
+    Ok(Discovery {
+        files,
+        skipped_files,
+        errors,
+    })
+}
+
+/// Main search execution function. Files recorded in `searched` are only
+/// searched from where their last search ended.
+pub(crate) fn execute_search<S: Sink + ?Sized>(paths: &[PathBuf], matcher: &RegexMatcher, options: &SearchOptions, pools: &ThreadPools, searched: Option<&SearchedFiles>, sink: &S) -> Result<SearchResult> {
+    info!("Initializing search engine");
+    let start = Instant::now();
+    
+    let Discovery { files, skipped_files, errors: discovery_errors } = discover_files(paths, options, pools)?;
+    for error in &discovery_errors {
+        sink.on_error(error);
+    }
+    let discovery_time = start.elapsed();
+    info!("Found {} files to search in {:?}", files.len(), discovery_time);
     
     // Setup progress display if enabled
-    let progress = if args.progress {
+    let progress = if options.progress {
         let pb = ProgressBar::new(files.len() as u64);
         pb.set_style(
             ProgressStyle::default_bar()
@@ -62,136 +399,691 @@ pub fn execute_search(args: &Args) -> Result<SearchResult> {
         None
     };
     
-    // Store search results
-    let result = Arc::new(Mutex::new(SearchResult::default()));
+    let reorder = options.ordered
+        .then(|| Reorder::new(sink, options.reorder_window, options.max_buffered));
+    let control = SearchControl::new(options);
+    let planner = ReadPlanner::new(options.read_strategy,
+                                   options.chunk_size.map(|kb| kb * 1024),
+                                   pools.search.current_num_threads());
+    let prefetcher = options.prefetch
+        .then(|| Prefetcher::new(&files, pools.search.current_num_threads(), &pools.io));
     
-    // Process files in parallel
-    files.par_iter()
-        .for_each(|file| {
-            let file_str = file.to_string_lossy();
-            debug!("Searching file: {}", file_str);
+    // Process files in parallel. Files are handed to workers in order so that
+    // results can be reported in order with little buffering. Each worker
+    // accumulates its own totals, which are merged at the end.
+    let totals = pools.search.install(|| files.iter()
+        .enumerate()
+        .par_bridge()
+        .fold(Totals::default, |mut totals, (index, file)| {
+            // Skip remaining files once the search has been stopped
+            if control.stopped() || !control.accepting_files() {
+                control.truncate();
+                if let Some(reorder) = &reorder {
+                    reorder.complete(index, Some(FileBuffer::new(Some(file))));
+                }
+                return totals;
+            }
+            
+            debug!("Searching file: {}", file.display());
+            if let Some(prefetcher) = &prefetcher {
+                prefetcher.advance(index);
+            }
+            
+            // Process individual file, buffering its results if earlier files are still in progress
+            let (stats, errors) = match reorder.as_ref().map(|r| r.acquire(index)) {
+                Some(Slot::Buffered) => {
+                    let buffer = FileBuffer::new(Some(file));
+                    let outcome = search_file(file, matcher, options, &control, &planner, searched, &buffer);
+                    reorder.as_ref().unwrap().complete(index, Some(buffer));
+                    outcome
+                }
+                Some(Slot::Direct) => {
+                    let outcome = search_file(file, matcher, options, &control, &planner, searched, sink);
+                    reorder.as_ref().unwrap().complete(index, None);
+                    outcome
+                }
+                None => search_file(file, matcher, options, &control, &planner, searched, sink),
+            };
             
-            // Process individual file
-            match process_file(file, &matcher, args) {
-                Ok(file_result) => {
-                    // Update global results
-                    let mut result_guard = result.lock().unwrap();
-                    result_guard.total_matches += file_result.matches;
-                    result_guard.files_searched += 1;
-                    result_guard.bytes_processed += file_result.bytes_processed;
-                    
-                    if file_result.matches > 0 {
-                        result_guard.files_with_matches += 1;
-                        
-                        // Output results based on format options
-                        if !args.count && !args.files_with_matches {
-                            // For real-time display, we display matches immediately
-                            if args.line_numbers {
-                                // Display with line numbers requires opening the file again
-                                // This is not optimal but necessary for exact line numbers
-                                if let Err(e) = display_matches_with_line_numbers(file, &matcher, args) {
-                                    eprintln!("Error displaying matches for {}: {}", file_str, e);
-                                }
-                            } else {
-                                println!("Found {} matches in {}", file_result.matches, file_str);
-                            }
-                        } else if args.files_with_matches && file_result.matches > 0 {
-                            println!("{}", file_str);
-                        } else if args.count {
-                            println!("{}: {}", file_str, file_result.matches);
-                        }
-                    }
-                },
-                Err(e) => {
-                    eprintln!("Error processing file {}: {}", file_str, e);
+            // Files with errors could not be searched
+            if errors.is_empty() {
+                totals.result.add(&stats);
+                if stats.timed_out {
+                    totals.timed_out_files.push((index, file.clone()));
                 }
             }
+            totals.errors.extend(errors.into_iter().map(|error| (index, error)));
             
             // Update progress
             if let Some(pb) = &progress {
                 pb.inc(1);
             }
-        });
+            totals
+        })
+        .reduce(Totals::default, Totals::merge));
     
     // Finish progress display
     if let Some(pb) = progress {
         pb.finish_with_message("Search complete");
     }
     
-    let final_result = Arc::try_unwrap(result)
-        .expect("Failed to unwrap Arc")
-        .into_inner()
-        .expect("Failed to unwrap Mutex");
+    let mut final_result = totals.into_result(discovery_errors);
+    final_result.discovery_time = discovery_time;
+    final_result.elapsed = start.elapsed();
+    final_result.search_time = final_result.elapsed - discovery_time;
+    final_result.truncated = control.truncated();
+    final_result.timed_out = control.timed_out();
+    final_result.skipped_files = skipped_files;
     
     info!("Search completed. Found {} matches in {} files ({} had matches)",
           final_result.total_matches,
           final_result.files_searched,
           final_result.files_with_matches);
     
+    sink.on_finish(&final_result);
     Ok(final_result)
 }
 
-// Result for processing a single file
-struct FileResult {
-    matches: usize,
-    bytes_processed: usize,
+// Search a single file, reporting its results to the sink
+fn search_file<S: Sink + ?Sized>(file: &Path, matcher: &RegexMatcher, options: &SearchOptions, control: &SearchControl, planner: &ReadPlanner, searched: Option<&SearchedFiles>, sink: &S) -> (FileStats, Vec<SearchError>) {
+    sink.on_file_begin(Some(file));
+    let mut searcher = LineSearcher::new(matcher, options, control, sink, Some(file));
+    let outcome = process_file(file, options, planner, searched, &mut searcher);
+    let stats = searcher.finish();
+    
+    let mut errors = Vec::new();
+    if let Err(e) = outcome {
+        debug!("Error processing file {}: {:#}", file.display(), e);
+        errors.push(SearchError::from_anyhow(file, &e));
+    }
+    for error in &errors {
+        sink.on_error(error);
+    }
+    
+    sink.on_file_end(Some(file), &stats);
+    (stats, errors)
 }
 
 // Process individual file
-fn process_file(file: &std::path::Path, matcher: &RegexMatcher, args: &Args) -> Result<FileResult> {
-    let reader = FileReader::new(file)?;
+fn process_file<S: Sink + ?Sized>(file: &Path, options: &SearchOptions, planner: &ReadPlanner, searched: Option<&SearchedFiles>, searcher: &mut LineSearcher<'_, S>) -> Result<()> {
+    let metadata = std::fs::metadata(file)
+        .with_context(|| format!("Failed to get metadata for file: {}", file.display()))?;
+    let size = metadata.len();
+    let identity = identity(&metadata);
+    let plan = planner.plan(size);
+    debug!("Reading {} ({} bytes) with {:?} strategy in {} KB chunks",
+           file.display(), size, plan.strategy, plan.chunk_size / 1024);
+    
+    let reader = FileReader::open(file, plan.strategy)?;
     let file_size = reader.size() as usize;
+    let chunk_size = plan.chunk_size;
+    let mapped = reader.uses_mmap();
+    if options.prefetch && file_size > chunk_size {
+        reader.advise_sequential();
+    }
     
-    // For small files, just read the whole file at once
-    if file_size < args.chunk_size * 1024 {
+    // A file searched before is only searched from where that search ended
+    let resume = match searched {
+        Some(searched) => searched.resume(file, identity, &reader)?,
+        None => None,
+    };
+    
+    // Read small files and buffered files whole
+    if resume.is_none() && (plan.strategy == ReadStrategy::Buffered || file_size < chunk_size) {
+        let start = Instant::now();
         let content = reader.read_all()?;
-        let match_count = matcher.match_count(&content);
+        if !mapped {
+            planner.record_read(content.len(), start.elapsed());
+        }
+        searcher.stats.add_read(content.len(), mapped);
+        searcher.search(&content, 0, 0, 1);
+        searcher.stats.bytes_processed = content.len();
+        if let Some(searched) = searched {
+            let line_number = 1 + memchr::memchr_iter(b'\n', &content).count();
+            searched.record(file, identity, &content, 0, line_number, options.before_context);
+        }
+        return Ok(());
+    }
+    
+    // For large files, process in chunks that end on a line boundary. Each chunk
+    // is preceded by the last few lines of the previous one so that context
+    // lines before a match can be reported across chunk boundaries.
+    let (mut prefix_start, mut offset, mut line_number) = resume.unwrap_or((0, 0, 1));
+    let resumed_from = offset;
+    
+    while offset < file_size && !searcher.stopped() {
+        let search_from = offset - prefix_start;
+        let start = Instant::now();
+        let chunk = reader.read_chunk(prefix_start, search_from + chunk_size)?;
+        if !mapped {
+            planner.record_read(chunk.len(), start.elapsed());
+        }
+        searcher.stats.add_read(chunk.len(), mapped);
+        if chunk.len() <= search_from {
+            break;
+        }
+        
+        // Only search up to the last complete line unless this is the end of the file
+        let at_eof = prefix_start + chunk.len() >= file_size;
+        let len = match memchr::memrchr(b'\n', &chunk[search_from..]) {
+            Some(pos) if !at_eof => search_from + pos + 1,
+            _ => chunk.len(),
+        };
+        let chunk = &chunk[..len];
+        
+        // Start reading the next chunk while this one is searched
+        if options.prefetch {
+            reader.prefetch(prefix_start + len, chunk_size);
+        }
+        searcher.search(chunk, prefix_start, search_from, line_number);
         
-        return Ok(FileResult {
-            matches: match_count,
-            bytes_processed: content.len(),
-        });
+        line_number += memchr::memchr_iter(b'\n', &chunk[search_from..]).count();
+        if let Some(searched) = searched {
+            searched.record(file, identity, chunk, prefix_start, line_number, options.before_context);
+        }
+        offset = prefix_start + len;
+        prefix_start += start_of_last_lines(chunk, options.before_context);
     }
     
-    // For large files, process in chunks
-    let chunk_size = args.chunk_size * 1024;
-    let mut offset = 0;
-    let mut total_matches = 0;
+    if offset < file_size && searcher.stopped() {
+        searcher.truncate();
+    }
     
-    while offset < file_size {
-        let chunk = reader.read_chunk(offset, chunk_size)?;
-        if chunk.is_empty() {
+    searcher.stats.bytes_processed = offset - resumed_from;
+    Ok(())
+}
+
+/// Find the start of the last `count` lines of a buffer ending on a line boundary
+fn start_of_last_lines(text: &[u8], count: usize) -> usize {
+    let mut start = text.len();
+    for _ in 0..count {
+        if start == 0 {
             break;
         }
+        start = memchr::memrchr(b'\n', &text[..start - 1]).map_or(0, |pos| pos + 1);
+    }
+    start
+}
+
+/// How far watched files have been searched, so that searching a file again
+/// only searches the content appended to it since.
+///
+/// A file is searched in full again when it has been replaced, truncated, or
+/// its last searched lines have changed. An unfinished last line is searched
+/// again once more is appended to it.
+#[derive(Default)]
+pub(crate) struct SearchedFiles(Mutex<HashMap<PathBuf, SearchedTo>>);
+
+#[derive(Clone)]
+struct SearchedTo {
+    /// Device and inode of the file searched
+    identity: Option<(u64, u64)>,
+    /// Offset of the first line not searched yet
+    offset: usize,
+    /// Number of the line at `offset`
+    line_number: usize,
+    /// The last lines searched, kept as context and to tell whether the
+    /// file was rewritten
+    tail: Vec<u8>,
+}
+
+impl SearchedFiles {
+    /// Where to start searching a file that was searched before: the offset
+    /// of its last searched lines, the offset of the first unsearched line and
+    /// that line's number. Returns `None` if the file must be searched in full.
+    fn resume(&self, file: &Path, identity: Option<(u64, u64)>, reader: &FileReader) -> Result<Option<(usize, usize, usize)>> {
+        let Some(last) = self.0.lock().unwrap().get(file).cloned() else {
+            return Ok(None);
+        };
+        let tail_start = last.offset - last.tail.len();
+        if last.identity != identity
+            || last.offset > reader.size() as usize
+            || *reader.read_chunk(tail_start, last.tail.len())? != *last.tail {
+            debug!("{} was replaced or rewritten, searching it in full", file.display());
+            return Ok(None);
+        }
+        Ok(Some((tail_start, last.offset, last.line_number)))
+    }
+    
+    /// Record that a file was searched up to the end of `text`, which starts
+    /// at `text_offset` in the file and is followed by line `line_number`
+    fn record(&self, file: &Path, identity: Option<(u64, u64)>, text: &[u8], text_offset: usize, line_number: usize, context: usize) {
+        let end = memchr::memrchr(b'\n', text).map_or(0, |pos| pos + 1);
+        let tail_start = start_of_last_lines(&text[..end], context.max(1));
+        let searched = SearchedTo {
+            identity,
+            offset: text_offset + end,
+            line_number,
+            tail: text[tail_start..end].to_vec(),
+        };
+        self.0.lock().unwrap().insert(file.to_path_buf(), searched);
+    }
+}
+
+/// Search a complete in-memory buffer
+pub(crate) fn search_input<S: Sink + ?Sized>(text: &[u8], matcher: &RegexMatcher, options: &SearchOptions, sink: &S) -> SearchResult {
+    let start = Instant::now();
+    let control = SearchControl::new(options);
+    sink.on_file_begin(None);
+    let mut searcher = LineSearcher::new(matcher, options, &control, sink, None);
+    searcher.search(text, 0, 0, 1);
+    searcher.stats.bytes_processed = text.len();
+    let stats = searcher.finish();
+    sink.on_file_end(None, &stats);
+    
+    let mut result = SearchResult::default();
+    result.add(&stats);
+    result.elapsed = start.elapsed();
+    result.search_time = result.elapsed;
+    result.truncated = control.truncated();
+    result.timed_out = control.timed_out();
+    sink.on_finish(&result);
+    result
+}
+
+/// Bytes read from a followed file at a time
+const FOLLOW_READ_SIZE: usize = 1024 * 1024; // 1MB
+
+/// Longest unfinished line held until it ends. The content of a longer line
+/// is searched as a line of its own, so the buffer of a followed file stays bounded.
+const FOLLOW_MAX_LINE: usize = FOLLOW_READ_SIZE;
+
+/// How long to wait before reading followed files again once none had new content
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Search files and keep following them as they grow, searching the lines
+/// appended to them, until the search is stopped or cancelled or every file
+/// has reached its match limit. Each batch of new lines in a file is reported
+/// between its own `on_file_begin` and `on_file_end`.
+pub(crate) fn follow_files<S: Sink + ?Sized>(paths: &[PathBuf], matcher: &RegexMatcher, options: &SearchOptions, sink: &S) -> Result<SearchResult> {
+    if let Some(dir) = paths.iter().find(|path| path.is_dir()) {
+        bail!("Cannot follow {}: it is a directory", dir.display());
+    }
+    
+    let start = Instant::now();
+    let control = SearchControl::new(options);
+    let mut files: Vec<FollowedFile<'_, S>> = paths.iter()
+        .map(|path| FollowedFile {
+            path,
+            reader: FollowReader::new(path),
+            searcher: LineSearcher::new(matcher, options, &control, sink, Some(path)),
+            buffer: Vec::new(),
+            buffer_offset: 0,
+            search_from: 0,
+            line_number: 1,
+            failing: false,
+        })
+        .collect();
+    let mut errors = Vec::new();
+    
+    // Following stops once the total limit is reached rather than waiting
+    // for a line beyond it
+    while !control.total_reached() && !files.iter_mut().all(|file| file.searcher.stopped()) {
+        let mut idle = true;
+        for file in &mut files {
+            if file.searcher.stopped() {
+                continue;
+            }
+            
+            match file.reader.read(FOLLOW_READ_SIZE) {
+                Ok(Update::Data(data)) => {
+                    file.failing = false;
+                    file.searcher.stats.add_read(data.len(), false);
+                    file.buffer.extend_from_slice(&data);
+                    file.search(false);
+                    idle = false;
+                }
+                Ok(Update::Restarted) => {
+                    debug!("{} was replaced or truncated, following it from the start", file.path.display());
+                    file.restart();
+                    idle = false;
+                }
+                Ok(Update::Unchanged) => file.failing = false,
+                // A file that cannot be read is reported once, and read again until it can be
+                Err(e) if !file.failing => {
+                    debug!("Error following file {}: {:#}", file.path.display(), e);
+                    file.failing = true;
+                    let error = SearchError::from_anyhow(file.path, &e);
+                    sink.on_error(&error);
+                    errors.push(error);
+                }
+                Err(_) => {}
+            }
+        }
         
-        let matches = matcher.match_count(&chunk);
-        total_matches += matches;
+        if idle {
+            std::thread::sleep(FOLLOW_INTERVAL);
+        } else {
+            sink.on_flush();
+        }
+    }
+    
+    let mut result = SearchResult::default();
+    for mut file in files {
+        // Search a last line that was never finished
+        file.search(true);
+        let stats = file.searcher.finish();
+        if stats.timed_out {
+            result.timed_out_files.push(file.path.clone());
+        }
+        result.add(&stats);
+    }
+    result.errors = errors;
+    result.elapsed = start.elapsed();
+    result.search_time = result.elapsed;
+    result.truncated = control.truncated();
+    result.timed_out = control.timed_out();
+    sink.on_finish(&result);
+    Ok(result)
+}
+
+/// A file being followed, with the content read from it but not yet searched
+struct FollowedFile<'a, S: Sink + ?Sized> {
+    path: &'a PathBuf,
+    reader: FollowReader,
+    searcher: LineSearcher<'a, S>,
+    /// Content from `buffer_offset` in the file: the lines kept as context
+    /// before the next match, followed by the unsearched content
+    buffer: Vec<u8>,
+    buffer_offset: usize,
+    /// Offset in `buffer` of the first unsearched byte
+    search_from: usize,
+    /// Line number of the line starting at `search_from`
+    line_number: usize,
+    /// Whether the last read of the file failed
+    failing: bool,
+}
+
+impl<S: Sink + ?Sized> FollowedFile<'_, S> {
+    /// Search the complete lines read since the last search, or everything
+    /// read if `all` is set, reporting them as one batch. An unfinished line
+    /// that reaches `FOLLOW_MAX_LINE` is searched as it is.
+    fn search(&mut self, all: bool) {
+        let unsearched = &self.buffer[self.search_from..];
+        let len = match memchr::memrchr(b'\n', unsearched) {
+            _ if all => unsearched.len(),
+            Some(pos) => pos + 1,
+            None if unsearched.len() >= FOLLOW_MAX_LINE => unsearched.len(),
+            None => 0,
+        };
+        if len == 0 {
+            return;
+        }
+        let end = self.search_from + len;
         
-        offset += chunk.len();
+        let earlier = self.searcher.stats.clone();
+        self.sink().on_file_begin(Some(self.path));
+        self.searcher.search(&self.buffer[..end], self.buffer_offset, self.search_from, self.line_number);
+        self.searcher.stats.bytes_processed += len;
+        self.sink().on_file_end(Some(self.path), &self.searcher.stats.since(&earlier));
+        
+        // Keep the last lines searched as context for the next batch. The rest
+        // of a line searched unfinished is searched on its own, without context.
+        self.line_number += memchr::memchr_iter(b'\n', &self.buffer[self.search_from..end]).count();
+        let keep_from = match self.buffer[end - 1] {
+            b'\n' => start_of_last_lines(&self.buffer[..end], self.searcher.before_context),
+            _ => end,
+        };
+        self.buffer.drain(..keep_from);
+        self.buffer_offset += keep_from;
+        self.search_from = end - keep_from;
     }
     
-    Ok(FileResult {
-        matches: total_matches,
-        bytes_processed: file_size,
-    })
+    /// Search what is left of the file's old content, then start again from
+    /// the start of the file now at its path
+    fn restart(&mut self) {
+        self.search(true);
+        self.buffer.clear();
+        self.buffer_offset = 0;
+        self.search_from = 0;
+        self.line_number = 1;
+        self.searcher.restart();
+    }
+    
+    fn sink(&self) -> &S {
+        self.searcher.sink
+    }
 }
 
-// Display matches with line numbers
-fn display_matches_with_line_numbers(file: &std::path::Path, matcher: &RegexMatcher, _args: &Args) -> Result<()> {
-    let file_str = file.to_string_lossy();
-    let file = File::open(file)?;
-    let reader = BufReader::new(file);
+/// Line-oriented search over one file, reporting matching lines and their
+/// context to a sink.
+///
+/// A file may be searched in several buffers. Each buffer must start and end on
+/// a line boundary and may begin with lines that were already searched, which
+/// are only used as context.
+struct LineSearcher<'a, S: Sink + ?Sized> {
+    matcher: &'a RegexMatcher,
+    sink: &'a S,
+    path: Option<&'a Path>,
+    /// State shared by all workers to enforce limits and stop early
+    control: &'a SearchControl,
+    quit_after_match: bool,
+    max_count: Option<usize>,
+    /// Whether the per-file match limit has been reached
+    file_done: bool,
+    /// Time after which the rest of the file is abandoned
+    deadline: Option<Instant>,
+    before_context: usize,
+    after_context: usize,
+    /// File offset up to which lines have been reported
+    reported_to: usize,
+    /// Line number of the line starting at `reported_to`
+    reported_line: usize,
+    /// Number of after-context lines still to report
+    after_remaining: usize,
+    stats: FileStats,
+}
+
+impl<'a, S: Sink + ?Sized> LineSearcher<'a, S> {
+    fn new(matcher: &'a RegexMatcher, options: &SearchOptions, control: &'a SearchControl, sink: &'a S, path: Option<&'a Path>) -> Self {
+        Self {
+            matcher,
+            sink,
+            path,
+            control,
+            quit_after_match: options.quit_after_match,
+            max_count: options.max_count,
+            file_done: false,
+            deadline: options.file_timeout.map(|timeout| Instant::now() + timeout),
+            before_context: options.before_context,
+            after_context: options.after_context,
+            reported_to: 0,
+            reported_line: 1,
+            after_remaining: 0,
+            stats: FileStats::default(),
+        }
+    }
     
-    println!("File: {}", file_str);
+    /// Start searching the file again from its start, as it was replaced or
+    /// truncated while being followed
+    fn restart(&mut self) {
+        self.reported_to = 0;
+        self.reported_line = 1;
+        self.after_remaining = 0;
+    }
     
-    for (line_num, line_result) in reader.lines().enumerate() {
-        if let Ok(line) = line_result {
-            if matcher.is_match(line.as_bytes()) {
-                println!("{}: {}", line_num + 1, line);
+    /// Search `text[search_from..]`, where `text` starts at `text_offset` in the
+    /// file and `search_line` is the line number at `search_from`
+    fn search(&mut self, text: &[u8], text_offset: usize, search_from: usize, search_line: usize) {
+        let mut line_number = search_line;
+        let mut counted_to = search_from;
+        // Matching line being built, with the end of that line within `text`
+        let mut pending: Option<(LineMatch, usize)> = None;
+        
+        for m in self.matcher.find_matches(&text[search_from..], false) {
+            let start = search_from + m.byte_offset;
+            let end = start + m.byte_length;
+            
+            // Matches on a line that is already pending are added to it
+            if let Some((line, line_end)) = &mut pending {
+                if start <= *line_end {
+                    let line_start = line.byte_offset - text_offset;
+                    line.matches.push((start - line_start, end.min(*line_end) - line_start));
+                    self.stats.matches += 1;
+                    continue;
+                }
+            }
+            
+            // A further matching line is left unreported when the search stops early
+            if self.stopped() {
+                self.truncate();
+                break;
+            }
+            if let Some((line, line_end)) = pending.take() {
+                self.report_match(line, text, text_offset, line_end);
+            }
+            
+            if !self.accept_line() {
+                self.truncate();
+                break;
             }
+            self.stats.matches += 1;
+            
+            let line_start = memchr::memrchr(b'\n', &text[..start]).map_or(0, |pos| pos + 1);
+            let search_end = start.max(end.saturating_sub(1));
+            let line_end = memchr::memchr(b'\n', &text[search_end..])
+                .map_or(text.len(), |pos| search_end + pos);
+            
+            line_number += memchr::memchr_iter(b'\n', &text[counted_to..line_start]).count();
+            counted_to = line_start;
+            
+            self.report_after_context(text, text_offset, line_start);
+            self.report_before_context(text, text_offset, line_start, line_number);
+            
+            pending = Some((LineMatch {
+                line_number,
+                byte_offset: text_offset + line_start,
+                line: String::from_utf8_lossy(&text[line_start..line_end]).into_owned(),
+                matches: vec![(start - line_start, end.min(line_end) - line_start)],
+            }, line_end));
         }
+        
+        if let Some((line, line_end)) = pending.take() {
+            self.report_match(line, text, text_offset, line_end);
+        }
+        self.report_after_context(text, text_offset, text.len());
     }
     
-    println!("");
-    Ok(())
-} 
\ No newline at end of file
+    /// Report a matching line of `text` that ends at `line_end` in it
+    fn report_match(&mut self, mut line: LineMatch, text: &[u8], text_offset: usize, line_end: usize) {
+        // Lines that are not valid UTF-8 are decoded lossily, which moves the matches
+        let raw = &text[line.byte_offset - text_offset..line_end];
+        if std::str::from_utf8(raw).is_err() {
+            let decoded_len = |end: usize| String::from_utf8_lossy(&raw[..end]).len();
+            for (start, end) in &mut line.matches {
+                (*start, *end) = (decoded_len(*start), decoded_len(*end));
+            }
+        }
+        
+        self.sink.on_match(self.path, &line);
+        self.stats.matched_lines += 1;
+        self.reported_to = text_offset + (line_end + 1).min(text.len());
+        self.reported_line = line.line_number + 1;
+        self.after_remaining = self.after_context;
+    }
+    
+    /// Report pending after-context lines that start before `limit` in `text`
+    fn report_after_context(&mut self, text: &[u8], text_offset: usize, limit: usize) {
+        while self.after_remaining > 0 && self.reported_to >= text_offset {
+            let start = self.reported_to - text_offset;
+            if start >= limit {
+                break;
+            }
+            let end = memchr::memchr(b'\n', &text[start..limit]).map_or(limit, |pos| start + pos);
+            
+            self.sink.on_context(self.path, &ContextLine {
+                line_number: self.reported_line,
+                byte_offset: self.reported_to,
+                line: String::from_utf8_lossy(&text[start..end]).into_owned(),
+                kind: ContextKind::After,
+            });
+            
+            self.reported_to = text_offset + (end + 1).min(text.len());
+            self.reported_line += 1;
+            self.after_remaining -= 1;
+        }
+    }
+    
+    /// Report the context lines before a matching line starting at `line_start` in `text`
+    fn report_before_context(&mut self, text: &[u8], text_offset: usize, line_start: usize, line_number: usize) {
+        if self.before_context == 0 && self.after_context == 0 {
+            return;
+        }
+        
+        // Never report lines that were already reported
+        let floor = self.reported_to.saturating_sub(text_offset);
+        let mut context_start = line_start;
+        let mut count = 0;
+        while count < self.before_context && context_start > floor {
+            context_start = memchr::memrchr(b'\n', &text[..context_start - 1]).map_or(0, |pos| pos + 1);
+            count += 1;
+        }
+        
+        if self.stats.matched_lines > 0 && text_offset + context_start > self.reported_to {
+            self.sink.on_context_break(self.path);
+        }
+        
+        let mut start = context_start;
+        for line_number in line_number - count..line_number {
+            let end = memchr::memchr(b'\n', &text[start..line_start]).map_or(line_start, |pos| start + pos);
+            
+            self.sink.on_context(self.path, &ContextLine {
+                line_number,
+                byte_offset: text_offset + start,
+                line: String::from_utf8_lossy(&text[start..end]).into_owned(),
+                kind: ContextKind::Before,
+            });
+            start = end + 1;
+        }
+    }
+    
+    /// Check the match limits before reporting a new matching line
+    fn accept_line(&mut self) -> bool {
+        if self.max_count.is_some_and(|max| self.stats.matched_lines >= max) {
+            self.file_done = true;
+            return false;
+        }
+        if self.stats.matched_lines == 0 && !self.control.accept_file() {
+            self.file_done = true;
+            return false;
+        }
+        if !self.control.accept_line() {
+            return false;
+        }
+        
+        if self.quit_after_match {
+            self.control.stop();
+        }
+        if self.max_count.is_some_and(|max| self.stats.matched_lines + 1 >= max) {
+            self.file_done = true;
+        }
+        true
+    }
+    
+    /// Whether searching this file should stop early. A file abandoned because
+    /// a timeout expired is marked as timed out.
+    fn stopped(&mut self) -> bool {
+        if self.file_done || (self.stats.matched_lines == 0 && !self.control.accepting_files()) {
+            return true;
+        }
+        
+        let file_expired = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if file_expired || (self.control.stopped() && self.control.timed_out()) {
+            self.stats.timed_out = true;
+            return true;
+        }
+        self.control.stopped()
+    }
+    
+    /// Record that part of the file was left unsearched because of a match limit
+    fn truncate(&self) {
+        if !self.stats.timed_out {
+            self.control.truncate();
+        }
+    }
+    
+    /// Finish searching and return the statistics for the file
+    fn finish(self) -> FileStats {
+        self.stats
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Category of a search error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorKind {
+    /// A file or directory could not be read due to its permissions
+    PermissionDenied,
+    /// A symbolic link points to a file that does not exist
+    BrokenSymlink,
+    /// A path given to search does not exist
+    NotFound,
+    /// Any other I/O error while discovering or reading files
+    Io,
+    /// The search pattern is not a valid regex
+    Regex,
+}
+
+impl ErrorKind {
+    /// Short human-readable description of the error category
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorKind::PermissionDenied => "Permission denied",
+            ErrorKind::BrokenSymlink => "Broken symlink",
+            ErrorKind::NotFound => "No such file or directory",
+            ErrorKind::Io => "I/O error",
+            ErrorKind::Regex => "Regex error",
+        }
+    }
+    
+    /// Identifier used for the error category in JSON output
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::PermissionDenied => "permission_denied",
+            ErrorKind::BrokenSymlink => "broken_symlink",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Io => "io",
+            ErrorKind::Regex => "regex",
+        }
+    }
+    
+    /// Look up an error category by its identifier
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            ErrorKind::PermissionDenied,
+            ErrorKind::BrokenSymlink,
+            ErrorKind::NotFound,
+            ErrorKind::Io,
+            ErrorKind::Regex,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+/// An error that occurred while searching, optionally tied to a path
+#[derive(Debug, Clone)]
+pub struct SearchError {
+    pub kind: ErrorKind,
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl SearchError {
+    /// Create a new error
+    pub fn new(kind: ErrorKind, path: Option<&Path>, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            path: path.map(Path::to_path_buf),
+            message: message.into(),
+        }
+    }
+    
+    /// Classify an I/O error that occurred while accessing `path`
+    pub fn from_io(path: &Path, error: &io::Error) -> Self {
+        let kind = match error.kind() {
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound if is_broken_symlink(path) => ErrorKind::BrokenSymlink,
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            _ => ErrorKind::Io,
+        };
+        Self::new(kind, Some(path), error.to_string())
+    }
+    
+    /// Classify an error from a search operation, falling back to an I/O error
+    pub fn from_anyhow(path: &Path, error: &anyhow::Error) -> Self {
+        if let Some(error) = error.downcast_ref::<SearchError>() {
+            return error.clone();
+        }
+        match error.downcast_ref::<io::Error>() {
+            Some(io_error) => Self::from_io(path, io_error),
+            None => Self::new(ErrorKind::Io, Some(path), format!("{:#}", error)),
+        }
+    }
+    
+    /// Classify an error from directory traversal
+    pub fn from_walkdir(error: &walkdir::Error) -> Self {
+        let path = error.path().unwrap_or_else(|| Path::new(""));
+        match error.io_error() {
+            Some(io_error) => Self::from_io(path, io_error),
+            // Symlink loops are the only traversal errors without an I/O error
+            None => Self::new(ErrorKind::Io, Some(path), error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// Whether the path is a symbolic link whose target does not exist
+pub(crate) fn is_broken_symlink(path: &Path) -> bool {
+    path.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()) && !path.exists()
+}
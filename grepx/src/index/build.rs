@@ -0,0 +1,695 @@
+use anyhow::{bail, Result, Context};
+use log::{debug, info};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use ring::digest::{digest, SHA256};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::query::trigram;
+use super::{stamp, write_varint, Header, Index, Section, FILE_ENTRY_LEN, FLAG_HASHES, HEADER_LEN, VERSION};
+use crate::error::SearchError;
+use crate::io::file_discovery;
+
+/// Bytes of file contents indexed in parallel before their trigrams are collected
+const BATCH_BYTES: u64 = 256 * 1024 * 1024; // 256MB
+
+/// Largest number of files indexed in parallel before their trigrams are collected
+const BATCH_FILES: usize = 4096;
+
+/// Trigram and file ID pairs held in memory before they are sorted and
+/// written to a run on disk, to be merged once every file is indexed
+const RUN_ENTRIES: usize = 32 * 1024 * 1024; // 256MB of pairs
+
+/// Builder for writing a trigram [`Index`] of a directory, or bringing one
+/// up to date
+#[derive(Debug, Clone, Default)]
+pub struct IndexBuilder {
+    threads: usize,
+    hashes: bool,
+    verify: bool,
+}
+
+/// What was indexed, and for an update what changed
+#[derive(Debug, Clone, Default)]
+pub struct IndexStats {
+    /// Number of indexed files
+    pub files: usize,
+    /// Total size of the indexed files
+    pub bytes: u64,
+    /// Number of distinct trigrams in the indexed files
+    pub trigrams: usize,
+    /// Size of the index file
+    pub index_size: u64,
+    /// Files read and indexed because the previous index did not have them
+    pub added: usize,
+    /// Files read and indexed again because they changed
+    pub modified: usize,
+    /// Files of the previous index that are gone or can no longer be indexed
+    pub removed: usize,
+    /// Files of the previous index kept without indexing them again
+    pub unchanged: usize,
+    /// Files left out because they are not valid UTF-8; searches always scan them
+    pub skipped_files: Vec<PathBuf>,
+    /// Paths that could not be read, which searches also always scan
+    pub errors: Vec<SearchError>,
+}
+
+/// A file as recorded in the index
+struct Entry {
+    /// Path relative to the root
+    path: PathBuf,
+    size: u64,
+    changed: u64,
+    hash: u64,
+}
+
+/// What the previous index recorded about a file
+#[derive(Debug, Clone, Copy)]
+struct Previous {
+    id: usize,
+    size: u64,
+    changed: u64,
+    /// Hash of its contents, if the index has hashes
+    hash: Option<u64>,
+}
+
+/// Result of looking at a file to index
+enum Scanned {
+    /// Unchanged since the previous index, whose postings for it are kept
+    Kept { previous: usize, size: u64, changed: u64, hash: u64 },
+    /// Read and split into its distinct trigrams, in increasing order
+    Read { size: u64, changed: u64, hash: u64, trigrams: Vec<u32> },
+    /// Not valid UTF-8, so it cannot be searched anyway
+    NotText,
+}
+
+impl IndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Number of threads reading and indexing files (0 = auto)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+    
+    /// Record a hash of each file's contents, so that an update only indexes
+    /// a file whose size or change time changed again if its contents did. An index keeps its hashes when it is updated.
+    pub fn hashes(mut self, hashes: bool) -> Self {
+        self.hashes = hashes;
+        self
+    }
+    
+    /// When updating, also hash the files whose size and change time are
+    /// unchanged, to catch changes that kept both. This reads every file,
+    /// and adds hashes to an index without them.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+    
+    /// Index every file under a directory, writing the index to `output`.
+    /// The index is written to a temporary file that replaces `output` once
+    /// complete, so an existing index stays usable while it is rebuilt.
+    pub fn build(&self, dir: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<IndexStats> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            bail!("Cannot index {}: not a directory", dir.display());
+        }
+        let root = std::path::absolute(dir)
+            .with_context(|| format!("Invalid directory: {}", dir.display()))?;
+        self.run(&root, output.as_ref(), None)
+    }
+    
+    /// Bring an index up to date with the files under its directory, reading
+    /// only the files that were added or changed since it was written. The
+    /// updated index replaces the old one once complete.
+    pub fn update(&self, index: impl AsRef<Path>) -> Result<IndexStats> {
+        let path = index.as_ref();
+        let previous = Index::open(path)?;
+        if !previous.root().is_dir() {
+            bail!("Cannot update {}: {} is not a directory", path.display(), previous.root().display());
+        }
+        self.run(previous.root(), path, Some(&previous))
+    }
+    
+    fn run(&self, root: &Path, output: &Path, previous: Option<&Index>) -> Result<IndexStats> {
+        let output = std::path::absolute(output)
+            .with_context(|| format!("Invalid index path: {}", output.display()))?;
+        let hashes = self.hashes || self.verify || previous.is_some_and(Index::has_hashes);
+        
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .thread_name(|i| format!("grepx-index-{}", i))
+            .build()
+            .context("Failed to create index thread pool")?;
+        pool.install(|| index_files(root, &output, previous, hashes, self.verify))
+    }
+}
+
+fn index_files(root: &Path, output: &Path, previous: Option<&Index>, hashes: bool, verify: bool) -> Result<IndexStats> {
+    let mut stats = IndexStats::default();
+    let mut files = file_discovery::find_files(&[root], true, &mut stats.errors)?;
+    files.retain(|file| file != output);
+    // Paths are looked up by binary search over their bytes
+    files.par_sort_by(|a, b| relative_bytes(root, a).cmp(&relative_bytes(root, b)));
+    let metadata = file_discovery::file_metadata(&files);
+    let previous_files: Vec<Option<Previous>> = files.par_iter()
+        .map(|path| {
+            let index = previous?;
+            let id = index.find(relative_bytes(root, path).0)?;
+            let (size, changed) = index.file_stamp(id);
+            let hash = index.has_hashes().then(|| index.file_hash(id));
+            Some(Previous { id, size, changed, hash })
+        })
+        .collect();
+    match previous {
+        Some(_) => info!("Updating index of {} files under {}", files.len(), root.display()),
+        None => info!("Indexing {} files under {}", files.len(), root.display()),
+    }
+    
+    // Files are read unless the previous index has them with the same size
+    // and change time, or hashes have to be checked or added
+    let must_read: Vec<bool> = metadata.iter()
+        .zip(&previous_files)
+        .map(|(metadata, previous)| match (metadata, previous) {
+            (Some(metadata), Some(previous)) => {
+                verify || (hashes && previous.hash.is_none()) || stamp(metadata) != (previous.size, previous.changed)
+            }
+            _ => true,
+        })
+        .collect();
+    let read_sizes: Vec<u64> = metadata.iter()
+        .zip(&must_read)
+        .map(|(metadata, &read)| match read {
+            true => metadata.as_ref().map_or(0, |m| m.len()),
+            false => 0,
+        })
+        .collect();
+    
+    let mut runs = Runs::new(output);
+    let mut entries = Vec::new();
+    // New IDs of the previous index's files that are kept, or `u32::MAX`
+    let mut remap = vec![u32::MAX; previous.map_or(0, Index::file_count)];
+    let mut start = 0;
+    while start < files.len() {
+        let mut end = start;
+        let mut batch_bytes = 0;
+        while end < files.len() && end - start < BATCH_FILES && (end == start || batch_bytes + read_sizes[end] <= BATCH_BYTES) {
+            batch_bytes += read_sizes[end];
+            end += 1;
+        }
+        
+        let results: Vec<io::Result<Scanned>> = (start..end).into_par_iter()
+            .map_init(|| vec![0u64; (1 << 24) / 64], |seen, i| match (must_read[i], previous_files[i]) {
+                (false, Some(previous)) => Ok(Scanned::Kept {
+                    previous: previous.id,
+                    size: previous.size,
+                    changed: previous.changed,
+                    hash: previous.hash.unwrap_or(0),
+                }),
+                (_, previous) => scan_file(&files[i], previous, hashes, seen),
+            })
+            .collect();
+        for (i, result) in (start..end).zip(results) {
+            let id = u32::try_from(entries.len()).context("Too many files to index")?;
+            let (size, changed, hash) = match result {
+                Ok(Scanned::Kept { previous, size, changed, hash }) => {
+                    remap[previous] = id;
+                    stats.unchanged += 1;
+                    (size, changed, hash)
+                }
+                Ok(Scanned::Read { size, changed, hash, trigrams }) => {
+                    runs.add(&trigrams, id)?;
+                    match previous_files[i] {
+                        Some(_) => stats.modified += 1,
+                        None => stats.added += 1,
+                    }
+                    (size, changed, hash)
+                }
+                Ok(Scanned::NotText) => {
+                    debug!("Not indexing file that is not valid UTF-8: {}", files[i].display());
+                    stats.skipped_files.push(files[i].clone());
+                    continue;
+                }
+                Err(e) => {
+                    stats.errors.push(SearchError::from_io(&files[i], &e));
+                    continue;
+                }
+            };
+            stats.bytes += size;
+            entries.push(Entry {
+                path: relative_bytes(root, &files[i]).0.to_path_buf(),
+                size,
+                changed,
+                hash,
+            });
+        }
+        start = end;
+    }
+    
+    stats.files = entries.len();
+    stats.removed = remap.len() - stats.unchanged - stats.modified;
+    let mut sources = Vec::new();
+    if let Some(previous) = previous {
+        sources.push(kept_postings(previous, &remap));
+    }
+    let postings = runs.merge(sources)?;
+    stats.trigrams = write_index(root, output, &entries, hashes, postings, &mut stats.index_size)?;
+    info!("Indexed {} files with {} trigrams into {} ({} bytes)",
+          stats.files, stats.trigrams, output.display(), stats.index_size);
+    Ok(stats)
+}
+
+/// Path relative to the root, wrapped so it orders by its bytes
+fn relative_bytes<'a>(root: &Path, path: &'a Path) -> RelativePath<'a> {
+    RelativePath(path.strip_prefix(root).unwrap_or(path))
+}
+
+struct RelativePath<'a>(&'a Path);
+
+impl PartialEq for RelativePath<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_os_str().as_encoded_bytes() == other.0.as_os_str().as_encoded_bytes()
+    }
+}
+
+impl Eq for RelativePath<'_> {}
+
+impl PartialOrd for RelativePath<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RelativePath<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.as_os_str().as_encoded_bytes().cmp(other.0.as_os_str().as_encoded_bytes())
+    }
+}
+
+/// Read a file, keeping the previous index's postings for it if its hash,
+/// or without hashes its size and change time, still match. `seen` is
+/// a bitset of every trigram, left cleared for the next file.
+fn scan_file(path: &Path, previous: Option<Previous>, hashes: bool, seen: &mut [u64]) -> io::Result<Scanned> {
+    let file = File::open(path)?;
+    // Read before the contents, so a file changed while it is read no longer
+    // matches what was recorded
+    let metadata = file.metadata()?;
+    let map = match metadata.len() {
+        0 => None,
+        _ => Some(unsafe { Mmap::map(&file)? }),
+    };
+    let content = map.as_deref().unwrap_or_default();
+    if std::str::from_utf8(content).is_err() {
+        return Ok(Scanned::NotText);
+    }
+    
+    let (size, changed) = stamp(&metadata);
+    let hash = match hashes {
+        true => content_hash(content),
+        false => 0,
+    };
+    if let Some(previous) = previous {
+        let unchanged = match previous.hash {
+            Some(previous_hash) => hash == previous_hash,
+            None => (size, changed) == (previous.size, previous.changed),
+        };
+        if unchanged {
+            return Ok(Scanned::Kept { previous: previous.id, size, changed, hash });
+        }
+    }
+    
+    let mut trigrams = Vec::new();
+    for window in content.windows(3) {
+        let trigram = trigram(window[0], window[1], window[2]);
+        let (word, bit) = (trigram as usize / 64, 1u64 << (trigram % 64));
+        if seen[word] & bit == 0 {
+            seen[word] |= bit;
+            trigrams.push(trigram);
+        }
+    }
+    for &trigram in &trigrams {
+        seen[trigram as usize / 64] = 0;
+    }
+    trigrams.sort_unstable();
+    
+    Ok(Scanned::Read { size, changed, hash, trigrams })
+}
+
+/// First 8 bytes of the SHA-256 digest of a file's contents
+fn content_hash(content: &[u8]) -> u64 {
+    u64::from_le_bytes(digest(&SHA256, content).as_ref()[..8].try_into().unwrap())
+}
+
+/// Trigram and file ID pairs, packed into `u64`s that sort by trigram then
+/// file, in increasing order
+type Pairs<'a> = Box<dyn Iterator<Item = io::Result<u64>> + 'a>;
+
+/// The previous index's postings of the files it kept, under their new IDs.
+/// Kept files stay in the same order, so their IDs stay in increasing order.
+fn kept_postings<'a>(index: &'a Index, remap: &'a [u32]) -> Pairs<'a> {
+    Box::new((0..index.trigram_count()).flat_map(move |i| -> Vec<io::Result<u64>> {
+        let trigram = index.trigram_at(i) as u64;
+        match index.postings_at(i) {
+            Ok(ids) => ids.into_iter()
+                .filter_map(|id| remap.get(id as usize).copied().filter(|&id| id != u32::MAX))
+                .map(|id| Ok(trigram << 32 | id as u64))
+                .collect(),
+            Err(e) => vec![Err(io::Error::other(e))],
+        }
+    }))
+}
+
+/// Pairs of the files read, spilled to sorted runs on disk when memory fills up
+struct Runs {
+    pairs: Vec<u64>,
+    /// Base path of the run files
+    base: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl Runs {
+    fn new(output: &Path) -> Self {
+        Self {
+            pairs: Vec::new(),
+            base: output.to_path_buf(),
+            files: Vec::new(),
+        }
+    }
+    
+    fn add(&mut self, trigrams: &[u32], id: u32) -> Result<()> {
+        self.pairs.extend(trigrams.iter().map(|&trigram| (trigram as u64) << 32 | id as u64));
+        if self.pairs.len() >= RUN_ENTRIES {
+            self.spill()?;
+        }
+        Ok(())
+    }
+    
+    fn spill(&mut self) -> Result<()> {
+        let path = PathBuf::from(format!("{}.run{}", self.base.display(), self.files.len()));
+        debug!("Writing {} postings to {}", self.pairs.len(), path.display());
+        self.pairs.par_sort_unstable();
+        self.files.push(path.clone());
+        
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        for pair in &self.pairs {
+            writer.write_all(&pair.to_le_bytes())?;
+        }
+        writer.flush().with_context(|| format!("Failed to write {}", path.display()))?;
+        self.pairs.clear();
+        Ok(())
+    }
+    
+    /// Every pair in increasing order, merging the runs in memory and on disk
+    /// with other sources of pairs
+    fn merge(mut self, mut sources: Vec<Pairs<'_>>) -> Result<Merge<'_>> {
+        if self.files.is_empty() {
+            let mut pairs = std::mem::take(&mut self.pairs);
+            pairs.par_sort_unstable();
+            sources.push(Box::new(pairs.into_iter().map(Ok)));
+        } else if !self.pairs.is_empty() {
+            self.spill()?;
+        }
+        for path in &self.files {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            let mut reader = BufReader::new(file);
+            sources.push(Box::new(std::iter::from_fn(move || next_pair(&mut reader).transpose())));
+        }
+        
+        let mut heap = BinaryHeap::new();
+        for (source, pairs) in sources.iter_mut().enumerate() {
+            if let Some(pair) = pairs.next().transpose()? {
+                heap.push(Reverse((pair, source)));
+            }
+        }
+        Ok(Merge { _runs: self, sources, heap })
+    }
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        for path in &self.files {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Pairs merged from several sources. No two sources have pairs for the same
+/// file, so no two pairs are equal.
+struct Merge<'a> {
+    /// Kept to remove the run files once merged
+    _runs: Runs,
+    sources: Vec<Pairs<'a>>,
+    heap: BinaryHeap<Reverse<(u64, usize)>>,
+}
+
+impl Iterator for Merge<'_> {
+    type Item = io::Result<u64>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((pair, source)) = self.heap.pop()?;
+        match self.sources[source].next() {
+            Some(Ok(next)) => self.heap.push(Reverse((next, source))),
+            Some(Err(e)) => return Some(Err(e)),
+            None => {}
+        }
+        Some(Ok(pair))
+    }
+}
+
+fn next_pair(reader: &mut BufReader<File>) -> io::Result<Option<u64>> {
+    let mut bytes = [0; 8];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => Ok(Some(u64::from_le_bytes(bytes))),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes a temporary file unless it has been renamed into place
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Write the index file, returning the number of distinct trigrams
+fn write_index(root: &Path, output: &Path, files: &[Entry], hashes: bool, postings: Merge, index_size: &mut u64) -> Result<usize> {
+    let temp = TempFile(PathBuf::from(format!("{}.tmp", output.display())));
+    let file = File::create(&temp.0)
+        .with_context(|| format!("Failed to create index: {}", temp.0.display()))?;
+    let mut writer = CountingWriter::new(BufWriter::new(file));
+    let mut header = Header {
+        version: VERSION,
+        flags: if hashes { FLAG_HASHES } else { 0 },
+        file_count: files.len() as u64,
+        ..Header::default()
+    };
+    
+    let result = (|| -> io::Result<()> {
+        writer.write_all(&[0; HEADER_LEN])?;
+        
+        header.root = writer.section(|w| w.write_all(root.as_os_str().as_encoded_bytes()))?;
+        header.paths = writer.section(|w| {
+            files.iter().try_for_each(|file| w.write_all(file.path.as_os_str().as_encoded_bytes()))
+        })?;
+        header.files = writer.section(|w| {
+            let mut offset = 0u64;
+            for file in files {
+                let mut entry = [0; FILE_ENTRY_LEN];
+                entry[..8].copy_from_slice(&offset.to_le_bytes());
+                entry[8..16].copy_from_slice(&file.size.to_le_bytes());
+                entry[16..24].copy_from_slice(&file.changed.to_le_bytes());
+                entry[24..].copy_from_slice(&file.hash.to_le_bytes());
+                w.write_all(&entry)?;
+                offset += file.path.as_os_str().as_encoded_bytes().len() as u64;
+            }
+            Ok(())
+        })?;
+        
+        let mut table = Vec::new();
+        header.postings = writer.section(|w| write_postings(w, postings, &mut table))?;
+        header.trigram_count = table.len() as u64;
+        header.trigrams = writer.section(|w| {
+            table.iter().try_for_each(|(trigram, count, offset)| {
+                w.write_all(&trigram.to_le_bytes())?;
+                w.write_all(&count.to_le_bytes())?;
+                w.write_all(&offset.to_le_bytes())
+            })
+        })?;
+        Ok(())
+    })();
+    result.with_context(|| format!("Failed to write index: {}", temp.0.display()))?;
+    
+    *index_size = writer.position;
+    let mut file = writer.inner.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header.to_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temp.0, output)
+        .with_context(|| format!("Failed to write index: {}", output.display()))?;
+    Ok(header.trigram_count as usize)
+}
+
+/// Write the posting list of each trigram, adding its trigram, file count
+/// and offset to the table
+fn write_postings<W: Write>(writer: &mut CountingWriter<W>, postings: Merge, table: &mut Vec<(u32, u32, u64)>) -> io::Result<()> {
+    let start = writer.position;
+    let mut encoded = Vec::new();
+    let mut previous = 0;
+    for pair in postings {
+        let pair = pair?;
+        let (trigram, id) = ((pair >> 32) as u32, pair as u32);
+        match table.last_mut() {
+            Some((last, count, _)) if *last == trigram => *count += 1,
+            _ => {
+                writer.write_all(&encoded)?;
+                encoded.clear();
+                table.push((trigram, 1, writer.position - start));
+                previous = 0;
+            }
+        }
+        write_varint(&mut encoded, id - previous);
+        previous = id;
+    }
+    writer.write_all(&encoded)
+}
+
+/// Writer that tracks how many bytes have been written, to record offsets
+struct CountingWriter<W: Write> {
+    inner: W,
+    position: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, position: 0 }
+    }
+    
+    /// Write a section, returning where it was written
+    fn section(&mut self, write: impl FnOnce(&mut Self) -> io::Result<()>) -> io::Result<Section> {
+        let offset = self.position;
+        write(self)?;
+        Ok(Section {
+            offset,
+            len: self.position - offset,
+        })
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+    
+    use crate::index::DEFAULT_FILE_NAME;
+    
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grepx-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    /// Names of the indexed files containing the first trigram of `text`
+    fn files_with(index: &Index, text: &str) -> Vec<String> {
+        let t = text.as_bytes();
+        index.postings(trigram(t[0], t[1], t[2])).unwrap().into_iter()
+            .map(|id| String::from_utf8_lossy(index.file_path_bytes(id as usize)).into_owned())
+            .collect()
+    }
+    
+    #[test]
+    fn updates_pick_up_modified_deleted_and_new_files() {
+        let dir = temp_dir("index-update");
+        fs::write(dir.join("b_gone.txt"), "removed soon\n").unwrap();
+        fs::write(dir.join("c_keep.txt"), "unchanged content\n").unwrap();
+        fs::write(dir.join("d_edit.txt"), "old words\n").unwrap();
+        let output = dir.join(DEFAULT_FILE_NAME);
+        IndexBuilder::new().build(&dir, &output).unwrap();
+        
+        fs::remove_file(dir.join("b_gone.txt")).unwrap();
+        fs::write(dir.join("d_edit.txt"), "brand new words\n").unwrap();
+        fs::write(dir.join("a1_new.txt"), "fresh file\n").unwrap();
+        fs::write(dir.join("a2_new.txt"), "another fresh file\n").unwrap();
+        let stats = IndexBuilder::new().update(&output).unwrap();
+        assert_eq!((stats.added, stats.modified, stats.removed, stats.unchanged), (2, 1, 1, 1));
+        assert_eq!(stats.files, 4);
+        
+        // The kept file moved to a new ID, with its postings
+        let index = Index::open(&output).unwrap();
+        assert_eq!(index.find(Path::new("b_gone.txt")), None);
+        assert_eq!(files_with(&index, "unc"), ["c_keep.txt"]);
+        assert_eq!(files_with(&index, "bra"), ["d_edit.txt"]);
+        assert_eq!(files_with(&index, "fre"), ["a1_new.txt", "a2_new.txt"]);
+        assert!(files_with(&index, "old").is_empty());
+        assert!(files_with(&index, "rem").is_empty());
+        assert_eq!(files_with(&index, "wor"), ["d_edit.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn hashes_keep_files_whose_contents_are_unchanged() {
+        let dir = temp_dir("index-hashes");
+        fs::write(dir.join("same.txt"), "same content\n").unwrap();
+        fs::write(dir.join("edit.txt"), "first text\n").unwrap();
+        let output = dir.join(DEFAULT_FILE_NAME);
+        IndexBuilder::new().hashes(true).build(&dir, &output).unwrap();
+        
+        // Rewritten with a later change time, one with the same contents
+        // and one with different contents of the same size
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("same.txt"), "same content\n").unwrap();
+        fs::write(dir.join("edit.txt"), "other text\n").unwrap();
+        let stats = IndexBuilder::new().update(&output).unwrap();
+        assert_eq!((stats.modified, stats.unchanged), (1, 1));
+        
+        let index = Index::open(&output).unwrap();
+        assert!(index.has_hashes());
+        assert_eq!(files_with(&index, "sam"), ["same.txt"]);
+        assert_eq!(files_with(&index, "oth"), ["edit.txt"]);
+        assert!(files_with(&index, "fir").is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn verify_adds_hashes_to_an_index_without_them() {
+        let dir = temp_dir("index-verify");
+        fs::write(dir.join("a.txt"), "first text\n").unwrap();
+        let output = dir.join(DEFAULT_FILE_NAME);
+        IndexBuilder::new().build(&dir, &output).unwrap();
+        assert!(!Index::open(&output).unwrap().has_hashes());
+        
+        let stats = IndexBuilder::new().verify(true).update(&output).unwrap();
+        assert_eq!((stats.modified, stats.unchanged), (0, 1));
+        let index = Index::open(&output).unwrap();
+        assert!(index.has_hashes());
+        assert_eq!(index.file_hash(0), content_hash(b"first text\n"));
+        assert_eq!(files_with(&index, "fir"), ["a.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
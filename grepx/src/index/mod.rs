@@ -0,0 +1,533 @@
+//! Persistent trigram index for repeated searches of a directory.
+//!
+//! An index lists the files under a root directory with their sizes,
+//! change times and optionally hashes of their contents, and for each trigram (three consecutive bytes, ASCII
+//! letters lowercased) the files containing it. A search using the index
+//! derives from its pattern the trigrams any match must contain and only
+//! scans the files that have them. Files that changed since the index was
+//! built, and files it does not list, are always scanned, so an index can go
+//! stale without results being missed. Updating an index re-reads only the
+//! files that were added or changed since.
+//!
+//! The index is a single file read through a memory map. All integers are
+//! little-endian:
+//!
+//! ```text
+//! header    magic "GRPXIDX\0", version u32, flags u32, file count u64,
+//!           trigram count u64, then the offset and length (u64 each) of the
+//!           root, paths, files, postings and trigrams sections
+//! root      absolute path of the indexed directory
+//! paths     paths of the indexed files relative to the root, in sorted order
+//! files     per file: path offset in the paths section u64, size u64,
+//!           change time u64, content hash u64 (0 unless the hashes flag
+//!           is set)
+//! postings  per trigram: increasing file IDs, delta-encoded as varints
+//! trigrams  per trigram, in increasing order: trigram u32, file count u32,
+//!           offset of its postings u64
+//! ```
+//!
+//! A file's change time is the later of its modification time and, on Unix,
+//! its status change time, in nanoseconds since the epoch. Unlike the
+//! modification time, the status change time cannot be set back, so a file
+//! rewritten with its old modification time is still seen to have changed.
+//! A file's ID is its position in the files section. Each path and posting
+//! list ends where the next one starts. Version 1 indexes had no flags and
+//! no hashes; they must be rebuilt.
+
+mod build;
+pub(crate) mod query;
+
+pub use build::{IndexBuilder, IndexStats};
+
+use anyhow::{bail, Result, Context};
+use memmap2::Mmap;
+use std::fs::{File, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use query::Query;
+
+/// File name of an index built inside the directory it indexes
+pub const DEFAULT_FILE_NAME: &str = ".grepx-index";
+
+const MAGIC: &[u8; 8] = b"GRPXIDX\0";
+
+/// Version of the format written and read by this build
+pub const VERSION: u32 = 2;
+
+/// Flag set when the files section holds content hashes
+const FLAG_HASHES: u32 = 1;
+
+const HEADER_LEN: usize = 112;
+const FILE_ENTRY_LEN: usize = 32;
+const TRIGRAM_ENTRY_LEN: usize = 16;
+
+/// Offset and length of a section of the index file
+#[derive(Debug, Clone, Copy, Default)]
+struct Section {
+    offset: u64,
+    len: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Header {
+    version: u32,
+    flags: u32,
+    file_count: u64,
+    trigram_count: u64,
+    root: Section,
+    paths: Section,
+    files: Section,
+    postings: Section,
+    trigrams: Section,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[..8].copy_from_slice(MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.flags.to_le_bytes());
+        let fields = [
+            self.file_count, self.trigram_count,
+            self.root.offset, self.root.len,
+            self.paths.offset, self.paths.len,
+            self.files.offset, self.files.len,
+            self.postings.offset, self.postings.len,
+            self.trigrams.offset, self.trigrams.len,
+        ];
+        for (i, field) in fields.iter().enumerate() {
+            bytes[16 + i * 8..24 + i * 8].copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+    
+    fn parse(bytes: &[u8], path: &Path) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            bail!("{} is not a grepx index", path.display());
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != VERSION {
+            bail!("{} is a version {} index, but this grepx reads version {}; rebuild it with `grepx index build`",
+                  path.display(), version, VERSION);
+        }
+        let field = |i: usize| read_u64(bytes, 16 + i * 8);
+        let section = |i: usize| Section { offset: field(i), len: field(i + 1) };
+        Ok(Self {
+            version,
+            flags: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+            file_count: field(0),
+            trigram_count: field(1),
+            root: section(2),
+            paths: section(4),
+            files: section(6),
+            postings: section(8),
+            trigrams: section(10),
+        })
+    }
+}
+
+/// A trigram index opened for searching. See the [module documentation](self)
+/// for what it holds.
+pub struct Index {
+    path: PathBuf,
+    root: PathBuf,
+    map: Mmap,
+    header: Header,
+}
+
+impl Index {
+    /// Open an index file, checking its version and that its sections are intact
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open index: {}", path.display()))?;
+        let map = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("Failed to map index: {}", path.display()))?;
+        let header = Header::parse(&map, path)?;
+        
+        let mut index = Self {
+            path: path.to_path_buf(),
+            root: PathBuf::new(),
+            map,
+            header,
+        };
+        if !index.is_intact() {
+            bail!("{} is corrupt; rebuild it with `grepx index build`", path.display());
+        }
+        index.root = path_from_bytes(index.section(header.root));
+        Ok(index)
+    }
+    
+    /// Path of the index file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Directory the index covers
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    
+    /// Number of indexed files
+    pub fn file_count(&self) -> usize {
+        self.header.file_count as usize
+    }
+    
+    /// Number of distinct trigrams in the indexed files
+    pub fn trigram_count(&self) -> usize {
+        self.header.trigram_count as usize
+    }
+    
+    /// Whether the index holds hashes of the indexed files' contents
+    pub fn has_hashes(&self) -> bool {
+        self.header.flags & FLAG_HASHES != 0
+    }
+    
+    /// Check that every section lies within the file and that the offsets in
+    /// the file and trigram tables are in order, so lookups can slice freely
+    fn is_intact(&self) -> bool {
+        let header = &self.header;
+        let sections = [header.root, header.paths, header.files, header.postings, header.trigrams];
+        let in_bounds = sections.iter()
+            .all(|s| s.offset.checked_add(s.len).is_some_and(|end| end <= self.map.len() as u64));
+        if !in_bounds
+            || Some(header.files.len) != header.file_count.checked_mul(FILE_ENTRY_LEN as u64)
+            || Some(header.trigrams.len) != header.trigram_count.checked_mul(TRIGRAM_ENTRY_LEN as u64)
+            || header.file_count > u32::MAX as u64 + 1 {
+            return false;
+        }
+        
+        let files = self.section(header.files);
+        let paths_ordered = (0..self.file_count())
+            .map(|id| read_u64(files, id * FILE_ENTRY_LEN))
+            .chain([header.paths.len])
+            .try_fold(0, |previous, offset| (offset >= previous).then_some(offset))
+            .is_some();
+        
+        let trigrams = self.section(header.trigrams);
+        let postings_ordered = (0..self.trigram_count())
+            .map(|i| read_u64(trigrams, i * TRIGRAM_ENTRY_LEN + 8))
+            .chain([header.postings.len])
+            .try_fold(0, |previous, offset| (offset >= previous).then_some(offset))
+            .is_some();
+        
+        paths_ordered && postings_ordered
+    }
+    
+    fn section(&self, section: Section) -> &[u8] {
+        &self.map[section.offset as usize..(section.offset + section.len) as usize]
+    }
+    
+    /// Path of a file relative to the root, as stored
+    fn file_path_bytes(&self, id: usize) -> &[u8] {
+        let files = self.section(self.header.files);
+        let start = read_u64(files, id * FILE_ENTRY_LEN) as usize;
+        let end = match id + 1 < self.file_count() {
+            true => read_u64(files, (id + 1) * FILE_ENTRY_LEN) as usize,
+            false => self.header.paths.len as usize,
+        };
+        &self.section(self.header.paths)[start..end]
+    }
+    
+    /// Size and change time a file had when it was indexed
+    fn file_stamp(&self, id: usize) -> (u64, u64) {
+        let files = self.section(self.header.files);
+        (read_u64(files, id * FILE_ENTRY_LEN + 8), read_u64(files, id * FILE_ENTRY_LEN + 16))
+    }
+    
+    /// Hash of a file's contents when it was indexed, or 0 without hashes
+    fn file_hash(&self, id: usize) -> u64 {
+        read_u64(self.section(self.header.files), id * FILE_ENTRY_LEN + 24)
+    }
+    
+    /// ID of the file at a path relative to the root
+    fn find(&self, relative: &Path) -> Option<usize> {
+        let target = relative.as_os_str().as_encoded_bytes();
+        let (mut low, mut high) = (0, self.file_count());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.file_path_bytes(mid).cmp(target) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+    
+    /// Entry of the trigram table
+    fn trigram_entry(&self, i: usize) -> &[u8] {
+        &self.section(self.header.trigrams)[i * TRIGRAM_ENTRY_LEN..(i + 1) * TRIGRAM_ENTRY_LEN]
+    }
+    
+    /// Trigram of an entry of the trigram table
+    fn trigram_at(&self, i: usize) -> u32 {
+        u32::from_le_bytes(self.trigram_entry(i)[..4].try_into().unwrap())
+    }
+    
+    /// IDs of the files containing a trigram, in increasing order
+    pub(crate) fn postings(&self, trigram: u32) -> Result<Vec<u32>> {
+        let (mut low, mut high) = (0, self.trigram_count());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.trigram_at(mid) < trigram {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low == self.trigram_count() || self.trigram_at(low) != trigram {
+            return Ok(Vec::new());
+        }
+        self.postings_at(low)
+    }
+    
+    /// IDs of the files containing the trigram of an entry of the trigram table
+    fn postings_at(&self, i: usize) -> Result<Vec<u32>> {
+        let entry = self.trigram_entry(i);
+        let count = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize;
+        let start = read_u64(entry, 8) as usize;
+        let end = match i + 1 < self.trigram_count() {
+            true => read_u64(self.trigram_entry(i + 1), 8) as usize,
+            false => self.header.postings.len as usize,
+        };
+        decode_postings(&self.section(self.header.postings)[start..end], count)
+            .with_context(|| format!("{} is corrupt; rebuild it with `grepx index build`", self.path.display()))
+    }
+}
+
+/// The files of an index that may match a pattern
+pub(crate) struct Candidates {
+    index: Arc<Index>,
+    /// The root as stored and with symbolic links resolved, so paths under
+    /// either are recognised
+    roots: Vec<PathBuf>,
+    /// IDs of the indexed files that may match, or `None` if any may
+    files: Option<Vec<u32>>,
+}
+
+impl Candidates {
+    pub fn new(index: Arc<Index>, pattern: &str, case_insensitive: bool) -> Result<Self> {
+        let query = Query::from_pattern(pattern, case_insensitive)?;
+        let files = query.evaluate(&index)?;
+        let mut roots = vec![index.root().to_path_buf()];
+        roots.extend(index.root().canonicalize().ok().filter(|root| root != index.root()));
+        Ok(Self {
+            index,
+            roots,
+            files,
+        })
+    }
+    
+    /// Whether a discovered file must be searched: every file unless the
+    /// index rules it out and it has not changed since it was indexed
+    pub fn may_match(&self, path: &Path) -> bool {
+        let Some(files) = &self.files else {
+            return true;
+        };
+        let Ok(absolute) = std::path::absolute(path) else {
+            return true;
+        };
+        let Some(id) = self.roots.iter()
+            .find_map(|root| absolute.strip_prefix(root).ok())
+            .and_then(|relative| self.index.find(relative)) else {
+            return true;
+        };
+        if files.binary_search(&(id as u32)).is_ok() {
+            return true;
+        }
+        
+        match std::fs::metadata(path) {
+            Ok(metadata) => stamp(&metadata) != self.index.file_stamp(id),
+            Err(_) => true,
+        }
+    }
+    
+    /// Whether the index may rule out any files at all
+    pub fn is_selective(&self) -> bool {
+        self.files.is_some()
+    }
+}
+
+/// Size and change time of a file, compared with what the index recorded to
+/// tell whether it changed since
+fn stamp(metadata: &Metadata) -> (u64, u64) {
+    let modified = metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_nanos() as u64);
+    (metadata.len(), modified.max(status_changed_nanos(metadata)))
+}
+
+#[cfg(unix)]
+fn status_changed_nanos(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.ctime().max(0) as u64).saturating_mul(1_000_000_000).saturating_add(metadata.ctime_nsec().max(0) as u64)
+}
+
+#[cfg(not(unix))]
+fn status_changed_nanos(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// Whether a file name is that of an index or of a build's temporary files,
+/// which are never searched or indexed
+pub(crate) fn is_index_file(name: &std::ffi::OsStr) -> bool {
+    name.as_encoded_bytes().starts_with(DEFAULT_FILE_NAME.as_bytes())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Append a varint, seven bits per byte with the high bit set on all but the last
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decode a list of `count` delta-encoded file IDs
+fn decode_postings(mut bytes: &[u8], count: usize) -> Result<Vec<u32>> {
+    let mut files = Vec::with_capacity(count);
+    let mut previous = 0u32;
+    for i in 0..count {
+        let mut delta = 0u32;
+        let mut shift = 0;
+        loop {
+            let Some((&byte, rest)) = bytes.split_first() else {
+                bail!("Posting list ends early");
+            };
+            bytes = rest;
+            if shift > 28 {
+                bail!("Posting list has an invalid varint");
+            }
+            delta |= ((byte & 0x7f) as u32) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        previous = match i {
+            0 => delta,
+            _ => previous.checked_add(delta).context("Posting list overflows")?,
+        };
+        files.push(previous);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    
+    use query::trigram;
+    
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grepx-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn built_indexes_read_back() {
+        let dir = temp_dir("index-format");
+        fs::write(dir.join("a.txt"), "Hello world\n").unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/b.txt"), "goodbye\n").unwrap();
+        fs::write(dir.join("c.bin"), b"\xff\xfe hello").unwrap();
+        let output = dir.join(DEFAULT_FILE_NAME);
+        let stats = IndexBuilder::new().hashes(true).build(&dir, &output).unwrap();
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.skipped_files, [dir.join("c.bin")]);
+        
+        let index = Index::open(&output).unwrap();
+        assert_eq!(index.root(), dir);
+        assert_eq!(index.file_count(), 2);
+        assert_eq!(index.trigram_count(), stats.trigrams);
+        assert!(index.has_hashes());
+        assert_eq!(index.find(Path::new("c.bin")), None);
+        
+        for (name, text) in [("a.txt", "Hello world\n"), ("sub/b.txt", "goodbye\n")] {
+            let id = index.find(Path::new(name)).unwrap();
+            assert_eq!(index.file_path_bytes(id), name.as_bytes());
+            assert_eq!(index.file_stamp(id), stamp(&fs::metadata(dir.join(name)).unwrap()));
+            assert_ne!(index.file_hash(id), 0);
+            for w in text.as_bytes().windows(3) {
+                assert!(index.postings(trigram(w[0], w[1], w[2])).unwrap().contains(&(id as u32)));
+            }
+        }
+        assert_eq!(index.postings(trigram(b'h', b'e', b'l')).unwrap(), [0]);
+        assert!(index.postings(trigram(b'x', b'y', b'z')).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn damaged_indexes_are_rejected() {
+        let dir = temp_dir("index-damaged");
+        fs::write(dir.join("a.txt"), "some text to index\n").unwrap();
+        let output = dir.join(DEFAULT_FILE_NAME);
+        IndexBuilder::new().build(&dir, &output).unwrap();
+        let bytes = fs::read(&output).unwrap();
+        
+        let damaged = dir.join("damaged");
+        let mut old_version = bytes.clone();
+        old_version[8..12].copy_from_slice(&1u32.to_le_bytes());
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        for contents in [&bytes[..bytes.len() - 1], &bytes[..HEADER_LEN - 1], &old_version, &bad_magic] {
+            fs::write(&damaged, contents).unwrap();
+            assert!(Index::open(&damaged).is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn postings_round_trip_through_varints() {
+        let ids = [0u32, 1, 127, 128, 300, 16_384, u32::MAX];
+        let mut bytes = Vec::new();
+        let mut previous = 0;
+        for id in ids {
+            write_varint(&mut bytes, id - previous);
+            previous = id;
+        }
+        assert_eq!(decode_postings(&bytes, ids.len()).unwrap(), ids);
+        assert!(decode_postings(&bytes[..bytes.len() - 1], ids.len()).is_err());
+    }
+    
+    #[test]
+    fn candidates_rule_out_only_unchanged_files() {
+        let dir = temp_dir("index-candidates");
+        fs::write(dir.join("a.txt"), "hello\n").unwrap();
+        fs::write(dir.join("b.txt"), "world\n").unwrap();
+        let output = dir.join(DEFAULT_FILE_NAME);
+        IndexBuilder::new().build(&dir, &output).unwrap();
+        
+        let candidates = Candidates::new(Arc::new(Index::open(&output).unwrap()), "hello", false).unwrap();
+        assert!(candidates.is_selective());
+        assert!(candidates.may_match(&dir.join("a.txt")));
+        assert!(!candidates.may_match(&dir.join("b.txt")));
+        
+        fs::write(dir.join("b.txt"), "hello again\n").unwrap();
+        fs::write(dir.join("c.txt"), "hello\n").unwrap();
+        assert!(candidates.may_match(&dir.join("b.txt")));
+        assert!(candidates.may_match(&dir.join("c.txt")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,433 @@
+use anyhow::{Result, Context};
+use regex_syntax::hir::{Class, Hir, HirKind};
+use regex_syntax::ParserBuilder;
+use std::collections::BTreeSet;
+
+use super::Index;
+
+/// Largest set of exact strings tracked for part of a pattern before only
+/// its prefixes and suffixes are kept
+const MAX_EXACT: usize = 64;
+
+/// Largest set of prefixes or suffixes tracked for part of a pattern before
+/// they are shortened
+const MAX_AFFIXES: usize = 32;
+
+/// Largest character class expanded into its characters
+const MAX_CLASS: u32 = 16;
+
+/// Trigrams a file must contain to possibly match a pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Query {
+    /// Any file may match
+    All,
+    /// No file can match
+    None,
+    Trigram(u32),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+impl Query {
+    /// Derive the trigram query of a regex pattern, parsed as the matcher
+    /// parses it. Trigrams are of ASCII-lowercased text, as in the index, so
+    /// the query holds whatever the case mode.
+    pub fn from_pattern(pattern: &str, case_insensitive: bool) -> Result<Self> {
+        let hir = ParserBuilder::new()
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .dot_matches_new_line(false)
+            .build()
+            .parse(pattern)
+            .with_context(|| format!("Failed to parse regex pattern: {}", pattern))?;
+        Ok(analyze(&hir).into_query())
+    }
+    
+    fn and(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::All, query) | (query, Query::All) => query,
+            (Query::None, _) | (_, Query::None) => Query::None,
+            (Query::And(mut left), Query::And(right)) => {
+                left.extend(right);
+                Query::And(left)
+            }
+            (Query::And(mut queries), query) | (query, Query::And(mut queries)) => {
+                queries.push(query);
+                Query::And(queries)
+            }
+            (left, right) => Query::And(vec![left, right]),
+        }
+    }
+    
+    fn or(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::None, query) | (query, Query::None) => query,
+            (Query::All, _) | (_, Query::All) => Query::All,
+            (Query::Or(mut left), Query::Or(right)) => {
+                left.extend(right);
+                Query::Or(left)
+            }
+            (Query::Or(mut queries), query) | (query, Query::Or(mut queries)) => {
+                queries.push(query);
+                Query::Or(queries)
+            }
+            (left, right) => Query::Or(vec![left, right]),
+        }
+    }
+    
+    /// IDs of the indexed files that may match, in increasing order, or
+    /// `None` if any file may
+    pub fn evaluate(&self, index: &Index) -> Result<Option<Vec<u32>>> {
+        match self {
+            Query::All => Ok(None),
+            Query::None => Ok(Some(Vec::new())),
+            Query::Trigram(trigram) => index.postings(*trigram).map(Some),
+            Query::And(queries) => {
+                let mut result: Option<Vec<u32>> = None;
+                for query in queries {
+                    if let Some(files) = query.evaluate(index)? {
+                        let files = match result {
+                            Some(result) => intersect(&result, &files),
+                            None => files,
+                        };
+                        if files.is_empty() {
+                            return Ok(Some(files));
+                        }
+                        result = Some(files);
+                    }
+                }
+                Ok(result)
+            }
+            Query::Or(queries) => {
+                let mut result = Vec::new();
+                for query in queries {
+                    match query.evaluate(index)? {
+                        Some(files) => result = union(&result, &files),
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(result))
+            }
+        }
+    }
+}
+
+/// Pack three bytes into a trigram, lowercasing ASCII letters
+pub(crate) fn trigram(a: u8, b: u8, c: u8) -> u32 {
+    (a.to_ascii_lowercase() as u32) << 16 | (b.to_ascii_lowercase() as u32) << 8 | c.to_ascii_lowercase() as u32
+}
+
+/// What is known about the text matched by part of a pattern
+struct Info {
+    /// Every string it can match, if there are few enough to list
+    exact: Option<BTreeSet<Vec<u8>>>,
+    /// Strings every match starts with, when not exact
+    prefix: BTreeSet<Vec<u8>>,
+    /// Strings every match ends with, when not exact
+    suffix: BTreeSet<Vec<u8>>,
+    /// Trigrams every match contains, besides those of the sets above
+    query: Query,
+}
+
+impl Info {
+    fn exact(strings: BTreeSet<Vec<u8>>) -> Self {
+        Self {
+            exact: Some(strings),
+            prefix: BTreeSet::new(),
+            suffix: BTreeSet::new(),
+            query: Query::All,
+        }
+    }
+    
+    fn empty_string() -> Self {
+        Self::exact(BTreeSet::from([Vec::new()]))
+    }
+    
+    /// Anything at all
+    fn any() -> Self {
+        Self {
+            exact: None,
+            prefix: BTreeSet::from([Vec::new()]),
+            suffix: BTreeSet::from([Vec::new()]),
+            query: Query::All,
+        }
+    }
+    
+    /// Give up the exact strings, keeping them as prefixes and suffixes
+    fn inexact(mut self) -> Self {
+        if let Some(exact) = self.exact.take() {
+            self.query = self.query.and(any_of(&exact));
+            self.prefix = exact.clone();
+            self.suffix = exact;
+        }
+        self
+    }
+    
+    fn prefixes(&self) -> &BTreeSet<Vec<u8>> {
+        self.exact.as_ref().unwrap_or(&self.prefix)
+    }
+    
+    fn suffixes(&self) -> &BTreeSet<Vec<u8>> {
+        self.exact.as_ref().unwrap_or(&self.suffix)
+    }
+    
+    /// Keep the prefix and suffix sets small, moving what they imply into
+    /// the query before shortening them
+    fn limit(mut self) -> Self {
+        if self.prefix.len() > MAX_AFFIXES {
+            self.query = self.query.and(any_of(&self.prefix));
+            self.prefix = self.prefix.iter().map(|s| s[..s.len().min(2)].to_vec()).collect();
+        }
+        if self.suffix.len() > MAX_AFFIXES {
+            self.query = self.query.and(any_of(&self.suffix));
+            self.suffix = self.suffix.iter().map(|s| s[s.len().saturating_sub(2)..].to_vec()).collect();
+        }
+        self
+    }
+    
+    fn into_query(self) -> Query {
+        let info = self.inexact();
+        info.query.and(any_of(&info.prefix)).and(any_of(&info.suffix))
+    }
+}
+
+fn analyze(hir: &Hir) -> Info {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Info::empty_string(),
+        HirKind::Literal(literal) => Info::exact(BTreeSet::from([lowercase(&literal.0)])),
+        HirKind::Class(class) => match class_strings(class) {
+            Some(strings) => Info::exact(strings),
+            None => Info::any(),
+        },
+        HirKind::Capture(capture) => analyze(&capture.sub),
+        HirKind::Repetition(repetition) => {
+            let sub = analyze(&repetition.sub);
+            match (repetition.min, repetition.max) {
+                (0, Some(1)) => alternate(sub, Info::empty_string()),
+                (0, _) => Info::any(),
+                (min, Some(max)) if min == max && min <= 3 => {
+                    (1..min).fold(analyze(&repetition.sub), |info, _| concat(info, analyze(&repetition.sub)))
+                }
+                // Matches of several copies start with the first and end
+                // with the last, and contain every trigram of one copy
+                _ => sub.inexact(),
+            }
+        }
+        HirKind::Concat(subs) => subs.iter().fold(Info::empty_string(), |info, sub| concat(info, analyze(sub))),
+        HirKind::Alternation(subs) => {
+            let mut subs = subs.iter().map(analyze);
+            let first = subs.next().unwrap_or_else(Info::any);
+            subs.fold(first, alternate)
+        }
+    }
+}
+
+fn concat(left: Info, right: Info) -> Info {
+    if let (Some(a), Some(b)) = (&left.exact, &right.exact) {
+        if a.len() * b.len() <= MAX_EXACT {
+            return Info::exact(cross(a, b));
+        }
+    }
+    
+    // Trigrams spanning the boundary are known when the strings on either
+    // side of it are
+    let boundary = match left.suffixes().len() * right.prefixes().len() <= MAX_EXACT {
+        true => any_of(&cross(left.suffixes(), right.prefixes())),
+        false => Query::All,
+    };
+    let prefix = match &left.exact {
+        Some(exact) if exact.len() * right.prefixes().len() <= MAX_EXACT => cross(exact, right.prefixes()),
+        _ => left.prefixes().clone(),
+    };
+    let suffix = match &right.exact {
+        Some(exact) if left.suffixes().len() * exact.len() <= MAX_EXACT => cross(left.suffixes(), exact),
+        _ => right.suffixes().clone(),
+    };
+    let (left, right) = (left.inexact(), right.inexact());
+    
+    Info {
+        exact: None,
+        prefix,
+        suffix,
+        query: left.query.and(right.query).and(boundary),
+    }
+    .limit()
+}
+
+fn alternate(left: Info, right: Info) -> Info {
+    if let (Some(a), Some(b)) = (&left.exact, &right.exact) {
+        if a.len() + b.len() <= MAX_EXACT {
+            return Info::exact(a.union(b).cloned().collect());
+        }
+    }
+    
+    let (left, right) = (left.inexact(), right.inexact());
+    Info {
+        exact: None,
+        prefix: left.prefix.union(&right.prefix).cloned().collect(),
+        suffix: left.suffix.union(&right.suffix).cloned().collect(),
+        query: left.query.or(right.query),
+    }
+    .limit()
+}
+
+/// The strings a small character class matches
+fn class_strings(class: &Class) -> Option<BTreeSet<Vec<u8>>> {
+    match class {
+        Class::Unicode(class) => {
+            let size: u32 = class.ranges().iter().map(|range| range.end() as u32 - range.start() as u32 + 1).sum();
+            if size > MAX_CLASS {
+                return None;
+            }
+            Some(class.ranges().iter()
+                .flat_map(|range| range.start()..=range.end())
+                .map(|c| lowercase(c.encode_utf8(&mut [0; 4]).as_bytes()))
+                .collect())
+        }
+        Class::Bytes(class) => {
+            let size: u32 = class.ranges().iter().map(|range| range.end() as u32 - range.start() as u32 + 1).sum();
+            if size > MAX_CLASS {
+                return None;
+            }
+            Some(class.ranges().iter()
+                .flat_map(|range| range.start()..=range.end())
+                .map(|byte| vec![byte.to_ascii_lowercase()])
+                .collect())
+        }
+    }
+}
+
+/// Query matching files that contain every trigram of at least one of the strings
+fn any_of(strings: &BTreeSet<Vec<u8>>) -> Query {
+    strings.iter().fold(Query::None, |query, string| query.or(all_trigrams(string)))
+}
+
+fn all_trigrams(string: &[u8]) -> Query {
+    let trigrams: BTreeSet<u32> = string.windows(3)
+        .map(|w| trigram(w[0], w[1], w[2]))
+        .collect();
+    trigrams.into_iter().fold(Query::All, |query, trigram| query.and(Query::Trigram(trigram)))
+}
+
+fn cross(left: &BTreeSet<Vec<u8>>, right: &BTreeSet<Vec<u8>>) -> BTreeSet<Vec<u8>> {
+    left.iter()
+        .flat_map(|a| right.iter().map(move |b| [a.as_slice(), b.as_slice()].concat()))
+        .collect()
+}
+
+fn lowercase(bytes: &[u8]) -> Vec<u8> {
+    bytes.to_ascii_lowercase()
+}
+
+/// Intersection of two increasing lists
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().min(b.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                result.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Union of two increasing lists
+fn union(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => {
+                result.push(a[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                result.push(b[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                result.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    result.extend_from_slice(&a[i..]);
+    result.extend_from_slice(&b[j..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::bytes::RegexBuilder;
+    
+    /// Whether text with these trigrams has every trigram the query requires
+    fn satisfied(query: &Query, trigrams: &BTreeSet<u32>) -> bool {
+        match query {
+            Query::All => true,
+            Query::None => false,
+            Query::Trigram(trigram) => trigrams.contains(trigram),
+            Query::And(queries) => queries.iter().all(|query| satisfied(query, trigrams)),
+            Query::Or(queries) => queries.iter().any(|query| satisfied(query, trigrams)),
+        }
+    }
+    
+    fn text_trigrams(text: &str) -> BTreeSet<u32> {
+        text.as_bytes().windows(3).map(|w| trigram(w[0], w[1], w[2])).collect()
+    }
+    
+    #[test]
+    fn queries_never_rule_out_a_match() {
+        let texts = [
+            "say hello", "HeLLo there", "foo", "barbaz", "abef", "cdef", "adef", "x5yz", "ayz", "xxyz", "xxxxyz",
+            "\u{212A}ELVIN", "kelvin", "ab", "a", "abc", "singing", "color", "colour", "nanabatman",
+            "R\u{c9}SUM\u{c9}", "résumé", "unrelated text", "",
+        ];
+        let patterns = [
+            ("hello", false), ("HeLLo", true), ("(?i)kelvin", false), ("R\u{e9}sum\u{e9}", true),
+            ("foo|barbaz", false), ("(ab|cd)ef", false), ("[abc]def", false), ("x[0-9]yz", false),
+            ("[^x]yz", false), ("x{2,}yz", false), ("colou?r", false), ("(na){2}batman", false),
+            ("ab", false), ("a", false), ("a.c", false), (r"\w+ing", false), ("^abc$", false),
+        ];
+        for (pattern, case_insensitive) in patterns {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(case_insensitive)
+                .multi_line(true)
+                .build()
+                .unwrap();
+            let query = Query::from_pattern(pattern, case_insensitive).unwrap();
+            let mut matched = 0;
+            for text in texts.iter().filter(|text| regex.is_match(text.as_bytes())) {
+                assert!(satisfied(&query, &text_trigrams(text)), "{:?} matches {:?}, but {:?} rules it out", pattern, text, query);
+                matched += 1;
+            }
+            assert!(matched > 0, "no text matches {:?}", pattern);
+        }
+    }
+    
+    #[test]
+    fn literals_require_their_trigrams() {
+        let query = Query::from_pattern("Hello", false).unwrap();
+        assert!(satisfied(&query, &text_trigrams("hello")));
+        assert!(!satisfied(&query, &text_trigrams("help")));
+        
+        // Patterns with no trigram that every match contains rule nothing out
+        for pattern in ["ab", "a.c", r"\w+", "abc|x"] {
+            assert_eq!(Query::from_pattern(pattern, false).unwrap(), Query::All, "{}", pattern);
+        }
+    }
+    
+    #[test]
+    fn sorted_lists_are_combined() {
+        assert_eq!(intersect(&[1, 3, 5, 7], &[2, 3, 7, 9]), [3, 7]);
+        assert_eq!(union(&[1, 3, 5], &[2, 3, 9]), [1, 2, 3, 5, 9]);
+        assert!(intersect(&[1, 2], &[]).is_empty());
+    }
+}
@@ -1,69 +1,179 @@
 use anyhow::Result;
-use log::{debug, warn};
+use log::debug;
+use rayon::prelude::*;
+use std::fs::Metadata;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
-/// Find files to search based on provided paths and recursion options
-pub fn find_files(paths: &[String], recursive: bool) -> Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
+use crate::error::{is_broken_symlink, ErrorKind, SearchError};
+use crate::index::is_index_file;
+
+/// Order in which discovered files are searched and reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortBy {
+    /// Keep the order in which files were discovered
+    #[default]
+    Discovery,
+    /// Sort by file path
+    Path,
+    /// Sort by last modification time, oldest first
+    Modified,
+    /// Sort by file size, smallest first
+    Size,
+}
+
+/// Find files to search based on provided paths and recursion options.
+///
+/// The given paths are traversed in parallel on the current rayon pool, and
+/// their files are returned in the order the paths were given. Paths that
+/// cannot be accessed are recorded in `errors` and skipped.
+pub fn find_files<P: AsRef<Path> + Sync>(paths: &[P], recursive: bool, errors: &mut Vec<SearchError>) -> Result<Vec<PathBuf>> {
+    let found: Vec<(Vec<PathBuf>, Vec<SearchError>)> = paths.par_iter()
+        .map(|path| find_files_in(path.as_ref(), recursive))
+        .collect();
     
-    for path in paths {
-        let path = Path::new(path);
-        
-        if !path.exists() {
-            warn!("Path does not exist: {}", path.display());
-            continue;
-        }
-        
-        if path.is_file() {
-            debug!("Adding file: {}", path.display());
-            files.push(path.to_path_buf());
-        } else if path.is_dir() {
-            if recursive {
-                // Recursively traverse directory
-                debug!("Recursively traversing directory: {}", path.display());
-                add_files_recursive(path, &mut files)?;
-            } else {
-                // Add only top-level files
-                debug!("Adding top-level files from directory: {}", path.display());
-                add_files_nonrecursive(path, &mut files)?;
-            }
-        }
+    let mut files = Vec::new();
+    for (path_files, path_errors) in found {
+        files.extend(path_files);
+        errors.extend(path_errors);
     }
     
     debug!("Found {} files to search", files.len());
     Ok(files)
 }
 
+/// Find the files to search for a single path
+fn find_files_in(path: &Path, recursive: bool) -> (Vec<PathBuf>, Vec<SearchError>) {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    
+    if let Err(e) = path.metadata() {
+        debug!("Cannot access path {}: {}", path.display(), e);
+        errors.push(SearchError::from_io(path, &e));
+    } else if path.is_file() {
+        debug!("Adding file: {}", path.display());
+        files.push(path.to_path_buf());
+    } else if path.is_dir() {
+        if recursive {
+            // Recursively traverse directory
+            debug!("Recursively traversing directory: {}", path.display());
+            add_files_recursive(path, &mut files, &mut errors);
+        } else {
+            // Add only top-level files
+            debug!("Adding top-level files from directory: {}", path.display());
+            add_files_nonrecursive(path, &mut files, &mut errors);
+        }
+    }
+    
+    (files, errors)
+}
+
+/// Remove files larger than `max_filesize` bytes, returning the removed files.
+/// File sizes are read in parallel on the current rayon pool.
+pub fn remove_large_files(files: &mut Vec<PathBuf>, max_filesize: u64) -> Vec<PathBuf> {
+    let too_large: Vec<bool> = files.par_iter()
+        .map(|path| std::fs::metadata(path).is_ok_and(|m| m.len() > max_filesize))
+        .collect();
+    
+    let (skipped, kept) = std::mem::take(files)
+        .into_iter()
+        .zip(too_large)
+        .partition::<Vec<_>, _>(|(_, too_large)| *too_large);
+    *files = kept.into_iter().map(|(path, _)| path).collect();
+    
+    skipped.into_iter()
+        .map(|(path, _)| {
+            debug!("Skipping file larger than {} bytes: {}", max_filesize, path.display());
+            path
+        })
+        .collect()
+}
+
+/// Read the metadata of files in parallel on the current rayon pool, or
+/// `None` for files whose metadata cannot be read
+pub fn file_metadata(files: &[PathBuf]) -> Vec<Option<Metadata>> {
+    files.par_iter()
+        .map(|path| std::fs::metadata(path).ok())
+        .collect()
+}
+
+/// Sort discovered files in place. Metadata needed for sorting is read in
+/// parallel on the current rayon pool.
+pub fn sort_files(files: &mut Vec<PathBuf>, sort_by: SortBy) {
+    match sort_by {
+        SortBy::Discovery => {}
+        SortBy::Path => files.par_sort(),
+        SortBy::Modified => {
+            sort_by_metadata(files, |path| {
+                std::fs::metadata(path).and_then(|m| m.modified()).ok()
+            });
+        }
+        SortBy::Size => {
+            sort_by_metadata(files, |path| {
+                std::fs::metadata(path).map(|m| m.len()).ok()
+            });
+        }
+    }
+}
+
+/// Sort files by a key read from the file system, reading keys in parallel
+fn sort_by_metadata<K, F>(files: &mut Vec<PathBuf>, key: F)
+where
+    K: Ord + Send,
+    F: Fn(&Path) -> K + Sync,
+{
+    let mut keyed: Vec<(K, PathBuf)> = std::mem::take(files)
+        .into_par_iter()
+        .map(|path| (key(&path), path))
+        .collect();
+    keyed.par_sort_by(|(a, _), (b, _)| a.cmp(b));
+    *files = keyed.into_iter().map(|(_, path)| path).collect();
+}
+
 /// Add files recursively from a directory
-fn add_files_recursive(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+fn add_files_recursive(dir: &Path, files: &mut Vec<PathBuf>, errors: &mut Vec<SearchError>) {
     for entry in WalkDir::new(dir)
         .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok()) {
+        .sort_by_file_name() {
         
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("Error traversing {}: {}", dir.display(), e);
+                errors.push(SearchError::from_walkdir(&e));
+                continue;
+            }
+        };
         let path = entry.path();
         
-        if path.is_file() {
+        if path.is_file() && !is_index_file(entry.file_name()) {
             debug!("Adding file: {}", path.display());
             files.push(path.to_path_buf());
         }
     }
-    
-    Ok(())
 }
 
 /// Add only top-level files from a directory (non-recursive)
-fn add_files_nonrecursive(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
+fn add_files_nonrecursive(dir: &Path, files: &mut Vec<PathBuf>, errors: &mut Vec<SearchError>) {
+    let entries = std::fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>());
+    let mut entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            errors.push(SearchError::from_io(dir, &e));
+            return;
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+    
+    for entry in entries {
         let path = entry.path();
         
-        if path.is_file() {
+        if path.is_file() && !is_index_file(&entry.file_name()) {
             debug!("Adding file: {}", path.display());
             files.push(path);
+        } else if is_broken_symlink(&path) {
+            errors.push(SearchError::new(ErrorKind::BrokenSymlink, Some(&path), "target does not exist"));
         }
     }
-    
-    Ok(())
-} 
\ No newline at end of file
+}
\ No newline at end of file
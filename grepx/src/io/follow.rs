@@ -0,0 +1,160 @@
+//! Reading files that are being appended to, such as logs, as they grow
+
+use anyhow::Result;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use super::reader::FileReader;
+use super::strategy::ReadStrategy;
+
+/// What reading a followed file found
+pub(crate) enum Update {
+    /// Bytes appended to the file since it was last read
+    Data(Vec<u8>),
+    /// The file was replaced or truncated, and is read again from its start
+    Restarted,
+    /// Nothing has changed
+    Unchanged,
+}
+
+/// Bytes kept from the end of what was read, to notice a file truncated and
+/// written past the offset again between two reads
+const TAIL_LEN: usize = 256;
+
+/// Reader for a file that is followed as it grows, like `tail -F`.
+///
+/// The file is kept open at the offset read up to. When the path is renamed
+/// away and a new file takes its place, the rest of the old file is read
+/// before following the new one from its start. A file that shrinks, or whose
+/// last bytes read have changed, was truncated and is read again from its start.
+pub(crate) struct FollowReader {
+    path: PathBuf,
+    reader: Option<FileReader>,
+    /// Device and inode of the open file, where the platform has them
+    identity: Option<(u64, u64)>,
+    /// Offset up to which the open file has been read
+    offset: u64,
+    /// Size of the open file when it was last read
+    size: u64,
+    /// The last bytes read, ending at `offset`
+    tail: Vec<u8>,
+}
+
+impl FollowReader {
+    /// Follow the file at the given path, which is opened on the first read
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            reader: None,
+            identity: None,
+            offset: 0,
+            size: 0,
+            tail: Vec::new(),
+        }
+    }
+    
+    /// Read up to `limit` bytes appended to the file since the last read
+    pub fn read(&mut self, limit: usize) -> Result<Update> {
+        // A file that could not be opened yet is read from its start once it can be
+        let reader = match self.reader.take() {
+            Some(reader) => reader,
+            None => self.open()?,
+        };
+        let reader = self.reader.insert(reader);
+        
+        let size = reader.refresh()?;
+        let tail_start = self.offset as usize - self.tail.len();
+        let truncated = size < self.size || *reader.read_chunk(tail_start, self.tail.len())? != *self.tail;
+        self.size = size;
+        if truncated {
+            self.offset = 0;
+            self.tail.clear();
+            return Ok(Update::Restarted);
+        }
+        
+        if size > self.offset {
+            let len = ((size - self.offset) as usize).min(limit);
+            let data = reader.read_chunk(self.offset as usize, len)?.into_owned();
+            self.offset += data.len() as u64;
+            let kept = self.tail.len().min(TAIL_LEN.saturating_sub(data.len()));
+            self.tail.drain(..self.tail.len() - kept);
+            self.tail.extend_from_slice(&data[data.len().saturating_sub(TAIL_LEN)..]);
+            return Ok(Update::Data(data));
+        }
+        
+        // At the end of the open file, check whether the path now names another file
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return Ok(Update::Unchanged);
+        };
+        if identity(&metadata) != self.identity {
+            self.reader = Some(self.open()?);
+            return Ok(Update::Restarted);
+        }
+        Ok(Update::Unchanged)
+    }
+    
+    /// Open the file now at the path, to be read from its start
+    fn open(&mut self) -> Result<FileReader> {
+        let reader = FileReader::open(&self.path, ReadStrategy::Buffered)?;
+        self.identity = reader.metadata().ok().and_then(|metadata| identity(&metadata));
+        self.offset = 0;
+        self.size = 0;
+        self.tail.clear();
+        Ok(reader)
+    }
+}
+
+/// Device and inode of a file, which identify it whatever its path
+#[cfg(unix)]
+pub(crate) fn identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn read_all(reader: &mut FollowReader) -> Vec<u8> {
+        match reader.read(1024).unwrap() {
+            Update::Data(data) => data,
+            Update::Restarted => panic!("unexpected restart"),
+            Update::Unchanged => Vec::new(),
+        }
+    }
+    
+    #[test]
+    fn files_truncated_and_regrown_are_read_from_their_start() {
+        let path = std::env::temp_dir().join(format!("grepx-follow-regrown-{}", std::process::id()));
+        std::fs::write(&path, "first line\nsecond line\n").unwrap();
+        let mut reader = FollowReader::new(&path);
+        assert_eq!(read_all(&mut reader), b"first line\nsecond line\n");
+        assert!(matches!(reader.read(1024).unwrap(), Update::Unchanged));
+        
+        // Copied and truncated, then written past the old offset before the next read
+        std::fs::write(&path, "a new first line, longer than before\n").unwrap();
+        assert!(matches!(reader.read(1024).unwrap(), Update::Restarted));
+        assert_eq!(read_all(&mut reader), b"a new first line, longer than before\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn truncation_is_noticed_after_the_file_is_moved_away() {
+        let path = std::env::temp_dir().join(format!("grepx-follow-moved-{}", std::process::id()));
+        let moved = path.with_extension("1");
+        std::fs::write(&path, "old content\n").unwrap();
+        let mut reader = FollowReader::new(&path);
+        assert_eq!(read_all(&mut reader), b"old content\n");
+        
+        std::fs::rename(&path, &moved).unwrap();
+        std::fs::write(&moved, "new\n").unwrap();
+        assert!(matches!(reader.read(1024).unwrap(), Update::Restarted));
+        assert_eq!(read_all(&mut reader), b"new\n");
+        std::fs::remove_file(&moved).unwrap();
+    }
+}
@@ -1,2 +1,7 @@
 pub mod file_discovery;
-pub mod reader; 
\ No newline at end of file
+pub(crate) mod follow;
+pub(crate) mod prefetch;
+pub mod reader;
+pub(crate) mod roots;
+pub mod strategy;
+pub(crate) mod watch; 
\ No newline at end of file
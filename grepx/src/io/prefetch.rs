@@ -0,0 +1,141 @@
+use log::trace;
+use rayon::ThreadPool;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Bytes at the start of each upcoming file that are prefetched
+const PREFETCH_BYTES: u64 = 4 * 1024 * 1024; // 4MB
+
+/// Minimum number of files prefetched ahead of the files being searched.
+/// Prefetching only a few files ahead leaves too little time for the reads
+/// to complete before the files are searched.
+const MIN_DISTANCE: usize = 64;
+
+/// Files prefetched ahead per search thread
+const DISTANCE_PER_THREAD: usize = 8;
+
+/// Asks the operating system to start reading upcoming files into the page
+/// cache while earlier files are being searched, so that reading and matching
+/// overlap on cold caches and network file systems.
+///
+/// Files are searched roughly in order, so when a worker starts a file the
+/// files following it are prefetched, more of them the more threads are
+/// searching. The hints are issued from the I/O pool so that search threads
+/// never wait on them.
+pub(crate) struct Prefetcher<'a> {
+    files: &'a [PathBuf],
+    distance: usize,
+    /// Index of the first file that has not been prefetched
+    next: AtomicUsize,
+    pool: &'a ThreadPool,
+}
+
+impl<'a> Prefetcher<'a> {
+    pub fn new(files: &'a [PathBuf], threads: usize, pool: &'a ThreadPool) -> Self {
+        Self {
+            files,
+            distance: MIN_DISTANCE.max(DISTANCE_PER_THREAD * threads),
+            next: AtomicUsize::new(0),
+            pool,
+        }
+    }
+    
+    /// Note that the file at `index` is being searched, prefetching the files
+    /// after it that have not been prefetched yet
+    pub fn advance(&self, index: usize) {
+        let target = (index + 1 + self.distance).min(self.files.len());
+        let mut start = self.next.load(Ordering::Relaxed);
+        
+        while start < target {
+            match self.next.compare_exchange_weak(start, target, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => {
+                    let files = self.files[start.max(index + 1).min(target)..target].to_vec();
+                    if !files.is_empty() {
+                        self.pool.spawn(move || files.iter().for_each(|file| prefetch_file(file)));
+                    }
+                    return;
+                }
+                Err(current) => start = current,
+            }
+        }
+    }
+}
+
+/// Start reading the beginning of a file into the page cache
+fn prefetch_file(path: &Path) {
+    trace!("Prefetching {}", path.display());
+    if let Ok(file) = File::open(path) {
+        will_need(&file, 0, PREFETCH_BYTES);
+    }
+}
+
+/// Hint that a range of an open file will be read soon
+#[cfg(target_os = "linux")]
+pub(crate) fn will_need(file: &File, offset: u64, len: u64) {
+    advise(file, offset, len, libc::POSIX_FADV_WILLNEED);
+}
+
+/// Hint that an open file will be read sequentially, allowing more aggressive readahead
+#[cfg(target_os = "linux")]
+pub(crate) fn sequential(file: &File) {
+    advise(file, 0, 0, libc::POSIX_FADV_SEQUENTIAL);
+}
+
+#[cfg(target_os = "linux")]
+fn advise(file: &File, offset: u64, len: u64, advice: libc::c_int) {
+    use std::os::unix::io::AsRawFd;
+    
+    // Hints are best effort, so failures are ignored
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, len as libc::off_t, advice);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn will_need(_file: &File, _offset: u64, _len: u64) {}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn sequential(_file: &File) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::ThreadPoolBuilder;
+    
+    #[test]
+    fn files_are_prefetched_ahead_of_the_search_once() {
+        let files: Vec<PathBuf> = (0..200).map(|i| PathBuf::from(format!("missing-{}", i))).collect();
+        let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let prefetcher = Prefetcher::new(&files, 2, &pool);
+        assert_eq!(prefetcher.distance, MIN_DISTANCE);
+        assert_eq!(Prefetcher::new(&files, 16, &pool).distance, 16 * DISTANCE_PER_THREAD);
+        
+        prefetcher.advance(0);
+        assert_eq!(prefetcher.next.load(Ordering::Relaxed), 1 + MIN_DISTANCE);
+        prefetcher.advance(10);
+        assert_eq!(prefetcher.next.load(Ordering::Relaxed), 11 + MIN_DISTANCE);
+        
+        // Files searched out of order never move the prefetched range back
+        prefetcher.advance(5);
+        assert_eq!(prefetcher.next.load(Ordering::Relaxed), 11 + MIN_DISTANCE);
+        prefetcher.advance(190);
+        assert_eq!(prefetcher.next.load(Ordering::Relaxed), files.len());
+    }
+    
+    #[test]
+    fn concurrent_searches_advance_to_the_furthest_file() {
+        let files: Vec<PathBuf> = (0..1000).map(|i| PathBuf::from(format!("missing-{}", i))).collect();
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let prefetcher = Prefetcher::new(&files, 4, &pool);
+        
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let prefetcher = &prefetcher;
+                scope.spawn(move || (thread..500).step_by(4).for_each(|index| prefetcher.advance(index)));
+            }
+        });
+        assert_eq!(prefetcher.next.load(Ordering::Relaxed), 500 + MIN_DISTANCE);
+    }
+
+}
@@ -1,26 +1,45 @@
 use anyhow::{Result, Context};
 use memmap2::{Mmap, MmapOptions};
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::borrow::Cow;
+use std::fs::{File, Metadata};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
 
-const MIN_SIZE_FOR_MMAP: u64 = 1024 * 1024; // 1MB
+use super::prefetch;
+use super::strategy::ReadStrategy;
 
-/// File reader that uses memory mapping for large files and buffered I/O for small files
+/// File reader that reads a file with a given [`ReadStrategy`]. Memory mapped
+/// files are mapped once and read without copying.
 pub struct FileReader {
-    path: std::path::PathBuf,
+    file: File,
     size: u64,
+    strategy: ReadStrategy,
+    mmap: Option<Mmap>,
 }
 
 impl FileReader {
-    /// Create a new file reader for the given path
-    pub fn new(path: &Path) -> Result<Self> {
-        let metadata = std::fs::metadata(path)
-            .with_context(|| format!("Failed to get metadata for file: {}", path.display()))?;
+    /// Open the file at the given path, to be read with the given strategy
+    pub fn open(path: &Path, strategy: ReadStrategy) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let size = file.metadata()
+            .with_context(|| format!("Failed to get metadata for file: {}", path.display()))?
+            .len();
+        
+        // Empty files cannot be mapped
+        let mmap = if strategy == ReadStrategy::Mmap && size > 0 {
+            let mmap = unsafe { MmapOptions::new().map(&file) }
+                .with_context(|| format!("Failed to map file: {}", path.display()))?;
+            Some(mmap)
+        } else {
+            None
+        };
         
         Ok(Self {
-            path: path.to_path_buf(),
-            size: metadata.len(),
+            file,
+            size,
+            strategy,
+            mmap,
         })
     }
     
@@ -29,76 +48,115 @@ impl FileReader {
         self.size
     }
     
+    /// Update the size of a file that may have grown or shrunk since it was
+    /// opened, mapping it again if it is memory mapped, and return the new size
+    pub fn refresh(&mut self) -> Result<u64> {
+        let size = self.file.metadata()
+            .context("Failed to get file metadata")?
+            .len();
+        
+        if self.strategy == ReadStrategy::Mmap && size != self.size {
+            self.mmap = None;
+            if size > 0 {
+                let mmap = unsafe { MmapOptions::new().map(&self.file) }
+                    .context("Failed to map file")?;
+                self.mmap = Some(mmap);
+            }
+        }
+        self.size = size;
+        Ok(size)
+    }
+    
+    /// Get the metadata of the open file, which may since have been renamed or removed
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        self.file.metadata()
+    }
+    
+    /// Strategy the file is read with
+    pub fn strategy(&self) -> ReadStrategy {
+        self.strategy
+    }
+    
+    /// Hint that the file will be read from start to end
+    pub fn advise_sequential(&self) {
+        match &self.mmap {
+            #[cfg(unix)]
+            Some(mmap) => {
+                let _ = mmap.advise(memmap2::Advice::Sequential);
+            }
+            _ => prefetch::sequential(&self.file),
+        }
+    }
+    
+    /// Start reading a range of the file into memory ahead of its use
+    pub fn prefetch(&self, offset: usize, len: usize) {
+        let len = len.min((self.size as usize).saturating_sub(offset));
+        if len == 0 {
+            return;
+        }
+        
+        match &self.mmap {
+            #[cfg(unix)]
+            Some(mmap) => {
+                let _ = mmap.advise_range(memmap2::Advice::WillNeed, offset, len);
+            }
+            _ => prefetch::will_need(&self.file, offset as u64, len as u64),
+        }
+    }
+    
+    /// Whether the file is read through a memory map
+    pub fn uses_mmap(&self) -> bool {
+        self.mmap.is_some()
+    }
+    
     /// Read the entire file content as bytes
-    pub fn read_all(&self) -> Result<Vec<u8>> {
-        let content = if self.should_use_mmap() {
-            self.read_mmap()?
-        } else {
-            self.read_buffered()?
-        };
+    pub fn read_all(&self) -> Result<Cow<'_, [u8]>> {
+        if let Some(mmap) = &self.mmap {
+            return Ok(Cow::Borrowed(&mmap[..]));
+        }
         
-        Ok(content)
+        let mut buffer = Vec::with_capacity(self.size as usize);
+        (&self.file).read_to_end(&mut buffer)?;
+        Ok(Cow::Owned(buffer))
     }
     
     /// Read a chunk of the file from the given offset with specified size
-    pub fn read_chunk(&self, offset: usize, size: usize) -> Result<Vec<u8>> {
-        if offset as u64 > self.size {
-            return Ok(Vec::new());
+    pub fn read_chunk(&self, offset: usize, size: usize) -> Result<Cow<'_, [u8]>> {
+        if let Some(mmap) = &self.mmap {
+            let start = offset.min(mmap.len());
+            let end = offset.saturating_add(size).min(mmap.len());
+            return Ok(Cow::Borrowed(&mmap[start..end]));
         }
         
         let actual_size = std::cmp::min(size, (self.size as usize).saturating_sub(offset));
-        
-        if actual_size == 0 {
-            return Ok(Vec::new());
+        let mut buffer = vec![0; actual_size];
+        let mut filled = 0;
+        while filled < actual_size {
+            let read = read_at(&self.file, &mut buffer[filled..], (offset + filled) as u64)?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
         }
-        
-        let content = if self.should_use_mmap() {
-            let mmap = self.create_mmap()?;
-            let end = std::cmp::min(offset + actual_size, mmap.len());
-            mmap[offset..end].to_vec()
-        } else {
-            let mut file = File::open(&self.path)?;
-            file.seek(SeekFrom::Start(offset as u64))?;
-            
-            let mut buffer = vec![0; actual_size];
-            let bytes_read = file.read(&mut buffer)?;
-            buffer.truncate(bytes_read);
-            buffer
-        };
-        
-        Ok(content)
+        buffer.truncate(filled);
+        Ok(Cow::Owned(buffer))
     }
     
     /// Read file lines using a buffered reader
     pub fn read_lines(&self) -> Result<impl Iterator<Item = Result<String, io::Error>>> {
-        let file = File::open(&self.path)?;
+        let file = self.file.try_clone()?;
         let reader = BufReader::new(file);
         Ok(reader.lines())
     }
-    
-    /// Determine if memory mapping should be used based on file size
-    fn should_use_mmap(&self) -> bool {
-        self.size >= MIN_SIZE_FOR_MMAP
-    }
-    
-    /// Read file using memory mapping
-    fn read_mmap(&self) -> Result<Vec<u8>> {
-        let mmap = self.create_mmap()?;
-        Ok(mmap.to_vec())
-    }
-    
-    /// Create memory map for file
-    fn create_mmap(&self) -> Result<Mmap> {
-        let file = File::open(&self.path)?;
-        let mmap = unsafe { MmapOptions::new().map(&file)? };
-        Ok(mmap)
-    }
-    
-    /// Read file using buffered I/O
-    fn read_buffered(&self) -> Result<Vec<u8>> {
-        let mut buffer = Vec::with_capacity(self.size as usize);
-        let mut file = File::open(&self.path)?;
-        io::Read::read_to_end(&mut file, &mut buffer)?;
-        Ok(buffer)
-    }
-} 
\ No newline at end of file
+}
+
+/// Read from a file at an offset without moving a shared file position
+#[cfg(unix)]
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buffer, offset)
+}
@@ -0,0 +1,69 @@
+use anyhow::{Result, Context};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::{ErrorKind, SearchError};
+use crate::searcher::SearcherBuilder;
+
+/// Root directories a server allows searches under, or none to allow any path
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Roots(Arc<Vec<PathBuf>>);
+
+impl Roots {
+    pub fn new(dirs: &[PathBuf]) -> Result<Self> {
+        let roots = dirs.iter()
+            .map(|dir| dir.canonicalize().with_context(|| format!("Invalid root directory {}", dir.display())))
+            .collect::<Result<_>>()?;
+        Ok(Self(Arc::new(roots)))
+    }
+    
+    /// Whether a path is under one of the roots once symbolic links are resolved
+    pub fn contains(&self, path: &Path) -> bool {
+        if self.0.is_empty() {
+            return true;
+        }
+        let path = resolve(path);
+        self.0.iter().any(|root| path.starts_with(root))
+    }
+    
+    /// Limit a search to the roots. Paths outside them are rejected with an
+    /// error, and files reached through symbolic links that lead outside them
+    /// are skipped.
+    pub fn restrict(&self, builder: SearcherBuilder, paths: &[PathBuf]) -> Result<SearcherBuilder> {
+        if self.0.is_empty() {
+            return Ok(builder);
+        }
+        if let Some(path) = paths.iter().find(|path| !self.contains(path)) {
+            let roots: Vec<String> = self.0.iter().map(|root| root.display().to_string()).collect();
+            let message = format!("outside the allowed root directories ({})", roots.join(", "));
+            return Err(SearchError::new(ErrorKind::PermissionDenied, Some(path), message).into());
+        }
+        
+        let roots = self.clone();
+        Ok(builder.filter(move |file| roots.contains(file)))
+    }
+}
+
+/// Absolute path with symbolic links resolved. Of a path that does not
+/// exist, the longest part that does is resolved and the rest has `.` and
+/// `..` removed, so neither broken links nor `..` can lead out of a root.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let components: Vec<Component> = absolute.components().collect();
+    let (mut resolved, rest) = (0..=components.len()).rev()
+        .find_map(|end| {
+            let existing: PathBuf = components[..end].iter().collect();
+            Some((existing.canonicalize().ok()?, &components[end..]))
+        })
+        .unwrap_or((PathBuf::new(), &components[..]));
+    for component in rest {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
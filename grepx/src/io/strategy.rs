@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Files up to this size are read whole with a single read
+const SMALL_FILE: u64 = 1024 * 1024; // 1MB
+
+/// Smallest and largest chunk sizes chosen automatically
+const MIN_CHUNK: usize = 64 * 1024; // 64KB
+const MAX_CHUNK: usize = 8 * 1024 * 1024; // 8MB
+
+/// Memory that the chunks of all search threads may use together
+const CHUNK_MEMORY_BUDGET: usize = 256 * 1024 * 1024; // 256MB
+
+/// Read throughput below which storage is treated as slow, such as a network
+/// file system or a cold disk
+const SLOW_STORAGE: f64 = 200.0 * 1024.0 * 1024.0; // 200MB/s
+
+/// Bytes that must have been read before the measured throughput is trusted
+const MIN_MEASURED_BYTES: u64 = 16 * 1024 * 1024; // 16MB
+
+/// How the contents of a file are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStrategy {
+    /// Read the whole file into memory with a single read
+    Buffered,
+    /// Map the file into memory and search it in place
+    Mmap,
+    /// Read the file in order, chunk by chunk, from a single open handle so
+    /// the operating system can read ahead
+    Sequential,
+}
+
+/// Strategy and chunk size chosen for one file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadPlan {
+    pub strategy: ReadStrategy,
+    /// Bytes searched at a time
+    pub chunk_size: usize,
+}
+
+/// Chooses how each file of a search is read, based on its size, the number
+/// of search threads and the read throughput measured so far. Explicit
+/// settings override the automatic choice.
+pub(crate) struct ReadPlanner {
+    strategy: Option<ReadStrategy>,
+    chunk_size: Option<usize>,
+    threads: usize,
+    bytes_read: AtomicU64,
+    read_nanos: AtomicU64,
+}
+
+impl ReadPlanner {
+    pub fn new(strategy: Option<ReadStrategy>, chunk_size: Option<usize>, threads: usize) -> Self {
+        Self {
+            strategy,
+            chunk_size,
+            threads: threads.max(1),
+            bytes_read: AtomicU64::new(0),
+            read_nanos: AtomicU64::new(0),
+        }
+    }
+    
+    /// Choose how to read a file of the given size
+    pub fn plan(&self, size: u64) -> ReadPlan {
+        let strategy = self.strategy.unwrap_or_else(|| {
+            if size <= SMALL_FILE {
+                ReadStrategy::Buffered
+            } else if self.slow_storage() {
+                // Page faults on slow storage stall the search thread, while
+                // sequential reads let the kernel read ahead
+                ReadStrategy::Sequential
+            } else {
+                ReadStrategy::Mmap
+            }
+        });
+        
+        let chunk_size = self.chunk_size.unwrap_or_else(|| {
+            // Aim for a few dozen chunks per file, so limits and timeouts are
+            // checked regularly, within the memory budget for all threads
+            let max_chunk = (CHUNK_MEMORY_BUDGET / self.threads).clamp(MIN_CHUNK, MAX_CHUNK);
+            let target = (size / 32).min(usize::MAX as u64) as usize;
+            target.next_power_of_two().clamp(MIN_CHUNK, max_chunk)
+        });
+        
+        ReadPlan { strategy, chunk_size }
+    }
+    
+    /// Record a read from storage, used to detect slow storage. Small reads
+    /// are dominated by latency rather than throughput and are ignored.
+    pub fn record_read(&self, bytes: usize, elapsed: Duration) {
+        if bytes < MIN_CHUNK {
+            return;
+        }
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.read_nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+    
+    /// Read throughput measured so far in bytes per second, once enough data
+    /// has been read for it to be meaningful
+    pub fn throughput(&self) -> Option<f64> {
+        let bytes = self.bytes_read.load(Ordering::Relaxed);
+        let nanos = self.read_nanos.load(Ordering::Relaxed);
+        (bytes >= MIN_MEASURED_BYTES && nanos > 0)
+            .then(|| bytes as f64 / Duration::from_nanos(nanos).as_secs_f64())
+    }
+    
+    fn slow_storage(&self) -> bool {
+        self.throughput().is_some_and(|throughput| throughput < SLOW_STORAGE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const MB: u64 = 1024 * 1024;
+    
+    #[test]
+    fn strategies_follow_file_size_and_storage_speed() {
+        let planner = ReadPlanner::new(None, None, 4);
+        assert_eq!(planner.plan(0).strategy, ReadStrategy::Buffered);
+        assert_eq!(planner.plan(SMALL_FILE).strategy, ReadStrategy::Buffered);
+        assert_eq!(planner.plan(SMALL_FILE + 1).strategy, ReadStrategy::Mmap);
+        assert_eq!(planner.plan(u64::MAX).strategy, ReadStrategy::Mmap);
+        
+        // Small reads say nothing about throughput
+        planner.record_read(MIN_CHUNK - 1, Duration::from_secs(10));
+        assert_eq!(planner.throughput(), None);
+        
+        // 16MB in a second is slow, so large files are read sequentially
+        planner.record_read(MIN_MEASURED_BYTES as usize, Duration::from_secs(1));
+        assert_eq!(planner.throughput(), Some(MIN_MEASURED_BYTES as f64));
+        assert_eq!(planner.plan(SMALL_FILE).strategy, ReadStrategy::Buffered);
+        assert_eq!(planner.plan(SMALL_FILE + 1).strategy, ReadStrategy::Sequential);
+        
+        let fast = ReadPlanner::new(None, None, 4);
+        fast.record_read(MIN_MEASURED_BYTES as usize, Duration::from_millis(10));
+        assert_eq!(fast.plan(SMALL_FILE + 1).strategy, ReadStrategy::Mmap);
+    }
+    
+    #[test]
+    fn chunk_sizes_grow_with_the_file_within_the_memory_budget() {
+        let planner = ReadPlanner::new(None, None, 1);
+        assert_eq!(planner.plan(0).chunk_size, MIN_CHUNK);
+        assert_eq!(planner.plan(SMALL_FILE).chunk_size, MIN_CHUNK);
+        assert_eq!(planner.plan(64 * MB).chunk_size, 2 * MB as usize);
+        assert_eq!(planner.plan(100 * MB).chunk_size, 4 * MB as usize);
+        assert_eq!(planner.plan(1024 * MB).chunk_size, MAX_CHUNK);
+        assert_eq!(planner.plan(u64::MAX).chunk_size, MAX_CHUNK);
+        
+        // Many threads share the budget, but never go below the smallest chunk
+        assert_eq!(ReadPlanner::new(None, None, 128).plan(1024 * MB).chunk_size, 2 * MB as usize);
+        assert_eq!(ReadPlanner::new(None, None, 100_000).plan(1024 * MB).chunk_size, MIN_CHUNK);
+        assert_eq!(ReadPlanner::new(None, None, 0).plan(1024 * MB).chunk_size, MAX_CHUNK);
+    }
+    
+    #[test]
+    fn explicit_settings_override_the_choice() {
+        let planner = ReadPlanner::new(Some(ReadStrategy::Sequential), Some(12345), 4);
+        assert_eq!(planner.plan(10), ReadPlan { strategy: ReadStrategy::Sequential, chunk_size: 12345 });
+        assert_eq!(planner.plan(u64::MAX), ReadPlan { strategy: ReadStrategy::Sequential, chunk_size: 12345 });
+        
+        let planner = ReadPlanner::new(Some(ReadStrategy::Mmap), None, 4);
+        assert_eq!(planner.plan(10).strategy, ReadStrategy::Mmap);
+    }
+}
@@ -0,0 +1,294 @@
+//! Watching paths for files that are created or modified, with inotify
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::Watcher;
+#[cfg(not(target_os = "linux"))]
+pub(crate) use other::Watcher;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use anyhow::{Result, Context};
+    use log::{debug, warn};
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::ffi::{CString, OsStr, OsString};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+    use walkdir::WalkDir;
+    
+    use crate::index::is_index_file;
+    
+    /// Longest wait for changes to settle, in debounce periods, so that files
+    /// written to continuously are still searched
+    const MAX_DEBOUNCES: u32 = 10;
+    
+    /// How often a watcher waiting for changes checks whether it should stop
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+    
+    /// Events on a watched directory that can leave one of its files with new content
+    const EVENTS: u32 = libc::IN_CREATE | libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+    
+    /// Size of an `inotify_event` without the name that follows it
+    const EVENT_LEN: usize = 16;
+    
+    /// Watches files and directories for files that are created or modified.
+    ///
+    /// Directories are watched rather than files, so a file is still followed
+    /// when an editor replaces it by renaming another file over it, and a file
+    /// that does not exist yet is picked up once it is created.
+    pub(crate) struct Watcher {
+        fd: OwnedFd,
+        recursive: bool,
+        /// Watched directories by watch descriptor
+        dirs: HashMap<i32, Watch>,
+        /// The paths being watched, all checked for new content if events are lost
+        paths: Vec<PathBuf>,
+        overflowed: bool,
+    }
+    
+    struct Watch {
+        dir: PathBuf,
+        /// Names of the files watched in the directory, or `None` for all of them
+        names: Option<HashSet<OsString>>,
+    }
+    
+    impl Watcher {
+        /// Start watching the given files and directories. Directories are
+        /// watched with their subdirectories when `recursive` is set.
+        pub fn new(paths: &[PathBuf], recursive: bool) -> Result<Self> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error()).context("Failed to start watching files");
+            }
+            let mut watcher = Self {
+                fd: unsafe { OwnedFd::from_raw_fd(fd) },
+                recursive,
+                dirs: HashMap::new(),
+                paths: paths.to_vec(),
+                overflowed: false,
+            };
+            
+            for path in paths {
+                if path.is_dir() {
+                    watcher.watch_tree(path)?;
+                    continue;
+                }
+                let name = path.file_name()
+                    .with_context(|| format!("Cannot watch {}: not a file or directory", path.display()))?;
+                let dir = path.parent().unwrap_or(Path::new(""));
+                watcher.watch_dir(dir, Some(name))
+                    .with_context(|| format!("Cannot watch {}", path.display()))?;
+            }
+            Ok(watcher)
+        }
+        
+        /// Watch a directory, with its subdirectories when recursive,
+        /// returning the files already in the directories
+        fn watch_tree(&mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+            if !self.recursive {
+                self.watch_dir(dir, None)
+                    .with_context(|| format!("Cannot watch {}", dir.display()))?;
+                return Ok(Vec::new());
+            }
+            
+            let mut files = Vec::new();
+            for entry in WalkDir::new(dir).follow_links(true) {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        debug!("Not watching {}: {}", dir.display(), e);
+                        continue;
+                    }
+                };
+                if entry.file_type().is_dir() {
+                    self.watch_dir(entry.path(), None)
+                        .with_context(|| format!("Cannot watch {}", entry.path().display()))?;
+                } else if entry.file_type().is_file() {
+                    files.push(entry.into_path());
+                }
+            }
+            Ok(files)
+        }
+        
+        /// Watch a directory for changes to one of its files, or to any of
+        /// them if `name` is `None`. An empty `dir` is the current directory,
+        /// kept empty so changed files are reported as they were given.
+        fn watch_dir(&mut self, dir: &Path, name: Option<&OsStr>) -> io::Result<()> {
+            let target = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            let path = CString::new(target.as_os_str().as_bytes())
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), EVENTS | libc::IN_ONLYDIR) };
+            if wd < 0 {
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() == Some(libc::ENOSPC) {
+                    return Err(io::Error::other("too many watches; raise fs.inotify.max_user_watches"));
+                }
+                return Err(error);
+            }
+            
+            // Watching a directory again returns the same descriptor
+            let name = name.map(OsStr::to_os_string);
+            let watch = self.dirs.entry(wd).or_insert_with(|| Watch {
+                dir: dir.to_path_buf(),
+                names: Some(HashSet::new()),
+            });
+            match (&mut watch.names, name) {
+                (Some(names), Some(name)) => {
+                    names.insert(name);
+                }
+                (names, None) => *names = None,
+                (None, Some(_)) => {}
+            }
+            Ok(())
+        }
+        
+        /// Wait until files have been created or modified and no more changes
+        /// have followed for `debounce`, returning the paths to search again.
+        /// Returns `None` once `cancel` is set.
+        pub fn wait(&mut self, debounce: Duration, cancel: Option<&AtomicBool>) -> Result<Option<Vec<PathBuf>>> {
+            let mut changed = BTreeSet::new();
+            let mut first: Option<Instant> = None;
+            let mut last = Instant::now();
+            
+            loop {
+                if cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                    return Ok(None);
+                }
+                let timeout = match first {
+                    Some(first) => {
+                        let deadline = (last + debounce).min(first + debounce * MAX_DEBOUNCES);
+                        let now = Instant::now();
+                        if now >= deadline {
+                            break;
+                        }
+                        (deadline - now).min(POLL_INTERVAL)
+                    }
+                    None => POLL_INTERVAL,
+                };
+                
+                if self.poll(timeout)? && self.read_events(&mut changed)? > 0 {
+                    last = Instant::now();
+                    first.get_or_insert(last);
+                }
+            }
+            
+            if std::mem::take(&mut self.overflowed) {
+                return Ok(Some(self.paths.clone()));
+            }
+            Ok(Some(changed.into_iter()
+                .filter(|path| path.is_file() && !path.file_name().is_some_and(is_index_file))
+                .collect()))
+        }
+        
+        /// Wait up to `timeout` for events, returning whether any are ready
+        fn poll(&self, timeout: Duration) -> Result<bool> {
+            let mut fds = libc::pollfd {
+                fd: self.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut fds, 1, timeout.as_millis() as libc::c_int) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    return Ok(false);
+                }
+                return Err(error).context("Failed to wait for file changes");
+            }
+            Ok(ready > 0)
+        }
+        
+        /// Read the pending events, adding the files they concern to
+        /// `changed` and returning how many events were of interest
+        fn read_events(&mut self, changed: &mut BTreeSet<PathBuf>) -> Result<usize> {
+            let mut buffer = vec![0u8; 64 * 1024];
+            let mut relevant = 0;
+            loop {
+                let read = unsafe { libc::read(self.fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+                if read < 0 {
+                    let error = io::Error::last_os_error();
+                    match error.kind() {
+                        io::ErrorKind::WouldBlock => break,
+                        io::ErrorKind::Interrupted => continue,
+                        _ => return Err(error).context("Failed to read file changes"),
+                    }
+                }
+                
+                let events = &buffer[..read as usize];
+                let mut offset = 0;
+                while offset + EVENT_LEN <= events.len() {
+                    let field = |at: usize| u32::from_ne_bytes(events[offset + at..offset + at + 4].try_into().unwrap());
+                    let (wd, mask, len) = (field(0) as i32, field(4), field(12) as usize);
+                    let name = events.get(offset + EVENT_LEN..offset + EVENT_LEN + len).unwrap_or_default();
+                    let name = OsStr::from_bytes(&name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())]);
+                    offset += EVENT_LEN + len;
+                    if self.handle_event(wd, mask, name, changed) {
+                        relevant += 1;
+                    }
+                }
+            }
+            Ok(relevant)
+        }
+        
+        /// Note the file an event concerns, returning whether it is of interest
+        fn handle_event(&mut self, wd: i32, mask: u32, name: &OsStr, changed: &mut BTreeSet<PathBuf>) -> bool {
+            if mask & libc::IN_Q_OVERFLOW != 0 {
+                warn!("Missed file changes, searching all watched paths again");
+                self.overflowed = true;
+                return true;
+            }
+            if mask & libc::IN_IGNORED != 0 {
+                self.dirs.remove(&wd);
+                return false;
+            }
+            let Some(watch) = self.dirs.get(&wd) else {
+                return false;
+            };
+            if name.is_empty() {
+                return false;
+            }
+            let path = watch.dir.join(name);
+            
+            if mask & libc::IN_ISDIR != 0 {
+                // A new subdirectory is watched too, and the files it already
+                // holds are searched
+                if !self.recursive || watch.names.is_some() || mask & (libc::IN_CREATE | libc::IN_MOVED_TO) == 0 {
+                    return false;
+                }
+                match self.watch_tree(&path) {
+                    Ok(files) => changed.extend(files),
+                    Err(e) => warn!("{:#}", e),
+                }
+                return true;
+            }
+            if watch.names.as_ref().is_some_and(|names| !names.contains(name)) {
+                return false;
+            }
+            changed.insert(path);
+            true
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod other {
+    use anyhow::{bail, Result};
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
+    
+    pub(crate) struct Watcher;
+    
+    impl Watcher {
+        pub fn new(_paths: &[PathBuf], _recursive: bool) -> Result<Self> {
+            bail!("Watching files is only supported on Linux")
+        }
+        
+        pub fn wait(&mut self, _debounce: Duration, _cancel: Option<&AtomicBool>) -> Result<Option<Vec<PathBuf>>> {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! GrepX - A distributed, multi-threaded regex search engine
+//!
+//! ```no_run
+//! use grepx::{CaseMode, SearcherBuilder};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let searcher = SearcherBuilder::new(r"fn\s+\w+")
+//!     .case_mode(CaseMode::Smart)
+//!     .recursive(true)
+//!     .build()?;
+//!
+//! let result = searcher.search_path("src")?;
+//! for file in &result.files {
+//!     for line in &file.lines {
+//!         println!("{:?}:{}: {}", file.path, line.line_number, line.line);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Results can also be streamed as they are found by implementing [`Sink`]
+//! and calling one of the `search_*_with` methods, or by using one of the
+//! built-in printers from [`printer`].
+
+pub mod distributed;
+mod engine;
+pub mod error;
+pub mod index;
+pub mod io;
+pub mod printer;
+mod pool;
+pub mod regex;
+mod reorder;
+mod searcher;
+pub mod server;
+mod sink;
+pub mod utils;
+
+pub use distributed::{NodeState, NodeStatus};
+pub use engine::{ContextKind, ContextLine, FileResult, FileStats, LineMatch, SearchResult};
+pub use error::{ErrorKind, SearchError};
+pub use crate::regex::CaseMode;
+pub use io::file_discovery::SortBy;
+pub use io::strategy::ReadStrategy;
+pub use searcher::{FileFilter, Searcher, SearcherBuilder};
+pub use sink::Sink;
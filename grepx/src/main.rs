@@ -1,28 +1,171 @@
+mod bench;
 mod cli;
-mod engine;
-mod io;
-mod regex;
-mod utils;
 
 use anyhow::Result;
+use grepx::distributed::{read_token_file, WorkerBuilder};
+use grepx::index::{Index, IndexBuilder, DEFAULT_FILE_NAME};
+use grepx::server::ServerBuilder;
+use grepx::utils::format_size;
 use log::info;
+use std::path::Path;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+use cli::{Args, Command, IndexArgs, IndexCommand, ServeArgs, ServerArgs};
+
+fn main() -> ExitCode {
     // Initialize logger
     env_logger::init();
     
     // Parse command line arguments
-    let args = cli::parse_args()?;
+    let command = match cli::parse_args() {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return ExitCode::from(2);
+        }
+    };
     
+    let outcome = match &command {
+        Command::Search(args) => run(args),
+        Command::Bench(args) => bench::run(args).map(|()| ExitCode::SUCCESS),
+        Command::Serve(args) => serve(args).map(|()| ExitCode::SUCCESS),
+        Command::Server(args) => server(args).map(|()| ExitCode::SUCCESS),
+        Command::Index(args) => index(args),
+    };
+    match outcome {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Run the search and determine the grep-compatible exit code: 0 if a match
+/// was found, 1 if nothing matched and 2 if an error occurred or, in a
+/// distributed search, files were left unsearched because workers failed. In
+/// quiet mode a match takes precedence over errors.
+fn run(args: &Args) -> Result<ExitCode> {
     info!("Starting GrepX search with pattern: {}", args.pattern);
     
-    // Execute search based on arguments
-    let result = engine::execute_search(&args)?;
+    // Execute search based on arguments, streaming results to the printer
+    let mut builder = args.searcher_builder()
+        .worker_secret(token(args.token_file.as_deref())?);
+    if let Some(path) = &args.index {
+        builder = builder.index(Index::open(path)?);
+    }
+    let searcher = builder.build()?;
+    let printer = args.printer();
+    if args.watch {
+        searcher.watch_paths_with(&args.path, args.debounce, printer.as_ref())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let result = if args.follow {
+        searcher.follow_paths_with(&args.path, printer.as_ref())?
+    } else {
+        searcher.search_paths_with(&args.path, printer.as_ref())?
+    };
+    
+    let matched = result.total_matches > 0;
+    let failed = !result.errors.is_empty() || result.is_partial();
+    let code = if matched && (args.quiet || !failed) {
+        0
+    } else if failed {
+        2
+    } else {
+        1
+    };
+    
+    Ok(ExitCode::from(code))
+}
+
+
+/// Run a worker for distributed searches until the process is stopped
+fn serve(args: &ServeArgs) -> Result<()> {
+    let mut builder = WorkerBuilder::new()
+        .threads(args.threads)
+        .io_threads(args.io_threads)
+        .secret(token(args.token_file.as_deref())?)
+        .roots(&args.roots);
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        builder = builder.tls(cert, key);
+    }
+    let worker = builder.bind(&args.listen)?;
+    
+    let addr = worker.local_addr()?;
+    eprintln!("grepx: worker listening on {}", addr);
+    if !worker.is_authenticated() && !addr.ip().is_loopback() {
+        eprintln!("grepx: warning: any coordinator that can reach this worker may search it; use --token-file");
+    }
+    worker.serve()
+}
+
+/// Run the HTTP search server until the process is stopped
+fn server(args: &ServerArgs) -> Result<()> {
+    let server = ServerBuilder::new()
+        .threads(args.threads)
+        .io_threads(args.io_threads)
+        .token(token(args.token_file.as_deref())?)
+        .roots(&args.roots)
+        .hosts(args.allowed_hosts.iter().chain([&args.host]).cloned())
+        .bind((args.host.as_str(), args.port))?;
     
-    // Display results summary
-    println!("Found {} matches in {} files", 
-             result.total_matches, 
-             result.files_searched);
+    let addr = server.local_addr()?;
+    eprintln!("grepx: server listening on http://{}", addr);
+    if !server.is_authenticated() && !addr.ip().is_loopback() {
+        eprintln!("grepx: warning: any client that can reach this server may search it; use --token-file");
+    }
+    server.serve()
+}
+
+/// Build or update an index, exiting with 2 if some files could not be read
+/// and so were left out of it
+fn index(args: &IndexArgs) -> Result<ExitCode> {
+    let (output, stats) = match &args.command {
+        IndexCommand::Build(args) => {
+            let output = args.output.clone()
+                .unwrap_or_else(|| args.path.join(DEFAULT_FILE_NAME));
+            let stats = IndexBuilder::new()
+                .threads(args.threads)
+                .hashes(args.hash)
+                .build(&args.path, &output)?;
+            (output, stats)
+        }
+        IndexCommand::Update(args) => {
+            let output = match args.index.is_dir() {
+                true => args.index.join(DEFAULT_FILE_NAME),
+                false => args.index.clone(),
+            };
+            let stats = IndexBuilder::new()
+                .threads(args.threads)
+                .verify(args.verify)
+                .update(&output)?;
+            eprintln!("grepx: {} added, {} modified, {} removed, {} unchanged",
+                      stats.added, stats.modified, stats.removed, stats.unchanged);
+            (output, stats)
+        }
+    };
     
-    Ok(())
+    for error in &stats.errors {
+        eprintln!("grepx: {}", error);
+    }
+    eprintln!("grepx: indexed {} files ({}) with {} trigrams into {} ({})",
+              stats.files, format_size(stats.bytes as usize), stats.trigrams,
+              output.display(), format_size(stats.index_size as usize));
+    if !stats.skipped_files.is_empty() {
+        eprintln!("grepx: left out {} files that are not valid UTF-8", stats.skipped_files.len());
+    }
+    Ok(ExitCode::from(if stats.errors.is_empty() { 0 } else { 2 }))
 }
+
+/// Shared secret for distributed searches and the HTTP server, from a token file or the
+/// `GREPX_TOKEN` environment variable
+fn token(token_file: Option<&Path>) -> Result<Option<Vec<u8>>> {
+    match token_file {
+        Some(path) => Ok(Some(read_token_file(path)?)),
+        None => Ok(std::env::var("GREPX_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .map(String::into_bytes)),
+    }
+}
\ No newline at end of file
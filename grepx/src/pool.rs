@@ -0,0 +1,46 @@
+use anyhow::{Result, Context};
+use log::info;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::searcher::SearchOptions;
+
+/// Thread pools owned by a searcher, so that searches never depend on or
+/// modify rayon's global pool
+pub(crate) struct ThreadPools {
+    /// Pool that searches file contents
+    pub search: ThreadPool,
+    /// Pool for I/O-bound work: walking directories and reading file metadata
+    pub io: ThreadPool,
+}
+
+impl ThreadPools {
+    pub fn new(options: &SearchOptions) -> Result<Self> {
+        let search_threads = resolve_threads(options.threads);
+        let io_threads = resolve_threads(options.io_threads);
+        info!("Using {} search threads and {} I/O threads", search_threads, io_threads);
+        
+        Ok(Self {
+            search: build_pool("grepx-search", search_threads)?,
+            io: build_pool("grepx-io", io_threads)?,
+        })
+    }
+}
+
+/// Resolve a configured thread count, where 0 means one per available CPU
+fn resolve_threads(threads: usize) -> usize {
+    if threads == 0 {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    } else {
+        threads
+    }
+}
+
+fn build_pool(name: &'static str, threads: usize) -> Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(move |index| format!("{}-{}", name, index))
+        .build()
+        .with_context(|| format!("Failed to create {} thread pool", name))
+}
@@ -0,0 +1,120 @@
+use std::path::Path;
+
+use super::{begin_file, display_path, file_started, print_stats, start_file, Output, OutputMode, PrinterOptions};
+use crate::distributed::NodeState;
+use crate::engine::{ContextLine, FileStats, LineMatch, SearchResult};
+use crate::error::SearchError;
+use crate::sink::Sink;
+
+/// Printer producing grep-compatible `path:line:content` output
+pub struct GrepPrinter {
+    options: PrinterOptions,
+    output: Output,
+}
+
+impl GrepPrinter {
+    /// Create a new grep-style printer
+    pub fn new(options: PrinterOptions) -> Self {
+        Self {
+            options,
+            output: Output::new(),
+        }
+    }
+    
+    /// Print a line prefixed by its path and, optionally, its line number
+    fn print_line(&self, path: Option<&Path>, line_number: usize, separator: char, line: &str) {
+        start_file();
+        
+        let mut prefix = String::new();
+        if let Some(path) = path {
+            prefix.push_str(&path.to_string_lossy());
+            prefix.push(separator);
+        }
+        if self.options.line_numbers {
+            prefix.push_str(&line_number.to_string());
+            prefix.push(separator);
+        }
+        
+        self.output.line(format_args!("{}{}", prefix, line));
+    }
+}
+
+impl Sink for GrepPrinter {
+    fn on_file_begin(&self, _path: Option<&Path>) {
+        begin_file();
+    }
+    
+    fn on_match(&self, path: Option<&Path>, line: &LineMatch) {
+        if self.options.mode == OutputMode::Lines {
+            self.print_line(path, line.line_number, ':', &line.line);
+        }
+    }
+    
+    fn on_context(&self, path: Option<&Path>, line: &ContextLine) {
+        if self.options.mode == OutputMode::Lines {
+            self.print_line(path, line.line_number, '-', &line.line);
+        }
+    }
+    
+    fn on_context_break(&self, _path: Option<&Path>) {
+        if self.options.mode == OutputMode::Lines && file_started() {
+            self.output.line(format_args!("--"));
+        }
+    }
+    
+    fn on_error(&self, error: &SearchError) {
+        if !self.options.no_messages {
+            eprintln!("grepx: {}", error);
+        }
+    }
+    
+    fn on_file_end(&self, path: Option<&Path>, stats: &FileStats) {
+        match self.options.mode {
+            OutputMode::Lines => {}
+            OutputMode::FilesWithMatches => {
+                if stats.matches > 0 {
+                    self.output.line(format_args!("{}", display_path(path)));
+                }
+            }
+            OutputMode::Count => match path {
+                Some(path) => self.output.line(format_args!("{}:{}", path.to_string_lossy(), stats.matches)),
+                None => self.output.line(format_args!("{}", stats.matches)),
+            },
+        }
+        
+        self.output.end_file();
+    }
+    
+    fn on_flush(&self) {
+        self.output.flush();
+    }
+    
+    fn on_finish(&self, result: &SearchResult) {
+        if self.options.stats {
+            print_stats(&self.output, result);
+        }
+        self.output.flush();
+        
+        // Keep stdout grep-compatible by reporting incomplete searches on stderr
+        if result.timed_out {
+            eprintln!("grepx: search timed out before all files were searched");
+        }
+        for path in &result.timed_out_files {
+            eprintln!("grepx: {}: timed out, partially searched", path.display());
+        }
+        for path in &result.skipped_files {
+            eprintln!("grepx: {}: skipped, over the size limit", path.display());
+        }
+        for node in &result.nodes {
+            match node.state {
+                NodeState::Complete => {}
+                NodeState::Partial => eprintln!("grepx: worker {} failed after searching {} files",
+                                                node.address, node.files_searched),
+                NodeState::Failed => eprintln!("grepx: worker {} failed before searching", node.address),
+            }
+        }
+        for path in &result.unsearched_files {
+            eprintln!("grepx: {}: not searched, worker failed", path.display());
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{begin_file, file_started, start_file, Output, OutputMode, PrinterOptions};
+use crate::engine::{ContextKind, ContextLine, FileStats, LineMatch, SearchResult};
+use crate::error::SearchError;
+use crate::sink::Sink;
+use crate::utils::calculate_speed;
+
+/// Printer producing one JSON object per line for each search event.
+///
+/// Files with output are bracketed by `begin` and `end` events, with `match`
+/// and `context` events in between, and a final `summary` event is printed
+/// once the search completes. Paths that could not be searched are reported
+/// with `error` events.
+pub struct JsonPrinter {
+    options: PrinterOptions,
+    output: Output,
+}
+
+impl JsonPrinter {
+    /// Create a new JSON Lines printer
+    pub fn new(options: PrinterOptions) -> Self {
+        Self {
+            options,
+            output: Output::new(),
+        }
+    }
+    
+    /// Create a JSON Lines printer writing to `writer` instead of stdout. The
+    /// output is flushed after each file, so a reader sees results as soon
+    /// as they are found.
+    pub fn with_writer<W: Write + Send + 'static>(options: PrinterOptions, writer: W) -> Self {
+        Self {
+            options,
+            output: Output::with_writer(Box::new(writer), true),
+        }
+    }
+    
+    /// Print an event, preceded by a `begin` event if it is the first for the file
+    fn print_file_event(&self, path: Option<&Path>, kind: &str, data: Value) {
+        if start_file() {
+            self.print_event("begin", json!({ "path": json_path(path) }));
+        }
+        self.print_event(kind, data);
+    }
+    
+    /// Print a single JSON event line for the current file
+    fn print_event(&self, kind: &str, data: Value) {
+        self.output.line(format_args!("{}", json!({ "type": kind, "data": data })));
+    }
+}
+
+impl Sink for JsonPrinter {
+    fn on_file_begin(&self, _path: Option<&Path>) {
+        begin_file();
+    }
+    
+    fn on_match(&self, path: Option<&Path>, line: &LineMatch) {
+        if self.options.mode != OutputMode::Lines {
+            return;
+        }
+        
+        self.print_file_event(path, "match", match_json(path, line));
+    }
+    
+    fn on_context(&self, path: Option<&Path>, line: &ContextLine) {
+        if self.options.mode != OutputMode::Lines {
+            return;
+        }
+        
+        let kind = match line.kind {
+            ContextKind::Before => "before",
+            ContextKind::After => "after",
+        };
+        
+        self.print_file_event(path, "context", json!({
+            "path": json_path(path),
+            "line_number": line.line_number,
+            "byte_offset": line.byte_offset,
+            "line": line.line,
+            "kind": kind,
+        }));
+    }
+    
+    fn on_error(&self, error: &SearchError) {
+        if self.options.no_messages {
+            return;
+        }
+        
+        self.print_event("error", error_json(error));
+    }
+    
+    fn on_file_end(&self, path: Option<&Path>, stats: &FileStats) {
+        if stats.matches > 0 && !file_started() {
+            start_file();
+            self.print_event("begin", json!({ "path": json_path(path) }));
+        }
+        
+        if file_started() {
+            self.print_event("end", json!({
+                "path": json_path(path),
+                "stats": {
+                    "matches": stats.matches,
+                    "matched_lines": stats.matched_lines,
+                    "bytes_processed": stats.bytes_processed,
+                    "timed_out": stats.timed_out,
+                },
+            }));
+        }
+        
+        self.output.end_file();
+    }
+    
+    fn on_flush(&self) {
+        self.output.flush();
+    }
+    
+    fn on_finish(&self, result: &SearchResult) {
+        let summary = summary_json(result, self.options.stats);
+        self.output.write_line(format_args!("{}", json!({ "type": "summary", "data": summary })));
+        self.output.flush();
+    }
+}
+
+/// Data of a `match` event
+pub(crate) fn match_json(path: Option<&Path>, line: &LineMatch) -> Value {
+    let submatches: Vec<Value> = line.matches.iter()
+        .map(|&(start, end)| json!({ "start": start, "end": end }))
+        .collect();
+    
+    json!({
+        "path": json_path(path),
+        "line_number": line.line_number,
+        "byte_offset": line.byte_offset,
+        "line": line.line,
+        "submatches": submatches,
+    })
+}
+
+/// Data of an `error` event
+pub(crate) fn error_json(error: &SearchError) -> Value {
+    json!({
+        "kind": error.kind.name(),
+        "path": json_path(error.path.as_deref()),
+        "message": error.message,
+    })
+}
+
+/// Data of the `summary` event, with detailed statistics if `stats` is set
+pub(crate) fn summary_json(result: &SearchResult, stats: bool) -> Value {
+    let mut summary = json!({
+        "total_matches": result.total_matches,
+        "files_searched": result.files_searched,
+        "files_with_matches": result.files_with_matches,
+        "bytes_processed": result.bytes_processed,
+        "truncated": result.truncated,
+        "timed_out": result.timed_out,
+        "timed_out_files": paths_json(&result.timed_out_files),
+        "skipped_files": paths_json(&result.skipped_files),
+        "errors": result.errors.len(),
+    });
+    if !result.nodes.is_empty() {
+        summary["partial"] = json!(result.is_partial());
+        summary["unsearched_files"] = paths_json(&result.unsearched_files);
+        summary["nodes"] = result.nodes.iter()
+            .map(|node| json!({
+                "address": node.address,
+                "state": node.state.name(),
+                "shards": node.shards,
+                "files_searched": node.files_searched,
+                "bytes_processed": node.bytes_processed,
+                "error": node.error,
+            }))
+            .collect();
+    }
+    if stats {
+        summary["stats"] = stats_json(result);
+    }
+    summary
+}
+
+/// Detailed statistics for JSON output, with times in seconds
+fn stats_json(result: &SearchResult) -> Value {
+    let elapsed = result.elapsed.as_secs_f64();
+    json!({
+        "elapsed": elapsed,
+        "discovery_time": result.discovery_time.as_secs_f64(),
+        "search_time": result.search_time.as_secs_f64(),
+        "bytes_per_second": calculate_speed(result.bytes_processed, elapsed),
+        "files_searched": result.files_searched,
+        "files_with_matches": result.files_with_matches,
+        "files_skipped": result.skipped_files.len(),
+        "files_with_errors": result.files_with_errors(),
+        "matches": result.total_matches,
+        "matched_lines": result.matched_lines,
+        "bytes_searched": result.bytes_processed,
+        "bytes_read": result.bytes_read,
+        "bytes_mapped": result.bytes_mapped,
+    })
+}
+
+/// List of paths for JSON output
+fn paths_json(paths: &[PathBuf]) -> Value {
+    paths.iter()
+        .map(|path| Value::String(path.to_string_lossy().into_owned()))
+        .collect()
+}
+
+/// Path value for JSON output, `null` for in-memory input
+fn json_path(path: Option<&Path>) -> Value {
+    match path {
+        Some(path) => Value::String(path.to_string_lossy().into_owned()),
+        None => Value::Null,
+    }
+}
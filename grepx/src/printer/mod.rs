@@ -0,0 +1,373 @@
+//! Built-in [`Sink`](crate::Sink) implementations that print results to stdout
+
+mod grep;
+mod json;
+mod text;
+
+pub use grep::GrepPrinter;
+pub use json::JsonPrinter;
+pub(crate) use json::{error_json, match_json, summary_json};
+pub use text::TextPrinter;
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::engine::SearchResult;
+use crate::error::SearchError;
+use crate::sink::Sink;
+use crate::utils::{calculate_speed, format_duration, format_size, format_speed};
+
+/// Size of a file's output buffer above which it is written out before the file is complete
+const MAX_FILE_BUFFER: usize = 1024 * 1024; // 1MB
+
+/// What the printers output for each file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Print matching lines and their context
+    #[default]
+    Lines,
+    /// Only print the names of files with matches
+    FilesWithMatches,
+    /// Print the number of matches per file
+    Count,
+}
+
+/// Options shared by the built-in printers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrinterOptions {
+    pub mode: OutputMode,
+    pub line_numbers: bool,
+    /// Suppress messages about paths that could not be searched
+    pub no_messages: bool,
+    /// Print detailed statistics once the search completes
+    pub stats: bool,
+}
+
+/// Printer that prints no results, for when only the outcome of a search
+/// matters. Errors are still reported on stderr unless suppressed.
+pub struct QuietPrinter {
+    options: PrinterOptions,
+}
+
+impl QuietPrinter {
+    /// Create a new quiet printer
+    pub fn new(options: PrinterOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl Sink for QuietPrinter {
+    fn on_error(&self, error: &SearchError) {
+        if !self.options.no_messages {
+            eprintln!("grepx: {}", error);
+        }
+    }
+}
+
+/// Output of the file being printed on the current thread
+#[derive(Default)]
+struct FileOutput {
+    /// Whether a file is being searched on this thread
+    active: bool,
+    /// Whether anything has been printed for the file
+    started: bool,
+    buffer: Vec<u8>,
+}
+
+thread_local! {
+    static FILE_OUTPUT: RefCell<FileOutput> = RefCell::new(FileOutput::default());
+}
+
+/// Reset the per-file output state when a file begins
+fn begin_file() {
+    FILE_OUTPUT.with(|output| {
+        let mut output = output.borrow_mut();
+        output.active = true;
+        output.started = false;
+        output.buffer.clear();
+    });
+}
+
+/// Mark output as started for the current file, returning true the first time
+fn start_file() -> bool {
+    FILE_OUTPUT.with(|output| !std::mem::replace(&mut output.borrow_mut().started, true))
+}
+
+/// Whether anything has been printed for the current file
+fn file_started() -> bool {
+    FILE_OUTPUT.with(|output| output.borrow().started)
+}
+
+/// Buffered output shared by the threads of a search, standard output unless
+/// a printer is given another writer.
+///
+/// Lines for a file are collected in a thread-local buffer and written out
+/// in one piece when the file ends, so output from files searched in
+/// parallel never interleaves. The writer is only locked once per file and
+/// is flushed per file when it is interactive, or when the search finishes.
+struct Output {
+    writer: Mutex<BufWriter<Box<dyn Write + Send>>>,
+    interactive: bool,
+}
+
+impl Output {
+    fn new() -> Self {
+        let stdout = io::stdout();
+        let interactive = stdout.is_terminal();
+        Self::with_writer(Box::new(stdout), interactive)
+    }
+    
+    fn with_writer(writer: Box<dyn Write + Send>, interactive: bool) -> Self {
+        Self {
+            writer: Mutex::new(BufWriter::with_capacity(64 * 1024, writer)),
+            interactive,
+        }
+    }
+    
+    /// Append a line to the current file's output, or write it directly when
+    /// no file is being searched on this thread
+    fn line(&self, args: fmt::Arguments<'_>) {
+        FILE_OUTPUT.with(|output| {
+            let mut output = output.borrow_mut();
+            if !output.active {
+                drop(output);
+                self.write_line(args);
+                return;
+            }
+            
+            let _ = output.buffer.write_fmt(args);
+            output.buffer.push(b'\n');
+            
+            // Very large outputs are written in pieces to bound memory use
+            if output.buffer.len() >= MAX_FILE_BUFFER {
+                self.write(&output.buffer);
+                output.buffer.clear();
+            }
+        });
+    }
+    
+    /// Write out the current file's output
+    fn end_file(&self) {
+        FILE_OUTPUT.with(|output| {
+            let mut output = output.borrow_mut();
+            output.active = false;
+            if !output.buffer.is_empty() {
+                self.write(&output.buffer);
+                output.buffer.clear();
+            }
+        });
+    }
+    
+    /// Write a line that does not belong to any file
+    fn write_line(&self, args: fmt::Arguments<'_>) {
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writer.write_fmt(args);
+        let _ = writer.write_all(b"\n");
+    }
+    
+    /// Write bytes under a single lock
+    fn write(&self, bytes: &[u8]) {
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writer.write_all(bytes);
+        if self.interactive {
+            let _ = writer.flush();
+        }
+    }
+    
+    /// Flush everything written so far
+    fn flush(&self) {
+        let _ = self.writer.lock().unwrap().flush();
+    }
+}
+
+/// Print detailed statistics for a completed search
+fn print_stats(output: &Output, result: &SearchResult) {
+    let elapsed = result.elapsed.as_secs_f64();
+    let speed = calculate_speed(result.bytes_processed, elapsed);
+    
+    output.write_line(format_args!("Statistics:"));
+    output.write_line(format_args!("  Elapsed:            {}", format_duration(elapsed)));
+    output.write_line(format_args!("  Discovery time:     {}", format_duration(result.discovery_time.as_secs_f64())));
+    output.write_line(format_args!("  Search time:        {}", format_duration(result.search_time.as_secs_f64())));
+    output.write_line(format_args!("  Throughput:         {}", format_speed(speed)));
+    output.write_line(format_args!("  Files searched:     {}", result.files_searched));
+    output.write_line(format_args!("  Files with matches: {}", result.files_with_matches));
+    output.write_line(format_args!("  Files skipped:      {}", result.skipped_files.len()));
+    output.write_line(format_args!("  Files with errors:  {}", result.files_with_errors()));
+    output.write_line(format_args!("  Matches:            {}", result.total_matches));
+    output.write_line(format_args!("  Matched lines:      {}", result.matched_lines));
+    output.write_line(format_args!("  Bytes searched:     {}", format_size(result.bytes_processed)));
+    output.write_line(format_args!("  Bytes read:         {}", format_size(result.bytes_read)));
+    output.write_line(format_args!("  Bytes mapped:       {}", format_size(result.bytes_mapped)));
+    if !result.nodes.is_empty() {
+        output.write_line(format_args!("  Workers:"));
+        for node in &result.nodes {
+            output.write_line(format_args!("    {} {:<8} {} shards, {} files, {}",
+                                           node.address, node.state.name(), node.shards,
+                                           node.files_searched, format_size(node.bytes_processed)));
+        }
+    }
+}
+
+/// Name used for a searched input in output
+fn display_path(path: Option<&Path>) -> Cow<'_, str> {
+    match path {
+        Some(path) => path.to_string_lossy(),
+        None => Cow::Borrowed("(standard input)"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    
+    /// Writer whose output can be read while it is still in use
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+    
+    #[test]
+    fn file_output_is_written_once_the_file_ends() {
+        let shared = Shared::default();
+        let output = Output::with_writer(Box::new(shared.clone()), true);
+        
+        begin_file();
+        output.line(format_args!("File: a"));
+        output.line(format_args!("1: match"));
+        output.flush();
+        assert_eq!(shared.text(), "");
+        
+        // Interactive output is flushed as each file ends
+        output.end_file();
+        assert_eq!(shared.text(), "File: a\n1: match\n");
+        
+        // Lines outside a file are written directly
+        output.line(format_args!("Found 1 matches"));
+        output.flush();
+        assert_eq!(shared.text(), "File: a\n1: match\nFound 1 matches\n");
+    }
+    
+    #[test]
+    fn piped_output_is_flushed_when_the_search_finishes() {
+        let shared = Shared::default();
+        let output = Output::with_writer(Box::new(shared.clone()), false);
+        
+        begin_file();
+        output.line(format_args!("1: match"));
+        output.end_file();
+        assert_eq!(shared.text(), "");
+        output.flush();
+        assert_eq!(shared.text(), "1: match\n");
+    }
+    
+    #[test]
+    fn files_printed_in_parallel_never_interleave() {
+        let shared = Shared::default();
+        let output = Arc::new(Output::with_writer(Box::new(shared.clone()), false));
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let output = output.clone();
+                std::thread::spawn(move || {
+                    for file in 0..20 {
+                        begin_file();
+                        for line in 0..50 {
+                            output.line(format_args!("{} {} {}", thread, file, line));
+                        }
+                        output.end_file();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        output.flush();
+        
+        let text = shared.text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 8 * 20 * 50);
+        for block in lines.chunks(50) {
+            let file = block[0].rsplit_once(' ').unwrap().0;
+            for (line, text) in block.iter().enumerate() {
+                assert_eq!(*text, format!("{} {}", file, line));
+            }
+        }
+    }
+    
+    #[test]
+    fn stats_are_reported_as_text_and_json() {
+        let mut result = SearchResult {
+            total_matches: 3,
+            files_searched: 4,
+            files_with_matches: 2,
+            matched_lines: 2,
+            bytes_processed: 3 * 1024,
+            bytes_read: 1024,
+            bytes_mapped: 2 * 1024,
+            discovery_time: Duration::from_millis(250),
+            search_time: Duration::from_millis(750),
+            elapsed: Duration::from_secs(1),
+            skipped_files: vec!["large".into()],
+            ..Default::default()
+        };
+        let error = |path: &str| SearchError::new(crate::error::ErrorKind::Io, Some(Path::new(path)), "failed");
+        result.errors = vec![error("a"), error("a"), error("b")];
+        
+        let shared = Shared::default();
+        let output = Output::with_writer(Box::new(shared.clone()), false);
+        print_stats(&output, &result);
+        output.flush();
+        let text = shared.text();
+        for line in [
+            "  Elapsed:            1.00 s",
+            "  Discovery time:     250.00 ms",
+            "  Search time:        750.00 ms",
+            "  Throughput:         3.00 KB/s",
+            "  Files searched:     4",
+            "  Files with matches: 2",
+            "  Files skipped:      1",
+            "  Files with errors:  2",
+            "  Matches:            3",
+            "  Matched lines:      2",
+            "  Bytes searched:     3.00 KB",
+            "  Bytes read:         1.00 KB",
+            "  Bytes mapped:       2.00 KB",
+        ] {
+            assert!(text.lines().any(|text| text == line), "missing {:?} in {}", line, text);
+        }
+        
+        assert!(json::summary_json(&result, false).get("stats").is_none());
+        let stats = &json::summary_json(&result, true)["stats"];
+        assert_eq!(stats["elapsed"], 1.0);
+        assert_eq!(stats["discovery_time"], 0.25);
+        assert_eq!(stats["search_time"], 0.75);
+        assert_eq!(stats["bytes_per_second"], 3072.0);
+        assert_eq!(stats["files_skipped"], 1);
+        assert_eq!(stats["files_with_errors"], 2);
+        assert_eq!(stats["matched_lines"], 2);
+        assert_eq!(stats["bytes_read"], 1024);
+        assert_eq!(stats["bytes_mapped"], 2048);
+    }
+}
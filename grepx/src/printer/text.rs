@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::{begin_file, display_path, file_started, print_stats, start_file, Output, OutputMode, PrinterOptions};
+use crate::distributed::NodeState;
+use crate::engine::{ContextLine, FileStats, LineMatch, SearchResult};
+use crate::error::{ErrorKind, SearchError};
+use crate::sink::Sink;
+
+/// Human-readable printer grouping matches under a header per file
+pub struct TextPrinter {
+    options: PrinterOptions,
+    output: Output,
+}
+
+impl TextPrinter {
+    /// Create a new text printer
+    pub fn new(options: PrinterOptions) -> Self {
+        Self {
+            options,
+            output: Output::new(),
+        }
+    }
+    
+    /// Whether individual lines are printed
+    fn prints_lines(&self) -> bool {
+        self.options.mode == OutputMode::Lines && self.options.line_numbers
+    }
+    
+    /// Print a line, preceded by the file header if it is the first for the file
+    fn print_line(&self, path: Option<&Path>, line_number: usize, separator: char, line: &str) {
+        if start_file() {
+            self.output.line(format_args!("File: {}", display_path(path)));
+        }
+        self.output.line(format_args!("{}{} {}", line_number, separator, line));
+    }
+    
+    /// Print the errors of the search to stderr, grouped by kind
+    fn print_errors(&self, errors: &[SearchError]) {
+        let mut groups: BTreeMap<ErrorKind, Vec<&SearchError>> = BTreeMap::new();
+        for error in errors {
+            groups.entry(error.kind).or_default().push(error);
+        }
+        
+        eprintln!("{} errors while searching:", errors.len());
+        for (kind, errors) in groups {
+            eprintln!("  {} ({}):", kind.description(), errors.len());
+            for error in errors {
+                eprintln!("    {}", error);
+            }
+        }
+    }
+}
+
+impl Sink for TextPrinter {
+    fn on_file_begin(&self, _path: Option<&Path>) {
+        begin_file();
+    }
+    
+    fn on_match(&self, path: Option<&Path>, line: &LineMatch) {
+        if self.prints_lines() {
+            self.print_line(path, line.line_number, ':', &line.line);
+        }
+    }
+    
+    fn on_context(&self, path: Option<&Path>, line: &ContextLine) {
+        if self.prints_lines() {
+            self.print_line(path, line.line_number, '-', &line.line);
+        }
+    }
+    
+    fn on_context_break(&self, _path: Option<&Path>) {
+        if self.prints_lines() && file_started() {
+            self.output.line(format_args!("--"));
+        }
+    }
+    
+    fn on_file_end(&self, path: Option<&Path>, stats: &FileStats) {
+        match self.options.mode {
+            OutputMode::Lines if self.options.line_numbers => {
+                if file_started() {
+                    self.output.line(format_args!(""));
+                }
+            }
+            OutputMode::Lines => {
+                if stats.matches > 0 {
+                    self.output.line(format_args!("Found {} matches in {}", stats.matches, display_path(path)));
+                }
+            }
+            OutputMode::FilesWithMatches => {
+                if stats.matches > 0 {
+                    self.output.line(format_args!("{}", display_path(path)));
+                }
+            }
+            OutputMode::Count => {
+                if stats.matches > 0 {
+                    self.output.line(format_args!("{}: {}", display_path(path), stats.matches));
+                }
+            }
+        }
+        
+        self.output.end_file();
+    }
+    
+    fn on_flush(&self) {
+        self.output.flush();
+    }
+    
+    fn on_finish(&self, result: &SearchResult) {
+        if result.total_matches > 0 {
+            let truncated = if result.truncated { " (results truncated)" } else { "" };
+            self.output.write_line(format_args!("Found {} matches in {} files{}",
+                                                result.total_matches,
+                                                result.files_searched,
+                                                truncated));
+        }
+        
+        if result.timed_out {
+            self.output.write_line(format_args!("Search timed out before all files were searched"));
+        }
+        for path in &result.timed_out_files {
+            self.output.write_line(format_args!("Timed out, partially searched: {}", path.display()));
+        }
+        if !result.skipped_files.is_empty() {
+            self.output.write_line(format_args!("Skipped {} files over the size limit",
+                                                result.skipped_files.len()));
+        }
+        for node in &result.nodes {
+            match node.state {
+                NodeState::Complete => {}
+                NodeState::Partial => self.output.write_line(format_args!("Worker {} failed after searching {} files",
+                                                                          node.address, node.files_searched)),
+                NodeState::Failed => self.output.write_line(format_args!("Worker {} failed before searching", node.address)),
+            }
+        }
+        if !result.unsearched_files.is_empty() {
+            self.output.write_line(format_args!("Partial results: {} files on failed workers were not searched",
+                                                result.unsearched_files.len()));
+        }
+        if self.options.stats {
+            print_stats(&self.output, result);
+        }
+        
+        self.output.flush();
+        
+        if !result.errors.is_empty() && !self.options.no_messages {
+            self.print_errors(&result.errors);
+        }
+    }
+}
\ No newline at end of file
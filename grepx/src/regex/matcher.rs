@@ -1,7 +1,34 @@
-use anyhow::{Result, Context};
-use regex::{Regex, RegexBuilder};
+use anyhow::Result;
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::ast::{self, Ast, ClassSet, ClassSetItem};
+use regex_syntax::hir::{Class, ClassBytes, ClassBytesRange, ClassUnicode, ClassUnicodeRange, Hir, HirKind};
+use regex_syntax::ParserBuilder;
 use std::borrow::Cow;
 
+use crate::error::{ErrorKind, SearchError};
+
+/// How letter case is treated when matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseMode {
+    /// Match case exactly
+    Sensitive,
+    /// Ignore case
+    Insensitive,
+    /// Ignore case unless the pattern contains an uppercase literal
+    Smart,
+}
+
+impl CaseMode {
+    /// Whether case is ignored when matching the given pattern
+    pub(crate) fn is_insensitive(self, pattern: &str) -> bool {
+        match self {
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+            CaseMode::Smart => !has_uppercase_literal(pattern),
+        }
+    }
+}
+
 /// A match found in the text
 #[derive(Debug, Clone)]
 pub struct Match<'a> {
@@ -18,15 +45,32 @@ pub struct RegexMatcher {
 }
 
 impl RegexMatcher {
-    /// Create a new RegexMatcher with the given pattern
-    pub fn new(pattern: &str, case_sensitive: bool) -> Result<Self> {
-        // Build regex with appropriate options
-        let regex = RegexBuilder::new(pattern)
-            .case_insensitive(!case_sensitive)
+    /// Create a new RegexMatcher with the given pattern.
+    ///
+    /// Text is searched a line at a time, so the pattern is rewritten to never
+    /// match a line terminator: classes such as `\s` or `[^x]` leave out `\n`,
+    /// and a pattern with a literal `\n` is rejected.
+    pub fn new(pattern: &str, case_mode: CaseMode) -> Result<Self> {
+        let case_insensitive = case_mode.is_insensitive(pattern);
+        let error = |message: String| SearchError::new(
+            ErrorKind::Regex,
+            None,
+            format!("Failed to compile regex pattern: {}: {}", pattern, message),
+        );
+        
+        let hir = ParserBuilder::new()
+            .case_insensitive(case_insensitive)
             .multi_line(true)
             .dot_matches_new_line(false)
             .build()
-            .with_context(|| format!("Failed to compile regex pattern: {}", pattern))?;
+            .parse(pattern)
+            .map_err(|e| error(e.to_string()))?;
+        let hir = without_newlines(hir).map_err(error)?;
+        
+        // The rewritten pattern carries its flags, so it is compiled as is
+        let regex = RegexBuilder::new(&hir.to_string())
+            .build()
+            .map_err(|e| error(e.to_string()))?;
         
         Ok(Self {
             regex,
@@ -39,22 +83,19 @@ impl RegexMatcher {
         &self.pattern
     }
     
-    /// Find all matches in the given text
+    /// Find all matches in the given text. Text that is not valid UTF-8 is
+    /// searched too; invalid bytes never match, and the text of matches is
+    /// decoded lossily.
     pub fn find_matches<'a>(&self, text: &'a [u8], with_line_numbers: bool) -> Vec<Match<'a>> {
-        let text_str = match std::str::from_utf8(text) {
-            Ok(s) => s,
-            Err(_) => return Vec::new(), // Skip non-UTF8 content
-        };
-        
         // Line number mapping (only if needed)
         let line_map = if with_line_numbers {
-            self.create_line_map(text_str)
+            self.create_line_map(text)
         } else {
             Vec::new()
         };
         
         // Find all regex matches
-        self.regex.find_iter(text_str)
+        self.regex.find_iter(text)
             .map(|m| {
                 let line_number = if with_line_numbers {
                     self.find_line_number(&line_map, m.start())
@@ -63,7 +104,7 @@ impl RegexMatcher {
                 };
                 
                 Match {
-                    text: Cow::Borrowed(&text_str[m.start()..m.end()]),
+                    text: String::from_utf8_lossy(m.as_bytes()),
                     line_number,
                     byte_offset: m.start(),
                     byte_length: m.end() - m.start(),
@@ -73,16 +114,10 @@ impl RegexMatcher {
     }
     
     /// Create a map of newline positions for line number calculations
-    fn create_line_map(&self, text: &str) -> Vec<usize> {
+    fn create_line_map(&self, text: &[u8]) -> Vec<usize> {
         let mut positions = Vec::new();
         positions.push(0); // First line starts at position 0
-        
-        for (i, c) in text.char_indices() {
-            if c == '\n' {
-                positions.push(i + 1);
-            }
-        }
-        
+        positions.extend(memchr::memchr_iter(b'\n', text).map(|i| i + 1));
         positions
     }
     
@@ -102,19 +137,162 @@ impl RegexMatcher {
     
     /// Check if the text contains any match
     pub fn is_match(&self, text: &[u8]) -> bool {
-        if let Ok(text_str) = std::str::from_utf8(text) {
-            self.regex.is_match(text_str)
-        } else {
-            false
-        }
+        self.regex.is_match(text)
     }
     
     /// Count the number of matches in the text
     pub fn match_count(&self, text: &[u8]) -> usize {
-        if let Ok(text_str) = std::str::from_utf8(text) {
-            self.regex.find_iter(text_str).count()
-        } else {
-            0
+        self.regex.find_iter(text).count()
+    }
+}
+
+/// Rewrite a pattern so that it cannot match `\n`, failing if it contains a
+/// literal `\n` that no line could ever match
+fn without_newlines(hir: Hir) -> Result<Hir, String> {
+    let hir = match hir.into_kind() {
+        HirKind::Empty => Hir::empty(),
+        HirKind::Literal(literal) => {
+            if literal.0.contains(&b'\n') {
+                return Err("the literal '\\n' is not allowed, as lines are matched one at a time".to_string());
+            }
+            Hir::literal(literal.0)
+        }
+        HirKind::Class(Class::Unicode(mut class)) => {
+            class.difference(&ClassUnicode::new([ClassUnicodeRange::new('\n', '\n')]));
+            Hir::class(Class::Unicode(class))
+        }
+        HirKind::Class(Class::Bytes(mut class)) => {
+            class.difference(&ClassBytes::new([ClassBytesRange::new(b'\n', b'\n')]));
+            Hir::class(Class::Bytes(class))
+        }
+        HirKind::Look(look) => Hir::look(look),
+        HirKind::Repetition(mut repetition) => {
+            repetition.sub = Box::new(without_newlines(*repetition.sub)?);
+            Hir::repetition(repetition)
+        }
+        HirKind::Capture(mut capture) => {
+            capture.sub = Box::new(without_newlines(*capture.sub)?);
+            Hir::capture(capture)
+        }
+        HirKind::Concat(subs) => Hir::concat(subs.into_iter().map(without_newlines).collect::<Result<_, _>>()?),
+        HirKind::Alternation(subs) => Hir::alternation(subs.into_iter().map(without_newlines).collect::<Result<_, _>>()?),
+    };
+    Ok(hir)
+}
+
+/// Check whether the pattern contains an uppercase literal character.
+///
+/// The check walks the parsed AST rather than the raw pattern text, so escapes
+/// such as `\W` or `\S` are not mistaken for uppercase literals. Patterns that
+/// fail to parse are treated as having no uppercase literals; the regex
+/// builder reports the actual error.
+fn has_uppercase_literal(pattern: &str) -> bool {
+    match ast::parse::Parser::new().parse(pattern) {
+        Ok(ast) => ast_has_uppercase(&ast),
+        Err(_) => false,
+    }
+}
+
+fn ast_has_uppercase(ast: &Ast) -> bool {
+    match ast {
+        Ast::Literal(lit) => lit.c.is_uppercase(),
+        Ast::ClassBracketed(class) => class_set_has_uppercase(&class.kind),
+        Ast::Repetition(rep) => ast_has_uppercase(&rep.ast),
+        Ast::Group(group) => ast_has_uppercase(&group.ast),
+        Ast::Alternation(alt) => alt.asts.iter().any(ast_has_uppercase),
+        Ast::Concat(concat) => concat.asts.iter().any(ast_has_uppercase),
+        _ => false,
+    }
+}
+
+fn class_set_has_uppercase(set: &ClassSet) -> bool {
+    match set {
+        ClassSet::Item(item) => class_item_has_uppercase(item),
+        ClassSet::BinaryOp(op) => {
+            class_set_has_uppercase(&op.lhs) || class_set_has_uppercase(&op.rhs)
         }
     }
-} 
\ No newline at end of file
+}
+
+fn class_item_has_uppercase(item: &ClassSetItem) -> bool {
+    match item {
+        ClassSetItem::Literal(lit) => lit.c.is_uppercase(),
+        ClassSetItem::Range(range) => {
+            range.start.c.is_uppercase() || range.end.c.is_uppercase()
+        }
+        ClassSetItem::Bracketed(class) => class_set_has_uppercase(&class.kind),
+        ClassSetItem::Union(union) => union.items.iter().any(class_item_has_uppercase),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn smart_case_ignores_case_without_uppercase_literals() {
+        assert!(CaseMode::Smart.is_insensitive("hello"));
+        assert!(CaseMode::Smart.is_insensitive(r"\W\S\D\p{Lu}"));
+        assert!(CaseMode::Smart.is_insensitive("[a-z]+"));
+        assert!(CaseMode::Smart.is_insensitive("(unclosed"));
+    }
+    
+    #[test]
+    fn smart_case_respects_uppercase_literals() {
+        assert!(!CaseMode::Smart.is_insensitive("Hello"));
+        assert!(!CaseMode::Smart.is_insensitive("foo|BAR"));
+        assert!(!CaseMode::Smart.is_insensitive("[A-Z]"));
+        assert!(!CaseMode::Smart.is_insensitive("x(?:Y)*"));
+    }
+    
+    #[test]
+    fn matches_never_cross_lines() {
+        let text = b"foo\nHELLO\nfoo HELLO\n";
+        let matcher = RegexMatcher::new(r"\Whello", CaseMode::Smart).unwrap();
+        let matches = matcher.find_matches(text, false);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].text, " HELLO");
+        
+        let matcher = RegexMatcher::new(r"[^x]+", CaseMode::Sensitive).unwrap();
+        let lines: Vec<_> = matcher.find_matches(b"ab\ncd\n", false)
+            .into_iter()
+            .map(|m| m.text.into_owned())
+            .collect();
+        assert_eq!(lines, ["ab", "cd"]);
+        
+        let matcher = RegexMatcher::new(r"a\s*b", CaseMode::Sensitive).unwrap();
+        assert_eq!(matcher.match_count(b"a\nb\na \tb\n"), 1);
+        let matcher = RegexMatcher::new(r"(?s)a.b", CaseMode::Sensitive).unwrap();
+        assert_eq!(matcher.match_count(b"a\nb\naxb\n"), 1);
+    }
+    
+    #[test]
+    fn invalid_utf8_does_not_stop_matching() {
+        let matcher = RegexMatcher::new("hello", CaseMode::Sensitive).unwrap();
+        let matches = matcher.find_matches(b"caf\xe9 hello\n\xff\xfe\nhello again\n", true);
+        let found: Vec<_> = matches.iter().map(|m| (m.line_number, m.byte_offset)).collect();
+        assert_eq!(found, [(Some(1), 5), (Some(3), 14)]);
+        assert!(!matcher.is_match(b"\xff"));
+        assert_eq!(RegexMatcher::new(".", CaseMode::Sensitive).unwrap().match_count(b"\xffa\xfe"), 1);
+    }
+    
+    #[test]
+    fn literal_newlines_are_rejected() {
+        for pattern in [r"foo\nbar", r"(foo)\n+", "x\ny"] {
+            let error = RegexMatcher::new(pattern, CaseMode::Sensitive).err().unwrap();
+            assert_eq!(error.to_string(), format!("Failed to compile regex pattern: {}: the literal '\\n' is not allowed, as lines are matched one at a time", pattern));
+        }
+        assert!(RegexMatcher::new(r"^$", CaseMode::Sensitive).is_ok());
+        assert!(RegexMatcher::new(r"[^\n]+", CaseMode::Sensitive).is_ok());
+    }
+    
+    #[test]
+    fn case_modes_match_accordingly() {
+        let text = b"Hello\nhello\n";
+        assert_eq!(RegexMatcher::new("hello", CaseMode::Sensitive).unwrap().match_count(text), 1);
+        assert_eq!(RegexMatcher::new("hello", CaseMode::Insensitive).unwrap().match_count(text), 2);
+        assert_eq!(RegexMatcher::new("hello", CaseMode::Smart).unwrap().match_count(text), 2);
+        assert_eq!(RegexMatcher::new("Hello", CaseMode::Smart).unwrap().match_count(text), 1);
+    }
+}
@@ -1,3 +1,3 @@
 mod matcher;
 
-pub use matcher::RegexMatcher; 
\ No newline at end of file
+pub use matcher::{CaseMode, Match, RegexMatcher}; 
\ No newline at end of file
@@ -0,0 +1,373 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+use crate::engine::{ContextKind, ContextLine, FileStats, LineMatch};
+use crate::error::SearchError;
+use crate::sink::Sink;
+
+/// A search event recorded for later delivery to a sink
+enum Event {
+    FileBegin,
+    Match(LineMatch),
+    Context(ContextLine),
+    ContextBreak,
+    Error(SearchError),
+    FileEnd(FileStats),
+}
+
+/// Sink that records the events of a single file so they can be replayed later
+pub(crate) struct FileBuffer {
+    path: Option<PathBuf>,
+    events: Mutex<Vec<Event>>,
+    bytes: AtomicUsize,
+}
+
+impl FileBuffer {
+    pub fn new(path: Option<&Path>) -> Self {
+        Self {
+            path: path.map(Path::to_path_buf),
+            events: Mutex::new(Vec::new()),
+            bytes: AtomicUsize::new(0),
+        }
+    }
+    
+    fn record(&self, event: Event, bytes: usize) {
+        self.events.lock().unwrap().push(event);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+    
+    /// Approximate memory held by the recorded events
+    fn size(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+    
+    /// Deliver the recorded events to the sink in their original order
+    pub fn replay<S: Sink + ?Sized>(self, sink: &S) {
+        let path = self.path.as_deref();
+        for event in self.events.into_inner().unwrap() {
+            deliver(sink, path, event);
+        }
+    }
+    
+    /// Deliver the recorded events to the sink as long as `accept` allows
+    /// each matching line, then end the file with `stats`. The first line
+    /// refused and everything after it are dropped along with their context,
+    /// and `stats` is reduced to the lines delivered.
+    pub fn replay_limited<S: Sink + ?Sized>(self, sink: &S, mut stats: FileStats, mut accept: impl FnMut() -> bool) -> FileStats {
+        let path = self.path.as_deref();
+        // Context breaks and before-context lines are held until the line they lead up to is accepted
+        let mut held = Vec::new();
+        let mut refused = false;
+        let (mut matches, mut matched_lines) = (0, 0);
+        for event in self.events.into_inner().unwrap() {
+            match event {
+                Event::Match(_) | Event::Context(_) | Event::ContextBreak if refused => {}
+                Event::Match(line) => {
+                    if !accept() {
+                        refused = true;
+                        continue;
+                    }
+                    for event in held.drain(..) {
+                        deliver(sink, path, event);
+                    }
+                    matches += line.matches.len();
+                    matched_lines += 1;
+                    sink.on_match(path, &line);
+                }
+                Event::Context(ContextLine { kind: ContextKind::Before, .. }) | Event::ContextBreak => held.push(event),
+                Event::FileEnd(_) => {}
+                event => deliver(sink, path, event),
+            }
+        }
+        
+        if refused {
+            stats.matches = matches;
+            stats.matched_lines = matched_lines;
+        }
+        sink.on_file_end(path, &stats);
+        stats
+    }
+}
+
+fn deliver<S: Sink + ?Sized>(sink: &S, path: Option<&Path>, event: Event) {
+    match event {
+        Event::FileBegin => sink.on_file_begin(path),
+        Event::Match(line) => sink.on_match(path, &line),
+        Event::Context(line) => sink.on_context(path, &line),
+        Event::ContextBreak => sink.on_context_break(path),
+        Event::Error(error) => sink.on_error(&error),
+        Event::FileEnd(stats) => sink.on_file_end(path, &stats),
+    }
+}
+
+impl Sink for FileBuffer {
+    fn on_file_begin(&self, _path: Option<&Path>) {
+        self.record(Event::FileBegin, 0);
+    }
+    
+    fn on_match(&self, _path: Option<&Path>, line: &LineMatch) {
+        let bytes = std::mem::size_of::<LineMatch>() + line.line.len();
+        self.record(Event::Match(line.clone()), bytes);
+    }
+    
+    fn on_context(&self, _path: Option<&Path>, line: &ContextLine) {
+        let bytes = std::mem::size_of::<ContextLine>() + line.line.len();
+        self.record(Event::Context(line.clone()), bytes);
+    }
+    
+    fn on_context_break(&self, _path: Option<&Path>) {
+        self.record(Event::ContextBreak, 0);
+    }
+    
+    fn on_error(&self, error: &SearchError) {
+        let bytes = std::mem::size_of::<SearchError>() + error.message.len();
+        self.record(Event::Error(error.clone()), bytes);
+    }
+    
+    fn on_file_end(&self, _path: Option<&Path>, stats: &FileStats) {
+        self.record(Event::FileEnd(stats.clone()), 0);
+    }
+}
+
+/// How a worker should report the events of the file it is about to search
+pub(crate) enum Slot {
+    /// Write events straight to the sink
+    Direct,
+    /// Record events in a [`FileBuffer`] until earlier files are complete
+    Buffered,
+}
+
+/// Delivers per-file results to a sink in file order, even though files are
+/// searched in parallel.
+///
+/// The file at the head of the order streams directly to the sink, and files
+/// that complete ahead of it are buffered until it is done. Workers wait before
+/// starting a file that is more than `window` files ahead of the head. If the
+/// buffered results exceed `max_buffered` bytes, ordering is abandoned and all
+/// results are streamed as soon as they are available.
+pub(crate) struct Reorder<'s, S: Sink + ?Sized> {
+    sink: &'s S,
+    window: usize,
+    max_buffered: usize,
+    state: Mutex<ReorderState>,
+    advanced: Condvar,
+}
+
+struct ReorderState {
+    /// Index of the next file to deliver
+    next: usize,
+    /// Completed files waiting for earlier ones
+    pending: BTreeMap<usize, FileBuffer>,
+    buffered_bytes: usize,
+    /// Whether ordering was abandoned because of the memory limit
+    streaming: bool,
+}
+
+impl<'s, S: Sink + ?Sized> Reorder<'s, S> {
+    pub fn new(sink: &'s S, window: usize, max_buffered: usize) -> Self {
+        Self {
+            sink,
+            window: window.max(1),
+            max_buffered,
+            state: Mutex::new(ReorderState {
+                next: 0,
+                pending: BTreeMap::new(),
+                buffered_bytes: 0,
+                streaming: false,
+            }),
+            advanced: Condvar::new(),
+        }
+    }
+    
+    /// Wait until the file at `index` is inside the reorder window and decide
+    /// how its events should be reported
+    pub fn acquire(&self, index: usize) -> Slot {
+        let mut state = self.state.lock().unwrap();
+        while !state.streaming && index >= state.next + self.window {
+            state = self.advanced.wait(state).unwrap();
+        }
+        
+        if state.streaming || index == state.next {
+            Slot::Direct
+        } else {
+            Slot::Buffered
+        }
+    }
+    
+    /// Mark the file at `index` as complete, delivering any results that are
+    /// now in order. `buffer` holds the file's events if it was buffered.
+    pub fn complete(&self, index: usize, buffer: Option<FileBuffer>) {
+        let mut state = self.state.lock().unwrap();
+        
+        if state.streaming {
+            if let Some(buffer) = buffer {
+                buffer.replay(self.sink);
+            }
+            return;
+        }
+        
+        match buffer {
+            Some(buffer) => {
+                state.buffered_bytes += buffer.size();
+                state.pending.insert(index, buffer);
+            }
+            None => state.next = index + 1,
+        }
+        
+        // Deliver every buffered file that is now at the head of the order
+        loop {
+            let next = state.next;
+            match state.pending.remove(&next) {
+                Some(buffer) => {
+                    state.buffered_bytes -= buffer.size();
+                    buffer.replay(self.sink);
+                    state.next += 1;
+                }
+                None => break,
+            }
+        }
+        
+        if state.buffered_bytes > self.max_buffered {
+            warn!("Reorder buffer exceeded {} bytes, streaming remaining results unordered",
+                  self.max_buffered);
+            state.streaming = true;
+            for (_, buffer) in std::mem::take(&mut state.pending) {
+                buffer.replay(self.sink);
+            }
+            state.buffered_bytes = 0;
+        }
+        
+        self.advanced.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    
+    /// Sink recording the paths of the files it was given, and their lines, in order
+    #[derive(Default)]
+    struct Recorder {
+        files: Mutex<Vec<String>>,
+        lines: Mutex<Vec<String>>,
+    }
+    
+    impl Sink for Recorder {
+        fn on_match(&self, _path: Option<&Path>, line: &LineMatch) {
+            self.lines.lock().unwrap().push(line.line.clone());
+        }
+        
+        fn on_context(&self, _path: Option<&Path>, line: &ContextLine) {
+            self.lines.lock().unwrap().push(line.line.clone());
+        }
+        
+        fn on_file_end(&self, path: Option<&Path>, _stats: &FileStats) {
+            self.files.lock().unwrap().push(path.unwrap().display().to_string());
+        }
+    }
+    
+    /// Buffer the events of a file with one matching line
+    fn buffered(name: &str) -> FileBuffer {
+        let path = Path::new(name);
+        let buffer = FileBuffer::new(Some(path));
+        buffer.on_file_begin(Some(path));
+        buffer.on_match(Some(path), &LineMatch {
+            line_number: 1,
+            byte_offset: 0,
+            line: "match".to_string(),
+            matches: vec![(0, 5)],
+        });
+        buffer.on_file_end(Some(path), &FileStats::default());
+        buffer
+    }
+    
+    #[test]
+    fn files_completed_out_of_order_are_delivered_in_order() {
+        let recorder = Recorder::default();
+        let reorder = Reorder::new(&recorder, 8, usize::MAX);
+        
+        for index in [3, 1, 2] {
+            assert!(matches!(reorder.acquire(index), Slot::Buffered));
+            reorder.complete(index, Some(buffered(&index.to_string())));
+        }
+        assert!(recorder.files.lock().unwrap().is_empty());
+        
+        assert!(matches!(reorder.acquire(0), Slot::Direct));
+        recorder.on_file_end(Some(Path::new("0")), &FileStats::default());
+        reorder.complete(0, None);
+        assert_eq!(*recorder.files.lock().unwrap(), ["0", "1", "2", "3"]);
+    }
+    
+    #[test]
+    fn ordering_is_abandoned_over_the_memory_limit() {
+        let recorder = Recorder::default();
+        let reorder = Reorder::new(&recorder, 8, 1);
+        
+        reorder.acquire(2);
+        reorder.complete(2, Some(buffered("2")));
+        assert_eq!(*recorder.files.lock().unwrap(), ["2"]);
+        assert!(matches!(reorder.acquire(1), Slot::Direct));
+    }
+    
+    #[test]
+    fn files_beyond_the_window_wait_for_the_head() {
+        let recorder = Recorder::default();
+        let reorder = Reorder::new(&recorder, 2, usize::MAX);
+        
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| {
+                reorder.acquire(2);
+                reorder.complete(2, Some(buffered("2")));
+            });
+            thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!waiting.is_finished());
+            
+            reorder.acquire(0);
+            reorder.complete(0, Some(buffered("0")));
+            reorder.acquire(1);
+            reorder.complete(1, Some(buffered("1")));
+            waiting.join().unwrap();
+        });
+        assert_eq!(*recorder.files.lock().unwrap(), ["0", "1", "2"]);
+    }
+    
+    #[test]
+    fn limited_replay_drops_refused_lines_and_their_context() {
+        let path = Path::new("limited");
+        let buffer = FileBuffer::new(Some(path));
+        let line = |line_number: usize, line: &str| LineMatch {
+            line_number,
+            byte_offset: 0,
+            line: line.to_string(),
+            matches: vec![(0, 1)],
+        };
+        let context = |line_number: usize, line: &str, kind: ContextKind| ContextLine {
+            line_number,
+            byte_offset: 0,
+            line: line.to_string(),
+            kind,
+        };
+        buffer.on_file_begin(Some(path));
+        buffer.on_match(Some(path), &line(1, "first"));
+        buffer.on_context(Some(path), &context(2, "after first", ContextKind::After));
+        buffer.on_context_break(Some(path));
+        buffer.on_context(Some(path), &context(9, "before second", ContextKind::Before));
+        buffer.on_match(Some(path), &line(10, "second"));
+        buffer.on_context(Some(path), &context(11, "after second", ContextKind::After));
+        
+        let recorder = Recorder::default();
+        let mut allowed = 1;
+        let stats = FileStats { matches: 2, matched_lines: 2, ..FileStats::default() };
+        let stats = buffer.replay_limited(&recorder, stats, || {
+            allowed -= 1;
+            allowed >= 0
+        });
+        assert_eq!((stats.matches, stats.matched_lines), (1, 1));
+        assert_eq!(*recorder.lines.lock().unwrap(), ["first", "after first"]);
+        assert_eq!(*recorder.files.lock().unwrap(), ["limited"]);
+    }
+}
@@ -0,0 +1,728 @@
+use anyhow::{bail, Result, Context};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::distributed::coordinator;
+use crate::distributed::protocol::{Manifest, ManifestFile, Request, RequestKind};
+use crate::engine::{self, SearchResult, SearchedFiles};
+use crate::index::{Candidates, Index};
+use crate::io::file_discovery::{self, SortBy};
+use crate::io::strategy::ReadStrategy;
+use crate::io::watch::Watcher;
+use crate::pool::ThreadPools;
+use crate::regex::{CaseMode, RegexMatcher};
+use crate::sink::{Collector, Sink};
+
+/// Predicate deciding whether a discovered file should be searched
+pub type FileFilter = Arc<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// Options controlling how a search is executed
+#[derive(Clone)]
+pub(crate) struct SearchOptions {
+    pub recursive: bool,
+    pub threads: usize,
+    pub io_threads: usize,
+    pub chunk_size: Option<usize>,
+    pub read_strategy: Option<ReadStrategy>,
+    pub prefetch: bool,
+    pub progress: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub sort_by: SortBy,
+    pub ordered: bool,
+    pub reorder_window: usize,
+    pub max_buffered: usize,
+    pub quit_after_match: bool,
+    pub max_count: Option<usize>,
+    pub max_total: Option<usize>,
+    pub max_files: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub file_timeout: Option<Duration>,
+    pub max_filesize: Option<u64>,
+    pub filter: Option<FileFilter>,
+    pub workers: Vec<String>,
+    pub worker_timeout: Duration,
+    pub worker_secret: Option<Vec<u8>>,
+    pub worker_tls_ca: Option<PathBuf>,
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Indexed files the pattern may match, set when the searcher is built
+    pub candidates: Option<Arc<Candidates>>,
+}
+
+/// Builder for configuring a [`Searcher`]
+#[derive(Clone)]
+pub struct SearcherBuilder {
+    pattern: String,
+    case_mode: CaseMode,
+    index: Option<Arc<Index>>,
+    options: SearchOptions,
+}
+
+impl SearcherBuilder {
+    /// Create a new builder for the given regex pattern
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            case_mode: CaseMode::Sensitive,
+            index: None,
+            options: SearchOptions {
+                recursive: false,
+                threads: 0,
+                io_threads: 0,
+                chunk_size: None,
+                read_strategy: None,
+                prefetch: true,
+                progress: false,
+                before_context: 0,
+                after_context: 0,
+                sort_by: SortBy::Discovery,
+                ordered: true,
+                reorder_window: 1024,
+                max_buffered: 64 * 1024 * 1024,
+                quit_after_match: false,
+                max_count: None,
+                max_total: None,
+                max_files: None,
+                timeout: None,
+                file_timeout: None,
+                max_filesize: None,
+                filter: None,
+                workers: Vec::new(),
+                worker_timeout: Duration::from_secs(10),
+                worker_secret: None,
+                worker_tls_ca: None,
+                cancel: None,
+                candidates: None,
+            },
+        }
+    }
+    
+    /// Set how letter case is treated when matching
+    pub fn case_mode(mut self, case_mode: CaseMode) -> Self {
+        self.case_mode = case_mode;
+        self
+    }
+    
+    /// Recursively search directories
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.options.recursive = recursive;
+        self
+    }
+    
+    /// Number of threads searching file contents (0 = auto)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        self
+    }
+    
+    /// Number of threads walking directories and reading file metadata (0 = auto)
+    pub fn io_threads(mut self, threads: usize) -> Self {
+        self.options.io_threads = threads;
+        self
+    }
+    
+    /// Chunk size in KB for processing large files. By default it is chosen
+    /// per file from the file size and the number of threads.
+    pub fn chunk_size(mut self, chunk_size: Option<usize>) -> Self {
+        self.options.chunk_size = chunk_size.map(|kb| kb.max(1));
+        self
+    }
+    
+    /// How file contents are read. By default it is chosen per file from the
+    /// file size and the measured read throughput.
+    pub fn read_strategy(mut self, strategy: Option<ReadStrategy>) -> Self {
+        self.options.read_strategy = strategy;
+        self
+    }
+    
+    /// Ask the operating system to read upcoming files and chunks into memory
+    /// while earlier ones are searched (the default)
+    pub fn prefetch(mut self, prefetch: bool) -> Self {
+        self.options.prefetch = prefetch;
+        self
+    }
+    
+    /// Display a progress bar while searching files
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.options.progress = progress;
+        self
+    }
+    
+    /// Number of lines of context to report before each match
+    pub fn before_context(mut self, lines: usize) -> Self {
+        self.options.before_context = lines;
+        self
+    }
+    
+    /// Number of lines of context to report after each match
+    pub fn after_context(mut self, lines: usize) -> Self {
+        self.options.after_context = lines;
+        self
+    }
+    
+    /// Order in which files are searched and their results reported
+    pub fn sort(mut self, sort_by: SortBy) -> Self {
+        self.options.sort_by = sort_by;
+        self
+    }
+    
+    /// Report results in file order (the default). When disabled, results are
+    /// streamed as soon as each file has been searched.
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.options.ordered = ordered;
+        self
+    }
+    
+    /// Maximum number of files that may be searched ahead of the next file to be reported
+    pub fn reorder_window(mut self, files: usize) -> Self {
+        self.options.reorder_window = files;
+        self
+    }
+    
+    /// Maximum bytes of results buffered for ordering before falling back to
+    /// unordered streaming
+    pub fn max_buffered(mut self, bytes: usize) -> Self {
+        self.options.max_buffered = bytes;
+        self
+    }
+    
+    /// Stop the whole search as soon as any match is found
+    pub fn quit_after_match(mut self, quit: bool) -> Self {
+        self.options.quit_after_match = quit;
+        self
+    }
+    
+    /// Stop searching a file after this many matching lines
+    pub fn max_count(mut self, lines: Option<usize>) -> Self {
+        self.options.max_count = lines;
+        self
+    }
+    
+    /// Stop the whole search after this many matching lines across all files
+    pub fn max_total(mut self, lines: Option<usize>) -> Self {
+        self.options.max_total = lines;
+        self
+    }
+    
+    /// Stop the search once this many files have matches
+    pub fn max_files(mut self, files: Option<usize>) -> Self {
+        self.options.max_files = files;
+        self
+    }
+    
+    /// Stop the whole search once this much time has elapsed
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+    
+    /// Stop the search early once this flag is set, e.g. from another thread.
+    /// Files being searched are abandoned and no new files are started.
+    pub fn cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+    
+    /// Abandon a file once searching it has taken this long, reporting it as
+    /// partially searched
+    pub fn file_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.file_timeout = timeout;
+        self
+    }
+    
+    /// Skip files larger than this many bytes
+    pub fn max_filesize(mut self, bytes: Option<u64>) -> Self {
+        self.options.max_filesize = bytes;
+        self
+    }
+    
+    /// Only search files for which the predicate returns true. Errors found
+    /// while discovering paths it rejects are not reported either.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Path) -> bool + Send + Sync + 'static,
+    {
+        self.options.filter = Some(Arc::new(filter));
+        self
+    }
+    
+    /// Run searches of paths on remote workers at these addresses (`host:port`)
+    /// instead of locally, each worker searching the paths on its own file system
+    pub fn workers<I, W>(mut self, workers: I) -> Self
+    where
+        I: IntoIterator<Item = W>,
+        W: Into<String>,
+    {
+        self.options.workers = workers.into_iter().map(Into::into).collect();
+        self
+    }
+    
+    /// Treat a worker as failed when it has not been heard from for this long
+    /// (default 10 seconds)
+    pub fn worker_timeout(mut self, timeout: Duration) -> Self {
+        self.options.worker_timeout = timeout;
+        self
+    }
+    
+    /// Only search on workers that prove they know this shared secret, and
+    /// prove it to them in turn
+    pub fn worker_secret(mut self, secret: Option<Vec<u8>>) -> Self {
+        self.options.worker_secret = secret;
+        self
+    }
+    
+    /// Connect to workers over TLS, trusting certificates signed by the CA
+    /// certificates in this PEM file
+    pub fn worker_tls(mut self, ca: Option<PathBuf>) -> Self {
+        self.options.worker_tls_ca = ca;
+        self
+    }
+    
+    /// Only scan the files under the index's root that may match according
+    /// to the index, along with files that changed since it was built and
+    /// files it does not cover
+    pub fn index(mut self, index: Index) -> Self {
+        self.index = Some(Arc::new(index));
+        self
+    }
+    
+    /// Compile the pattern, create the searcher's thread pools and build the searcher
+    pub fn build(mut self) -> Result<Searcher> {
+        let matcher = RegexMatcher::new(&self.pattern, self.case_mode)?;
+        let pools = ThreadPools::new(&self.options)?;
+        if let Some(index) = self.index {
+            let case_insensitive = self.case_mode.is_insensitive(&self.pattern);
+            let candidates = Candidates::new(index, &self.pattern, case_insensitive)?;
+            self.options.candidates = Some(Arc::new(candidates));
+        }
+        
+        Ok(Searcher {
+            matcher,
+            case_mode: self.case_mode,
+            options: self.options,
+            pools,
+        })
+    }
+}
+
+/// A compiled search that can be run against paths, byte slices and readers.
+///
+/// Each searcher owns the thread pools its searches run on, so searchers with
+/// different thread counts can be used side by side in one process.
+pub struct Searcher {
+    matcher: RegexMatcher,
+    case_mode: CaseMode,
+    options: SearchOptions,
+    pools: ThreadPools,
+}
+
+impl Searcher {
+    /// Get the regex pattern being searched for
+    pub fn pattern(&self) -> &str {
+        self.matcher.pattern()
+    }
+    
+    /// Search a single file or directory, collecting the matching lines
+    pub fn search_path<P: AsRef<Path>>(&self, path: P) -> Result<SearchResult> {
+        self.search_paths(&[path])
+    }
+    
+    /// Search several files or directories in parallel, collecting the matching lines
+    pub fn search_paths<P: AsRef<Path>>(&self, paths: &[P]) -> Result<SearchResult> {
+        let collector = Collector::default();
+        let mut result = self.search_paths_with(paths, &collector)?;
+        result.files = collector.into_files();
+        Ok(result)
+    }
+    
+    /// Search an in-memory byte slice, collecting the matching lines
+    pub fn search_slice(&self, bytes: &[u8]) -> SearchResult {
+        let collector = Collector::default();
+        let mut result = self.search_slice_with(bytes, &collector);
+        result.files = collector.into_files();
+        result
+    }
+    
+    /// Search everything read from the given reader, collecting the matching lines
+    pub fn search_reader<R: Read>(&self, reader: R) -> Result<SearchResult> {
+        let collector = Collector::default();
+        let mut result = self.search_reader_with(reader, &collector)?;
+        result.files = collector.into_files();
+        Ok(result)
+    }
+    
+    /// Search a single file or directory, streaming results to the sink
+    pub fn search_path_with<P: AsRef<Path>, S: Sink + ?Sized>(&self, path: P, sink: &S) -> Result<SearchResult> {
+        self.search_paths_with(&[path], sink)
+    }
+    
+    /// Search several files or directories in parallel, streaming results to the sink
+    pub fn search_paths_with<P: AsRef<Path>, S: Sink + ?Sized>(&self, paths: &[P], sink: &S) -> Result<SearchResult> {
+        let paths: Vec<PathBuf> = paths.iter()
+            .map(|p| p.as_ref().to_path_buf())
+            .collect();
+        
+        if !self.options.workers.is_empty() {
+            if self.options.filter.is_some() {
+                bail!("File filters cannot be used in a distributed search");
+            }
+            if self.options.candidates.is_some() {
+                bail!("An index cannot be used in a distributed search");
+            }
+            let request = Request {
+                kind: RequestKind::Search,
+                pattern: self.pattern().to_string(),
+                case_mode: self.case_mode,
+                paths,
+                options: self.options.clone(),
+            };
+            return coordinator::execute_search(&self.options.workers, &request, sink);
+        }
+        
+        engine::execute_search(&paths, &self.matcher, &self.options, &self.pools, None, sink)
+    }
+    
+    /// Search several files or directories, then keep watching them and
+    /// search the content appended to each file once it is created or
+    /// modified and has not changed for `debounce`. A file that was replaced,
+    /// truncated or rewritten is searched again in full. Every batch of
+    /// changed files is finished like a separate search. Runs until the cancel
+    /// flag is set (Linux only).
+    pub fn watch_paths_with<P: AsRef<Path>, S: Sink + ?Sized>(&self, paths: &[P], debounce: Duration, sink: &S) -> Result<()> {
+        if !self.options.workers.is_empty() {
+            bail!("Watching files cannot be combined with a distributed search");
+        }
+        let paths: Vec<PathBuf> = paths.iter()
+            .map(|p| p.as_ref().to_path_buf())
+            .collect();
+        
+        // Watch before the first search so no change made during it is missed
+        let mut watcher = Watcher::new(&paths, self.options.recursive)?;
+        let searched = SearchedFiles::default();
+        engine::execute_search(&paths, &self.matcher, &self.options, &self.pools, Some(&searched), sink)?;
+        
+        let cancel = self.options.cancel.as_deref();
+        while let Some(files) = watcher.wait(debounce, cancel)? {
+            if !files.is_empty() {
+                engine::execute_search(&files, &self.matcher, &self.options, &self.pools, Some(&searched), sink)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Search files and keep following them as they grow, like `tail -F`,
+    /// streaming the lines appended to them to the sink. A file that is
+    /// replaced or truncated is followed again from its start. Runs until the
+    /// cancel flag is set, the timeout expires or the match limits are reached.
+    pub fn follow_paths_with<P: AsRef<Path>, S: Sink + ?Sized>(&self, paths: &[P], sink: &S) -> Result<SearchResult> {
+        if !self.options.workers.is_empty() {
+            bail!("Following files cannot be combined with a distributed search");
+        }
+        let paths: Vec<PathBuf> = paths.iter()
+            .map(|p| p.as_ref().to_path_buf())
+            .collect();
+        
+        engine::follow_files(&paths, &self.matcher, &self.options, sink)
+    }
+    
+    /// List the files a search of the given paths would search, with their
+    /// sizes and modification times, without searching them
+    pub(crate) fn manifest(&self, paths: &[PathBuf]) -> Result<Manifest> {
+        let discovery = engine::discover_files(paths, &self.options, &self.pools)?;
+        let metadata = self.pools.io.install(|| file_discovery::file_metadata(&discovery.files));
+        
+        let files = discovery.files.into_iter()
+            .zip(metadata)
+            .map(|(path, metadata)| {
+                let modified = metadata.as_ref()
+                    .and_then(|m| m.modified().ok())
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |modified| modified.as_nanos() as u64);
+                ManifestFile {
+                    path,
+                    size: metadata.map_or(0, |m| m.len()),
+                    modified,
+                }
+            })
+            .collect();
+        
+        Ok(Manifest {
+            files,
+            skipped_files: discovery.skipped_files,
+            errors: discovery.errors,
+        })
+    }
+    
+    /// Search an in-memory byte slice, streaming results to the sink
+    pub fn search_slice_with<S: Sink + ?Sized>(&self, bytes: &[u8], sink: &S) -> SearchResult {
+        engine::search_input(bytes, &self.matcher, &self.options, sink)
+    }
+    
+    /// Search everything read from the given reader, streaming results to the sink
+    pub fn search_reader_with<R: Read, S: Sink + ?Sized>(&self, mut reader: R, sink: &S) -> Result<SearchResult> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)
+            .context("Failed to read search input")?;
+        
+        Ok(self.search_slice_with(&buffer, sink))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn matching_lines_are_reported_one_at_a_time() {
+        let searcher = SearcherBuilder::new(r"\Whello").case_mode(CaseMode::Smart).build().unwrap();
+        let result = searcher.search_slice(b"foo\nHELLO\nbar hello\n");
+        
+        let lines = &result.files[0].lines;
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].line_number, 3);
+        assert_eq!(lines[0].line, "bar hello");
+    }
+    
+    #[test]
+    fn lines_after_invalid_utf8_are_searched() {
+        let searcher = SearcherBuilder::new("hello").build().unwrap();
+        let result = searcher.search_slice(b"\xff\xfe hello\nhello again\n");
+        
+        assert!(result.errors.is_empty());
+        let lines = &result.files[0].lines;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].line, "\u{fffd}\u{fffd} hello");
+        assert_eq!(lines[0].matches, [(7, 12)]);
+        assert_eq!(lines[1].line, "hello again");
+    }
+    
+    #[test]
+    fn max_count_refuses_lines_beyond_the_limit() {
+        let input = b"x\nhello again\nhello\n";
+        let searcher = SearcherBuilder::new("hello").max_count(Some(0)).build().unwrap();
+        let result = searcher.search_slice(input);
+        assert_eq!(result.matched_lines, 0);
+        assert_eq!(result.files_with_matches, 0);
+        assert!(result.files.iter().all(|file| file.lines.is_empty()));
+        
+        let searcher = SearcherBuilder::new("hello").max_count(Some(1)).build().unwrap();
+        let result = searcher.search_slice(input);
+        assert_eq!(result.matched_lines, 1);
+        assert_eq!(result.files[0].lines[0].line_number, 2);
+        assert!(result.truncated);
+    }
+    
+    #[test]
+    fn reaching_the_total_limit_exactly_is_not_truncation() {
+        let searcher = SearcherBuilder::new("hello").max_total(Some(2)).build().unwrap();
+        let result = searcher.search_slice(b"hello\nhello\nbye\n");
+        assert_eq!(result.matched_lines, 2);
+        assert!(!result.truncated);
+        
+        let result = searcher.search_slice(b"hello\nhello\nhello\n");
+        assert_eq!(result.matched_lines, 2);
+        assert!(result.truncated);
+    }
+    
+    #[test]
+    fn chunked_files_find_every_line() {
+        let path = std::env::temp_dir().join(format!("grepx-chunks-{}", std::process::id()));
+        let content: String = (0..2000).map(|i| format!("line {} with a match\n", i)).collect();
+        std::fs::write(&path, &content).unwrap();
+        
+        let searcher = SearcherBuilder::new(r"\d+ with a \w+")
+            .chunk_size(Some(1))
+            .read_strategy(Some(ReadStrategy::Mmap))
+            .build()
+            .unwrap();
+        let result = searcher.search_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        
+        let lines = &result.unwrap().files[0].lines;
+        assert_eq!(lines.len(), 2000);
+        assert!(lines.iter().enumerate().all(|(i, line)| line.line_number == i + 1 && line.line == format!("line {} with a match", i)));
+    }
+    
+    #[test]
+    fn prefetching_does_not_change_what_is_found() {
+        let dir = std::env::temp_dir().join(format!("grepx-prefetch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in 0..100 {
+            let content: String = (0..200).map(|line| format!("file {} line {} {}\n", file, line, if line % 7 == 0 { "match" } else { "" })).collect();
+            std::fs::write(dir.join(format!("{:03}.txt", file)), content).unwrap();
+        }
+        
+        // Small chunks, so that chunks are prefetched as well as files
+        let search = |prefetch, strategy| {
+            let searcher = SearcherBuilder::new("match")
+                .recursive(true)
+                .threads(4)
+                .chunk_size(Some(1))
+                .read_strategy(Some(strategy))
+                .prefetch(prefetch)
+                .build()
+                .unwrap();
+            let result = searcher.search_path(&dir).unwrap();
+            assert!(result.errors.is_empty());
+            let mut files: Vec<_> = result.files.into_iter()
+                .map(|file| (file.path, file.lines.into_iter().map(|line| line.line).collect::<Vec<_>>()))
+                .collect();
+            files.sort();
+            files
+        };
+        let expected = search(false, ReadStrategy::Buffered);
+        assert_eq!(expected.len(), 100);
+        assert!(expected.iter().all(|(_, lines)| lines.len() == 29));
+        for strategy in [ReadStrategy::Buffered, ReadStrategy::Mmap, ReadStrategy::Sequential] {
+            assert_eq!(search(true, strategy), expected);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn watched_files_are_searched_from_where_they_were_left() {
+        let path = std::env::temp_dir().join(format!("grepx-watched-{}", std::process::id()));
+        let searcher = SearcherBuilder::new("match").build().unwrap();
+        let searched = SearchedFiles::default();
+        let search = || {
+            let collector = Collector::default();
+            engine::execute_search(std::slice::from_ref(&path), &searcher.matcher, &searcher.options, &searcher.pools, Some(&searched), &collector).unwrap();
+            collector.into_files().into_iter()
+                .flat_map(|file| file.lines)
+                .map(|line| (line.line_number, line.line))
+                .collect::<Vec<_>>()
+        };
+        let append = |text: &str| {
+            use std::io::Write;
+            std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(text.as_bytes()).unwrap();
+        };
+        
+        std::fs::write(&path, "a match\nnothing\n").unwrap();
+        assert_eq!(search(), [(1, "a match".to_string())]);
+        append("second match\npartial match");
+        assert_eq!(search(), [(3, "second match".to_string()), (4, "partial match".to_string())]);
+        append(" finished\n");
+        assert_eq!(search(), [(4, "partial match finished".to_string())]);
+        assert_eq!(search(), []);
+        
+        // Rewritten in place with more content than before, then truncated
+        std::fs::write(&path, "rewritten match\nanother match\nand lines that make it longer than before\n").unwrap();
+        assert_eq!(search(), [(1, "rewritten match".to_string()), (2, "another match".to_string())]);
+        std::fs::write(&path, "short match\n").unwrap();
+        assert_eq!(search(), [(1, "short match".to_string())]);
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn followed_lines_without_an_end_are_searched_once_long_enough() {
+        let path = std::env::temp_dir().join(format!("grepx-long-line-{}", std::process::id()));
+        let mut content = b"a match".to_vec();
+        content.resize(3 * 1024 * 1024, b'a');
+        std::fs::write(&path, &content).unwrap();
+        
+        // Following only stops early if the match is found before the line ends
+        let cancel = Arc::new(AtomicBool::new(false));
+        let searcher = SearcherBuilder::new("match")
+            .max_total(Some(1))
+            .cancel_flag(cancel.clone())
+            .build()
+            .unwrap();
+        let timer = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs(10));
+            timer.store(true, std::sync::atomic::Ordering::Relaxed);
+        });
+        let collector = Collector::default();
+        searcher.follow_paths_with(&[&path], &collector).unwrap();
+        let stopped_early = !cancel.load(std::sync::atomic::Ordering::Relaxed);
+        std::fs::remove_file(&path).unwrap();
+        
+        assert!(stopped_early);
+        let lines = &collector.into_files()[0].lines;
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].line_number, 1);
+        assert!(lines[0].line.starts_with("a match") && lines[0].line.len() < content.len());
+    }
+    
+    #[test]
+    fn stats_count_what_the_search_did() {
+        let dir = std::env::temp_dir().join(format!("grepx-stats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("match.txt"), "a match\nmatch and match\nnothing\n").unwrap();
+        std::fs::write(dir.join("none.txt"), "nothing\n").unwrap();
+        std::fs::write(dir.join("large.txt"), "match\n".repeat(100)).unwrap();
+        
+        let search = |strategy| {
+            SearcherBuilder::new("match")
+                .recursive(true)
+                .max_filesize(Some(64))
+                .read_strategy(Some(strategy))
+                .build()
+                .unwrap()
+                .search_path_with(&dir, &Collector::default())
+                .unwrap()
+        };
+        let buffered = search(ReadStrategy::Buffered);
+        let mapped = search(ReadStrategy::Mmap);
+        std::fs::remove_dir_all(&dir).unwrap();
+        
+        for result in [&buffered, &mapped] {
+            assert_eq!(result.files_searched, 2);
+            assert_eq!(result.files_with_matches, 1);
+            assert_eq!(result.skipped_files, [dir.join("large.txt")]);
+            assert_eq!(result.files_with_errors(), 0);
+            assert_eq!(result.matched_lines, 2);
+            assert_eq!(result.total_matches, 3);
+            assert_eq!(result.bytes_processed, 40);
+            assert!(result.elapsed >= result.search_time && result.elapsed >= result.discovery_time);
+        }
+        assert_eq!((buffered.bytes_read, buffered.bytes_mapped), (40, 0));
+        assert_eq!((mapped.bytes_read, mapped.bytes_mapped), (0, 40));
+    }
+    
+    /// Sink recording the names of the threads files were searched on
+    #[derive(Default)]
+    struct Threads(std::sync::Mutex<std::collections::HashSet<String>>);
+    
+    impl Sink for Threads {
+        fn on_file_begin(&self, _path: Option<&Path>) {
+            let name = std::thread::current().name().unwrap_or_default().to_string();
+            self.0.lock().unwrap().insert(name);
+        }
+    }
+    
+    #[test]
+    fn searchers_own_pools_of_the_configured_sizes() {
+        let searcher = SearcherBuilder::new("match").threads(2).io_threads(3).build().unwrap();
+        assert_eq!(searcher.pools.search.current_num_threads(), 2);
+        assert_eq!(searcher.pools.io.current_num_threads(), 3);
+        
+        let dir = std::env::temp_dir().join(format!("grepx-pools-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..64 {
+            std::fs::write(dir.join(format!("{}.txt", i)), "a match\n").unwrap();
+        }
+        
+        // Searches run concurrently, so a shared or global pool would show
+        let search = |threads| {
+            let searcher = SearcherBuilder::new("match").threads(threads).recursive(true).build().unwrap();
+            let sink = Threads::default();
+            let result = searcher.search_path_with(&dir, &sink).unwrap();
+            assert_eq!(result.files_with_matches, 64);
+            sink.0.into_inner().unwrap()
+        };
+        let (one, three) = std::thread::scope(|scope| {
+            let one = scope.spawn(|| search(1));
+            let three = scope.spawn(|| search(3));
+            (one.join().unwrap(), three.join().unwrap())
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+        
+        assert_eq!(one.into_iter().collect::<Vec<_>>(), ["grepx-search-0"]);
+        assert!(!three.is_empty());
+        assert!(three.iter().all(|name| ["grepx-search-0", "grepx-search-1", "grepx-search-2"].contains(&name.as_str())));
+    }
+}
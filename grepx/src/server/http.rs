@@ -0,0 +1,240 @@
+//! Just enough HTTP/1.1 for the search server: one request per connection,
+//! request bodies with a `Content-Length`, and responses that are either
+//! complete JSON documents or streams of chunks.
+
+use serde_json::{json, Value};
+use std::io;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest request line and headers accepted
+const MAX_HEAD: usize = 64 * 1024; // 64KB
+
+/// Largest request body accepted
+const MAX_BODY: usize = 1024 * 1024; // 1MB
+
+/// A request read from a client
+pub(crate) struct Request {
+    pub method: String,
+    /// Path of the request target, without any query string
+    pub path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Value of a header, matching its name case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    
+    /// Decoded value of a query string parameter, or `None` if it is missing
+    /// or not valid UTF-8 once decoded
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query.split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .find(|(key, _)| percent_decode(key).as_deref() == Some(name))
+            .and_then(|(_, value)| percent_decode(value))
+    }
+}
+
+/// Decode a query string component, where `+` stands for a space
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match byte {
+            b'%' => {
+                let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &tail[2..];
+                continue;
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+        rest = tail;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// A request that cannot be served, answered with a JSON error body
+#[derive(Debug)]
+pub(crate) struct Error {
+    pub status: u16,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Read a request, answering `Expect: 100-continue` before reading its body.
+/// Returns `None` if the client closed the connection without sending one.
+pub(crate) async fn read_request<R, W>(reader: &mut R, writer: &mut W) -> Result<Option<Request>, Error>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut remaining = MAX_HEAD;
+    let line = match read_line(reader, &mut remaining).await? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) if version.starts_with("HTTP/1.") => (method, target, version),
+        _ => return Err(Error::new(400, "Malformed request line")),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+    let method = method.to_string();
+    let version = version.to_string();
+    
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader, &mut remaining).await?
+            .ok_or_else(|| Error::new(400, "Connection closed in request headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')
+            .ok_or_else(|| Error::new(400, "Malformed request header"))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    let mut request = Request {
+        method,
+        path,
+        query,
+        headers,
+        body: Vec::new(),
+    };
+    
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(Error::new(411, "Request bodies must have a Content-Length"));
+    }
+    let length = match request.header("Content-Length") {
+        Some(length) => length.parse::<usize>()
+            .map_err(|_| Error::new(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(Error::new(413, format!("Request body is larger than {} bytes", MAX_BODY)));
+    }
+    
+    if length > 0 {
+        let expects_continue = request.header("Expect")
+            .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"));
+        if expects_continue {
+            let _ = writer.write_all(format!("{} 100 Continue\r\n\r\n", version).as_bytes()).await;
+        }
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body).await
+            .map_err(|_| Error::new(400, "Connection closed in request body"))?;
+    }
+    Ok(Some(request))
+}
+
+/// Read a line of the request head without its line ending, failing once
+/// the head grows past its limit
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, remaining: &mut usize) -> Result<Option<String>, Error> {
+    let mut line = Vec::new();
+    let read = (&mut *reader).take(*remaining as u64).read_until(b'\n', &mut line).await
+        .map_err(|e| Error::new(400, format!("Failed to read request: {}", e)))?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(Error::new(431, "Request headers are too large"));
+    }
+    *remaining -= read;
+    
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| Error::new(400, "Request headers are not valid UTF-8"))
+}
+
+/// Write a complete response
+pub(crate) async fn write_body<W: AsyncWrite + Unpin>(writer: &mut W, status: u16, content_type: &str, headers: &[(&str, &str)], body: &[u8]) -> io::Result<()> {
+    let mut response = head(status, content_type, headers);
+    response.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    writer.write_all(response.as_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.flush().await
+}
+
+/// Write a complete response with a JSON body
+pub(crate) async fn write_json<W: AsyncWrite + Unpin>(writer: &mut W, status: u16, headers: &[(&str, &str)], body: &Value) -> io::Result<()> {
+    write_body(writer, status, "application/json", headers, format!("{}\n", body).as_bytes()).await
+}
+
+/// Write an error response
+pub(crate) async fn write_error<W: AsyncWrite + Unpin>(writer: &mut W, error: &Error) -> io::Result<()> {
+    let headers: &[(&str, &str)] = match error.status {
+        401 => &[("WWW-Authenticate", "Bearer")],
+        _ => &[],
+    };
+    write_json(writer, error.status, headers, &json!({ "error": error.message })).await
+}
+
+/// Write the head of a response whose body follows in chunks
+pub(crate) async fn write_stream_head<W: AsyncWrite + Unpin>(writer: &mut W, content_type: &str, headers: &[(&str, &str)]) -> io::Result<()> {
+    let mut response = head(200, content_type, headers);
+    response.push_str("Transfer-Encoding: chunked\r\n\r\n");
+    writer.write_all(response.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Write a chunk of a streamed body
+pub(crate) async fn write_chunk<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    writer.write_all(format!("{:x}\r\n", data.len()).as_bytes()).await?;
+    writer.write_all(data).await?;
+    writer.write_all(b"\r\n").await?;
+    writer.flush().await
+}
+
+/// End a streamed body
+pub(crate) async fn write_last_chunk<W: AsyncWrite + Unpin>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"0\r\n\r\n").await?;
+    writer.flush().await
+}
+
+fn head(status: u16, content_type: &str, headers: &[(&str, &str)]) -> String {
+    let mut head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nConnection: close\r\n",
+                           status, reason(status), content_type);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
@@ -0,0 +1,646 @@
+//! HTTP search server.
+//!
+//! `grepx server` answers searches over HTTP, so that other programs can
+//! search a machine without running grepx themselves. It serves:
+//!
+//! - `POST /search` runs the search described by a JSON body with the
+//!   `pattern`, the `paths` to search and optional `case_mode` and `options`,
+//!   named as in the distributed search protocol. The result is returned as
+//!   one JSON document, or with `"stream": true` as JSON Lines events in a
+//!   chunked response while the search runs. Each search has an ID, chosen
+//!   by the client with `id` or assigned by the server, which is returned in
+//!   the `X-Search-Id` header.
+//! - `DELETE /search/{id}` cancels a running search.
+//! - `GET /health` reports that the server is up.
+//! - `GET /stats` reports totals of the searches served and the searches
+//!   running.
+//! - `GET /` serves a self-contained web UI, which streams searches and
+//!   shows a heatmap of match density over the directory tree or over time,
+//!   from the timestamps of matching lines, a minimap of where matches are
+//!   in each file, and the files with their matches highlighted, read with
+//!   `GET /file?path=...`.
+//!
+//! Each connection carries a single request. When the server has a token,
+//! every request but `GET /health` and the web UI page must carry it as a
+//! bearer token. A search whose client disconnects is cancelled.
+//!
+//! Searches and file views are limited to root directories, by default the
+//! directory the server was started in. Requests must name the server in
+//! their `Host` header by `localhost`, an IP address or one of its allowed
+//! host names, so that a web page cannot reach it by rebinding its own
+//! domain name to the server's address.
+
+mod http;
+
+use anyhow::{Result, Context};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+use crate::distributed::auth;
+use crate::distributed::protocol;
+use crate::engine::SearchResult;
+use crate::error::{ErrorKind, SearchError};
+use crate::io::roots::Roots;
+use crate::printer::{self, JsonPrinter, PrinterOptions};
+use crate::searcher::Searcher;
+use crate::utils::format_size;
+
+use self::http::{Error, Request};
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Chunks of streamed output buffered between a search and its connection
+const STREAM_BUFFER: usize = 64;
+
+/// Largest file the web UI can open
+const MAX_FILE_VIEW: u64 = 16 * 1024 * 1024; // 16MB
+
+/// The web UI, a single page with inline styles and scripts
+const UI: &str = include_str!("ui.html");
+
+/// Builder for configuring a [`Server`]
+#[derive(Clone, Default)]
+pub struct ServerBuilder {
+    threads: usize,
+    io_threads: usize,
+    token: Option<Vec<u8>>,
+    roots: Vec<PathBuf>,
+    hosts: Vec<String>,
+}
+
+impl ServerBuilder {
+    /// Create a new builder with automatic thread counts
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Number of threads searching file contents for each search (0 = auto)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+    
+    /// Number of threads walking directories and reading file metadata for each search (0 = auto)
+    pub fn io_threads(mut self, threads: usize) -> Self {
+        self.io_threads = threads;
+        self
+    }
+    
+    /// Only serve requests carrying this bearer token, except health checks
+    pub fn token(mut self, token: Option<Vec<u8>>) -> Self {
+        self.token = token;
+        self
+    }
+    
+    /// Only allow searches of paths under these directories (default: the
+    /// current directory)
+    pub fn roots<I, P>(mut self, roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.roots = roots.into_iter().map(Into::into).collect();
+        self
+    }
+    
+    /// Host names clients may reach the server by, besides `localhost` and
+    /// IP addresses
+    pub fn hosts<I, H>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = H>,
+        H: Into<String>,
+    {
+        self.hosts = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+    
+    /// Listen for HTTP requests on the given address
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> Result<Server> {
+        let roots = if self.roots.is_empty() {
+            Roots::new(&[std::env::current_dir().context("Failed to get the current directory")?])?
+        } else {
+            Roots::new(&self.roots)?
+        };
+        let listener = TcpListener::bind(addr)
+            .context("Failed to bind server address")?;
+        let addr = listener.local_addr()?;
+        
+        Ok(Server {
+            listener,
+            state: Arc::new(State {
+                threads: self.threads,
+                io_threads: self.io_threads,
+                token: self.token,
+                roots,
+                addr,
+                hosts: self.hosts,
+                started: Instant::now(),
+                next_id: AtomicU64::new(1),
+                searches: Mutex::new(Searches::default()),
+            }),
+        })
+    }
+}
+
+/// A server answering searches over HTTP, described in the [module
+/// documentation](self)
+pub struct Server {
+    listener: TcpListener,
+    state: Arc<State>,
+}
+
+struct State {
+    threads: usize,
+    io_threads: usize,
+    token: Option<Vec<u8>>,
+    roots: Roots,
+    /// Address the server is bound to
+    addr: SocketAddr,
+    /// Host names clients may use besides `localhost` and IP addresses
+    hosts: Vec<String>,
+    started: Instant,
+    next_id: AtomicU64,
+    searches: Mutex<Searches>,
+}
+
+#[derive(Default)]
+struct Searches {
+    running: HashMap<String, Running>,
+    totals: Totals,
+}
+
+/// A search being served
+struct Running {
+    pattern: String,
+    paths: Vec<PathBuf>,
+    started: Instant,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Totals of the searches that have ended
+#[derive(Default)]
+struct Totals {
+    completed: u64,
+    cancelled: u64,
+    failed: u64,
+    files_searched: u64,
+    bytes_processed: u64,
+    total_matches: u64,
+}
+
+impl Server {
+    /// Address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+    
+    /// Whether requests must carry a bearer token
+    pub fn is_authenticated(&self) -> bool {
+        self.state.token.is_some()
+    }
+    
+    /// Serve requests until the process exits. Connections are handled on an
+    /// async runtime, and searches run on blocking threads.
+    pub fn serve(self) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("grepx-server")
+            .enable_all()
+            .build()
+            .context("Failed to start the server runtime")?;
+        
+        runtime.block_on(async {
+            self.listener.set_nonblocking(true)?;
+            let listener = tokio::net::TcpListener::from_std(self.listener)?;
+            info!("Server listening on {}", listener.local_addr()?);
+            
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        warn!("Failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+                
+                let state = self.state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &state).await {
+                        warn!("Request from {} failed: {:#}", peer, e);
+                    }
+                });
+            }
+        })
+    }
+}
+
+/// Read a request and answer it
+async fn handle_connection(stream: TcpStream, state: &Arc<State>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, http::read_request(&mut reader, &mut writer)).await {
+        Ok(Ok(Some(request))) => request,
+        Ok(Ok(None)) => return Ok(()),
+        Ok(Err(error)) => return Ok(http::write_error(&mut writer, &error).await?),
+        Err(_) => return Ok(http::write_error(&mut writer, &Error::new(408, "Timed out reading request")).await?),
+    };
+    debug!("{} {}", request.method, request.path);
+    
+    let response = match (request.method.as_str(), request.path.as_str()) {
+        _ if !host_allowed(&request, state) => Err(Error::new(403, "Host not allowed")),
+        ("GET", "/health") => Ok(health(state)),
+        ("GET", "/") => return Ok(http::write_body(&mut writer, 200, "text/html; charset=utf-8", &[], UI.as_bytes()).await?),
+        _ if !authorized(&request, state) => Err(Error::new(401, "Missing or invalid bearer token")),
+        ("GET", "/stats") => Ok(stats(state)),
+        ("GET", "/file") => return file(&request, state, &mut writer).await,
+        ("POST", "/search") => return search(&request, state, reader, writer).await,
+        ("DELETE", path) if path.starts_with("/search/") => cancel(state, &path["/search/".len()..]),
+        (_, "/" | "/health" | "/stats" | "/search" | "/file") => Err(Error::new(405, "Method not allowed")),
+        (_, path) if path.starts_with("/search/") => Err(Error::new(405, "Method not allowed")),
+        _ => Err(Error::new(404, "Not found")),
+    };
+    match response {
+        Ok(body) => http::write_json(&mut writer, 200, &[], &body).await?,
+        Err(error) => http::write_error(&mut writer, &error).await?,
+    }
+    Ok(())
+}
+
+/// Whether the request names the server by `localhost`, by an address it
+/// listens on or by one of its host names. A web page on another domain
+/// that resolves to the server still carries that domain as its host.
+fn host_allowed(request: &Request, state: &State) -> bool {
+    let Some(host) = request.header("Host") else {
+        return false;
+    };
+    // Strip the port, keeping IPv6 addresses whole
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(rest, |(address, _)| address),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    if name.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    match name.parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback() || ip == state.addr.ip() || state.addr.ip().is_unspecified(),
+        Err(_) => state.hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(name)),
+    }
+}
+
+fn authorized(request: &Request, state: &State) -> bool {
+    let token = match &state.token {
+        Some(token) => token,
+        None => return true,
+    };
+    request.header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|bearer| auth::tokens_match(token, bearer.trim().as_bytes()))
+}
+
+fn health(state: &State) -> Value {
+    json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime": state.started.elapsed().as_secs_f64(),
+    })
+}
+
+fn stats(state: &State) -> Value {
+    let searches = state.searches.lock().unwrap();
+    let totals = &searches.totals;
+    let mut running: Vec<(&String, &Running)> = searches.running.iter().collect();
+    running.sort_by_key(|(_, search)| search.started);
+    
+    json!({
+        "uptime": state.started.elapsed().as_secs_f64(),
+        "searches": {
+            "total": totals.completed + totals.cancelled + totals.failed + running.len() as u64,
+            "running": running.len(),
+            "completed": totals.completed,
+            "cancelled": totals.cancelled,
+            "failed": totals.failed,
+        },
+        "files_searched": totals.files_searched,
+        "bytes_processed": totals.bytes_processed,
+        "total_matches": totals.total_matches,
+        "running": running.iter()
+            .map(|(id, search)| json!({
+                "id": id,
+                "pattern": search.pattern,
+                "paths": search.paths.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>(),
+                "elapsed": search.started.elapsed().as_secs_f64(),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn cancel(state: &State, id: &str) -> Result<Value, Error> {
+    let searches = state.searches.lock().unwrap();
+    let search = searches.running.get(id)
+        .ok_or_else(|| Error::new(404, format!("No running search with ID {}", id)))?;
+    search.cancel.store(true, Ordering::Relaxed);
+    Ok(json!({ "id": id, "cancelled": true }))
+}
+
+/// Send the contents of a file for the web UI's file view
+async fn file(request: &Request, state: &State, writer: &mut OwnedWriteHalf) -> Result<()> {
+    let contents = match request.query_param("path") {
+        Some(path) if !path.is_empty() => read_file(Path::new(&path), &state.roots).await,
+        _ => Err(Error::new(400, "Missing path parameter")),
+    };
+    match contents {
+        Ok(contents) => http::write_body(writer, 200, "text/plain; charset=utf-8", &[], &contents).await?,
+        Err(error) => http::write_error(writer, &error).await?,
+    }
+    Ok(())
+}
+
+async fn read_file(path: &Path, roots: &Roots) -> Result<Vec<u8>, Error> {
+    let failed = |e: io::Error| {
+        let status = match e.kind() {
+            io::ErrorKind::NotFound => 404,
+            io::ErrorKind::PermissionDenied => 403,
+            _ => 500,
+        };
+        Error::new(status, format!("{}: {}", path.display(), e))
+    };
+    
+    if !roots.contains(path) {
+        return Err(Error::new(403, format!("{}: outside the allowed root directories", path.display())));
+    }
+    let metadata = tokio::fs::metadata(path).await.map_err(failed)?;
+    if !metadata.is_file() {
+        return Err(Error::new(400, format!("{}: not a file", path.display())));
+    }
+    if metadata.len() > MAX_FILE_VIEW {
+        return Err(Error::new(413, format!("{}: larger than {}", path.display(), format_size(MAX_FILE_VIEW as usize))));
+    }
+    tokio::fs::read(path).await.map_err(failed)
+}
+
+/// A search registered as running, removed from the running searches and
+/// added to the totals when dropped
+struct Registration<'a> {
+    state: &'a State,
+    id: String,
+    cancel: Arc<AtomicBool>,
+    result: Option<Result<SearchResult>>,
+}
+
+impl Registration<'_> {
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        let cancelled = self.cancelled();
+        let mut searches = self.state.searches.lock().unwrap();
+        searches.running.remove(&self.id);
+        
+        let totals = &mut searches.totals;
+        match &self.result {
+            Some(Ok(result)) => {
+                totals.files_searched += result.files_searched as u64;
+                totals.bytes_processed += result.bytes_processed as u64;
+                totals.total_matches += result.total_matches as u64;
+                if cancelled {
+                    totals.cancelled += 1;
+                } else {
+                    totals.completed += 1;
+                }
+            }
+            _ if cancelled => totals.cancelled += 1,
+            _ => totals.failed += 1,
+        }
+    }
+}
+
+/// A parsed search request, ready to run
+struct SearchRequest {
+    id: Option<String>,
+    pattern: String,
+    paths: Vec<PathBuf>,
+    stream: bool,
+    searcher: Searcher,
+    cancel: Arc<AtomicBool>,
+}
+
+impl SearchRequest {
+    fn parse(request: &Request, state: &State) -> Result<Self, Error> {
+        let body: Value = serde_json::from_slice(&request.body)
+            .map_err(|e| Error::new(400, format!("Malformed JSON body: {}", e)))?;
+        if !body.is_object() {
+            return Err(Error::new(400, "Expected a JSON object"));
+        }
+        
+        let id = match &body["id"] {
+            Value::Null => None,
+            Value::String(id) if valid_id(id) => Some(id.clone()),
+            _ => return Err(Error::new(400, "Search IDs must be 1 to 64 letters, digits, '-' or '_'")),
+        };
+        let (builder, paths) = protocol::parse_search(&body)
+            .map_err(|e| Error::new(400, format!("{:#}", e)))?;
+        if paths.is_empty() {
+            return Err(Error::new(400, "No paths to search"));
+        }
+        
+        let cancel = Arc::new(AtomicBool::new(false));
+        let searcher = state.roots.restrict(builder, &paths)
+            .map_err(|e| Error::new(403, e.to_string()))?
+            .threads(state.threads)
+            .io_threads(state.io_threads)
+            .cancel_flag(cancel.clone())
+            .build()
+            .map_err(|e| Error::new(400, format!("{:#}", e)))?;
+        
+        Ok(Self {
+            id,
+            pattern: body["pattern"].as_str().unwrap_or_default().to_string(),
+            paths,
+            stream: body["stream"].as_bool().unwrap_or(false),
+            searcher,
+            cancel,
+        })
+    }
+    
+    /// Add the search to the running searches under its ID, or a fresh one
+    fn register<'a>(&mut self, state: &'a State) -> Result<Registration<'a>, Error> {
+        let mut searches = state.searches.lock().unwrap();
+        let id = match self.id.take() {
+            Some(id) if searches.running.contains_key(&id) => {
+                return Err(Error::new(409, format!("A search with ID {} is already running", id)));
+            }
+            Some(id) => id,
+            None => loop {
+                let id = state.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+                if !searches.running.contains_key(&id) {
+                    break id;
+                }
+            },
+        };
+        
+        searches.running.insert(id.clone(), Running {
+            pattern: std::mem::take(&mut self.pattern),
+            paths: self.paths.clone(),
+            started: Instant::now(),
+            cancel: self.cancel.clone(),
+        });
+        Ok(Registration {
+            state,
+            id,
+            cancel: self.cancel.clone(),
+            result: None,
+        })
+    }
+}
+
+fn valid_id(id: &str) -> bool {
+    (1..=64).contains(&id.len()) && id.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+/// Run a search and send its results, cancelling it if the client goes away
+async fn search(request: &Request, state: &Arc<State>, mut reader: BufReader<OwnedReadHalf>, mut writer: OwnedWriteHalf) -> Result<()> {
+    let mut search = match SearchRequest::parse(request, state) {
+        Ok(search) => search,
+        Err(error) => return Ok(http::write_error(&mut writer, &error).await?),
+    };
+    let mut registration = match search.register(state) {
+        Ok(registration) => registration,
+        Err(error) => return Ok(http::write_error(&mut writer, &error).await?),
+    };
+    debug!("Search {} of {:?}", registration.id, search.paths);
+    
+    let id = registration.id.clone();
+    let headers = [("X-Search-Id", id.as_str())];
+    let SearchRequest { searcher, paths, cancel, stream, .. } = search;
+    
+    if stream {
+        let (sender, mut receiver) = mpsc::channel(STREAM_BUFFER);
+        let output = ChannelWriter {
+            sender,
+            cancel: cancel.clone(),
+        };
+        let task = tokio::task::spawn_blocking(move || {
+            let printer = JsonPrinter::with_writer(PrinterOptions { stats: true, ..PrinterOptions::default() }, output);
+            searcher.search_paths_with(&paths, &printer)
+        });
+        
+        // Output still buffered when the search is cancelled is dropped, and
+        // the summary is sent in its place
+        let mut connected = http::write_stream_head(&mut writer, "application/x-ndjson", &headers).await.is_ok();
+        while connected && !registration.cancelled() {
+            tokio::select! {
+                chunk = receiver.recv() => match chunk {
+                    Some(chunk) => connected = http::write_chunk(&mut writer, &chunk).await.is_ok(),
+                    None => break,
+                },
+                _ = client_closed(&mut reader) => connected = false,
+            }
+        }
+        if !connected {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        let stopped = !receiver.is_closed() || !receiver.is_empty();
+        // Let a search blocked on a full channel see that its output is unwanted
+        drop(receiver);
+        
+        let result = task.await.context("Search panicked")?;
+        if connected {
+            let mut trailer = String::new();
+            if let (true, Ok(result)) = (stopped, &result) {
+                trailer.push_str(&format!("{}\n", json!({ "type": "summary", "data": printer::summary_json(result, true) })));
+            }
+            if let Err(e) = &result {
+                let error = SearchError::new(ErrorKind::Io, None, format!("{:#}", e));
+                trailer.push_str(&format!("{}\n", json!({ "type": "error", "data": printer::error_json(&error) })));
+            }
+            if registration.cancelled() {
+                trailer.push_str(&format!("{}\n", json!({ "type": "cancelled", "data": { "id": id } })));
+            }
+            let _ = http::write_chunk(&mut writer, trailer.as_bytes()).await;
+            let _ = http::write_last_chunk(&mut writer).await;
+        }
+        registration.result = Some(result);
+    } else {
+        let mut task = tokio::task::spawn_blocking(move || searcher.search_paths(&paths));
+        let (result, connected) = tokio::select! {
+            result = &mut task => (result, true),
+            _ = client_closed(&mut reader) => {
+                cancel.store(true, Ordering::Relaxed);
+                (task.await, false)
+            }
+        };
+        let result = result.context("Search panicked")?;
+        
+        if connected {
+            let _ = match &result {
+                Ok(result) => http::write_json(&mut writer, 200, &headers, &result_json(&id, registration.cancelled(), result)).await,
+                Err(e) => http::write_error(&mut writer, &Error::new(500, format!("{:#}", e))).await,
+            };
+        }
+        registration.result = Some(result);
+    }
+    Ok(())
+}
+
+/// Resolve once the client closes its side of the connection. Anything the
+/// client sends after its request is ignored.
+async fn client_closed(reader: &mut BufReader<OwnedReadHalf>) {
+    let mut buffer = [0; 1024];
+    while let Ok(1..) = reader.read(&mut buffer).await {}
+}
+
+/// Complete result of a search that was not streamed
+fn result_json(id: &str, cancelled: bool, result: &SearchResult) -> Value {
+    json!({
+        "id": id,
+        "cancelled": cancelled,
+        "files": result.files.iter()
+            .map(|file| json!({
+                "path": file.path.as_ref().map(|path| path.to_string_lossy()),
+                "matches": file.matches,
+                "bytes_processed": file.bytes_processed,
+                "lines": file.lines.iter()
+                    .map(|line| printer::match_json(file.path.as_deref(), line))
+                    .collect::<Vec<_>>(),
+            }))
+            .collect::<Vec<_>>(),
+        "errors": result.errors.iter().map(printer::error_json).collect::<Vec<_>>(),
+        "summary": printer::summary_json(result, true),
+    })
+}
+
+/// Writer passing a streamed search's output to its connection. Once the
+/// connection is gone, writes fail and the search is cancelled.
+struct ChannelWriter {
+    sender: mpsc::Sender<Vec<u8>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.sender.blocking_send(buf.to_vec()).is_err() {
+            self.cancel.store(true, Ordering::Relaxed);
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
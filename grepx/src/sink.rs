@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::engine::{ContextLine, FileResult, FileStats, LineMatch, SearchResult};
+use crate::error::SearchError;
+
+/// Receiver for search results as they are produced.
+///
+/// Files are searched in parallel, so callbacks for different files may arrive
+/// concurrently from several threads. All callbacks for a single file are made
+/// from one thread, in order, between `on_file_begin` and `on_file_end`.
+/// `path` is `None` when searching in-memory input.
+pub trait Sink: Sync {
+    /// Called before a file is searched
+    fn on_file_begin(&self, _path: Option<&Path>) {}
+    
+    /// Called for each line containing at least one match
+    fn on_match(&self, _path: Option<&Path>, _line: &LineMatch) {}
+    
+    /// Called for each context line around a match
+    fn on_context(&self, _path: Option<&Path>, _line: &ContextLine) {}
+    
+    /// Called between two groups of context lines that are not adjacent
+    fn on_context_break(&self, _path: Option<&Path>) {}
+    
+    /// Called when a path cannot be searched, or only partially. Errors for a
+    /// file are reported before its `on_file_end`; errors found while
+    /// discovering files are reported before any file is searched.
+    fn on_error(&self, _error: &SearchError) {}
+    
+    /// Called after a file has been searched
+    fn on_file_end(&self, _path: Option<&Path>, _stats: &FileStats) {}
+    
+    /// Called when the results reported so far should be made visible without
+    /// waiting for the search to complete, such as while following files
+    fn on_flush(&self) {}
+    
+    /// Called once the whole search has completed
+    fn on_finish(&self, _result: &SearchResult) {}
+}
+
+/// Sink that collects matching lines into per-file results
+#[derive(Default)]
+pub(crate) struct Collector {
+    pending: Mutex<HashMap<Option<PathBuf>, FileResult>>,
+    files: Mutex<Vec<FileResult>>,
+}
+
+impl Collector {
+    /// Take the collected results for files that had matches
+    pub fn into_files(self) -> Vec<FileResult> {
+        self.files.into_inner().unwrap()
+    }
+}
+
+impl Sink for Collector {
+    fn on_match(&self, path: Option<&Path>, line: &LineMatch) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.entry(path.map(Path::to_path_buf))
+            .or_default()
+            .lines
+            .push(line.clone());
+    }
+    
+    fn on_file_end(&self, path: Option<&Path>, stats: &FileStats) {
+        let lines = self.pending.lock().unwrap()
+            .remove(&path.map(Path::to_path_buf))
+            .map(|file| file.lines)
+            .unwrap_or_default();
+        
+        if stats.matches > 0 {
+            self.files.lock().unwrap().push(FileResult {
+                path: path.map(Path::to_path_buf),
+                matches: stats.matches,
+                lines,
+                bytes_processed: stats.bytes_processed,
+            });
+        }
+    }
+}
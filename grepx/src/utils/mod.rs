@@ -1,3 +1,3 @@
 mod text;
 
-pub use text::{extract_context, format_size, format_duration, calculate_speed, format_speed}; 
\ No newline at end of file
+pub use text::{extract_context, format_size, format_duration, calculate_speed, format_speed, parse_size, parse_duration}; 
\ No newline at end of file
@@ -1,4 +1,6 @@
+use anyhow::{anyhow, Result};
 use std::borrow::Cow;
+use std::time::Duration;
 
 /// Extract a line containing the match with optional context
 pub fn extract_context<'a>(text: &'a str, match_start: usize, match_end: usize, context_lines: usize) -> Cow<'a, str> {
@@ -87,4 +89,84 @@ pub fn calculate_speed(bytes: usize, duration_secs: f64) -> f64 {
 /// Format search speed as a human-readable string
 pub fn format_speed(bytes_per_sec: f64) -> String {
     format!("{}/s", format_size(bytes_per_sec as usize))
-} 
\ No newline at end of file
+} 
+
+/// Parse a human-readable size such as `512`, `64K`, `100M` or `2G` into bytes
+pub fn parse_size(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let digits = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &text[digits.len()..];
+    
+    let multiplier: u64 = match suffix.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => return Err(anyhow!("Invalid size suffix '{}' in '{}'", suffix, text)),
+    };
+    
+    let value: u64 = digits.parse()
+        .map_err(|_| anyhow!("Invalid size: '{}'", text))?;
+    value.checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("Size too large: '{}'", text))
+}
+
+/// Parse a human-readable duration such as `500ms`, `30s`, `5m` or `1h`.
+/// A bare number is interpreted as seconds.
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    let number = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &text[number.len()..];
+    
+    let value: f64 = number.parse()
+        .map_err(|_| anyhow!("Invalid duration: '{}'", text))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(anyhow!("Invalid duration: '{}'", text));
+    }
+    
+    let seconds = match unit {
+        "ms" => value / 1_000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3_600.0,
+        _ => return Err(anyhow!("Invalid duration unit '{}' in '{}'", unit, text)),
+    };
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| anyhow!("Duration too large: '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn sizes_parse_with_suffixes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64K").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("100mb").unwrap(), 100 * 1024 * 1024);
+        assert_eq!(parse_size(" 2G ").unwrap(), 2 * 1024 * 1024 * 1024);
+    }
+    
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        for text in ["", "K", "12X", "-1", "1.5M", "99999999999999999999", "17179869184G"] {
+            assert!(parse_size(text).is_err(), "{}", text);
+        }
+    }
+    
+    #[test]
+    fn durations_parse_with_units() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+    }
+    
+    #[test]
+    fn invalid_durations_are_rejected() {
+        for text in ["", "s", "10x", "-1s", "NaN", "inf", "1e400", "99999999999999999999", "99999999999999999999h"] {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,75 @@
+//! The grepx command exits like grep: 0 on a match, 1 without one and 2 on errors
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn temp_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("grepx-cli-{}-{}", name, std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn grepx(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_grepx"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn missing() -> String {
+    std::env::temp_dir()
+        .join(format!("grepx-cli-missing-{}", std::process::id()))
+        .display()
+        .to_string()
+}
+
+#[test]
+fn exit_status_tells_matches_from_no_matches_and_errors() {
+    let path = temp_file("status", "hello\nHello again\n");
+    let file = path.to_str().unwrap();
+    
+    let output = grepx(&["hello", file]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Found 1 matches"));
+    
+    let output = grepx(&["nothing", file]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    
+    // Errors win over matches outside quiet mode
+    let output = grepx(&["hello", file, &missing()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!output.stderr.is_empty());
+    
+    assert_eq!(grepx(&["(unclosed", file]).status.code(), Some(2));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn quiet_mode_prints_nothing_and_a_match_wins_over_errors() {
+    let path = temp_file("quiet", "hello\nHello again\n");
+    let file = path.to_str().unwrap();
+    
+    let output = grepx(&["-q", "hello", file]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+    
+    assert_eq!(grepx(&["-q", "nothing", file]).status.code(), Some(1));
+    assert_eq!(grepx(&["-q", "-s", "HELLO", file]).status.code(), Some(1));
+    assert_eq!(grepx(&["-q", "-S", "HELLO", file]).status.code(), Some(1));
+    assert_eq!(grepx(&["-q", "-i", "HELLO", file]).status.code(), Some(0));
+    
+    let output = grepx(&["-q", "hello", file, &missing()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    
+    let output = grepx(&["-q", "nothing", file, &missing()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!output.stderr.is_empty());
+    
+    // Suppressing messages does not change the status
+    let output = grepx(&["-q", "--no-messages", "nothing", file, &missing()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
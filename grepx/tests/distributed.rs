@@ -0,0 +1,260 @@
+//! Searches spread over workers on this machine find what a local search finds
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use grepx::distributed::WorkerBuilder;
+use grepx::{NodeState, SearchResult, SearcherBuilder};
+
+/// Matching lines of a search, by file and line number
+fn lines(result: &SearchResult, strip: impl Fn(&Path) -> PathBuf) -> Vec<(PathBuf, usize, String)> {
+    let mut lines: Vec<_> = result.files.iter()
+        .flat_map(|file| {
+            let path = strip(file.path.as_deref().unwrap());
+            file.lines.iter().map(move |line| (path.clone(), line.line_number, line.line.clone()))
+        })
+        .collect();
+    lines.sort();
+    lines
+}
+
+/// Path of a file on a worker, without the worker's address
+#[cfg(unix)]
+fn strip_worker(path: &Path) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    let bytes = path.as_os_str().as_bytes();
+    let colon = bytes.iter().rposition(|&b| b == b':').expect("path has no worker prefix");
+    PathBuf::from(OsStr::from_bytes(&bytes[colon + 1..]))
+}
+
+#[cfg(not(unix))]
+fn strip_worker(path: &Path) -> PathBuf {
+    let path = path.to_str().unwrap();
+    PathBuf::from(&path[path.rfind(':').expect("path has no worker prefix") + 1..])
+}
+
+fn create_files(dir: &Path) {
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    for i in 0..20 {
+        let content: String = (0..50)
+            .map(|line| if line % 7 == i % 7 { format!("needle {} {}\n", i, line) } else { format!("hay {}\n", line) })
+            .collect();
+        fs::write(dir.join(format!("file{}.txt", i)), &content).unwrap();
+        fs::write(dir.join("nested").join(format!("file{}.log", i)), content).unwrap();
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(dir.join(name), "a needle in a latin-1 name\n").unwrap();
+    }
+}
+
+/// Start workers on this machine serving `dir`, returning their addresses
+fn start_workers(dir: &Path, count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let worker = WorkerBuilder::new()
+                .threads(2)
+                .roots([dir])
+                .bind("127.0.0.1:0")
+                .unwrap();
+            let addr = worker.local_addr().unwrap().to_string();
+            thread::spawn(move || worker.serve());
+            addr
+        })
+        .collect()
+}
+
+/// Start a proxy in front of `worker` that passes messages through until the
+/// first search request, then goes silent as a hung worker would: it neither
+/// answers nor sends heartbeats, but keeps the connection open
+fn start_hanging_proxy(worker: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let client = client.unwrap();
+            let upstream = TcpStream::connect(&worker).unwrap();
+            let hung = Arc::new(AtomicBool::new(false));
+            
+            let (mut from_worker, mut to_client) = (upstream.try_clone().unwrap(), client.try_clone().unwrap());
+            let silenced = hung.clone();
+            thread::spawn(move || {
+                let mut buffer = [0; 8192];
+                while let Ok(len @ 1..) = from_worker.read(&mut buffer) {
+                    if silenced.load(Ordering::SeqCst) || to_client.write_all(&buffer[..len]).is_err() {
+                        break;
+                    }
+                }
+            });
+            thread::spawn(move || {
+                let mut upstream = upstream;
+                for line in BufReader::new(client).lines() {
+                    let Ok(line) = line else { break };
+                    if line.contains(r#""type":"search""#) {
+                        hung.store(true, Ordering::SeqCst);
+                    }
+                    if !hung.load(Ordering::SeqCst) && writeln!(upstream, "{}", line).is_err() {
+                        break;
+                    }
+                }
+                // The coordinator gave up on the worker
+                let _ = upstream.shutdown(Shutdown::Both);
+            });
+        }
+    });
+    addr
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("grepx-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    create_files(&dir);
+    dir
+}
+
+#[test]
+fn workers_find_what_a_local_search_finds() {
+    let dir = temp_dir("distributed");
+    let workers = start_workers(&dir, 3);
+    
+    let local = SearcherBuilder::new("needle")
+        .recursive(true)
+        .build()
+        .unwrap()
+        .search_path(&dir)
+        .unwrap();
+    let distributed = SearcherBuilder::new("needle")
+        .recursive(true)
+        .workers(workers)
+        .build()
+        .unwrap()
+        .search_path(&dir)
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    
+    assert!(distributed.errors.is_empty(), "{:?}", distributed.errors);
+    assert_eq!(distributed.total_matches, local.total_matches);
+    assert_eq!(distributed.files_searched, local.files_searched);
+    assert_eq!(distributed.files_with_matches, local.files_with_matches);
+    assert_eq!(lines(&distributed, strip_worker), lines(&local, Path::to_path_buf));
+}
+
+#[test]
+fn limits_hold_across_workers() {
+    // Files large enough to be shards of their own, searched at the same time
+    let dir = std::env::temp_dir().join(format!("grepx-distributed-limits-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let content = "needle\n".repeat(5 * 1024 * 1024 / 7);
+    for i in 0..3 {
+        fs::write(dir.join(format!("large{}.txt", i)), &content).unwrap();
+    }
+    
+    let workers = start_workers(&dir, 3);
+    let search = |builder: SearcherBuilder| builder
+        .recursive(true)
+        .workers(workers.clone())
+        .build()
+        .unwrap()
+        .search_path(&dir)
+        .unwrap();
+    
+    let result = search(SearcherBuilder::new("needle").max_total(Some(7)));
+    assert_eq!(result.matched_lines, 7);
+    assert_eq!(lines(&result, strip_worker).len(), 7);
+    assert!(result.truncated);
+    
+    let result = search(SearcherBuilder::new("needle").max_files(Some(1)).max_count(Some(5)));
+    assert_eq!(result.files_with_matches, 1);
+    assert_eq!(result.files.iter().filter(|file| !file.lines.is_empty()).count(), 1);
+    assert!(result.truncated);
+    
+    let result = search(SearcherBuilder::new("needle").quit_after_match(true));
+    assert_eq!(result.matched_lines, 1);
+    assert_eq!(lines(&result, strip_worker).len(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn workers_require_the_shared_secret() {
+    let dir = temp_dir("distributed-auth");
+    let worker = WorkerBuilder::new()
+        .secret(Some(b"secret".to_vec()))
+        .roots([&dir])
+        .bind("127.0.0.1:0")
+        .unwrap();
+    let addr = worker.local_addr().unwrap().to_string();
+    thread::spawn(move || worker.serve());
+    
+    let search = |secret: Option<&[u8]>| SearcherBuilder::new("needle")
+        .recursive(true)
+        .workers([addr.clone()])
+        .worker_secret(secret.map(<[u8]>::to_vec))
+        .build()
+        .unwrap()
+        .search_path(&dir)
+        .unwrap();
+    
+    let result = search(Some(b"secret"));
+    assert!(!result.is_partial() && result.total_matches > 0);
+    for secret in [Some(&b"other"[..]), None] {
+        let result = search(secret);
+        assert!(result.is_partial(), "searched with secret {:?}", secret);
+        assert_eq!(result.total_matches, 0);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shards_of_a_hung_worker_are_searched_by_another() {
+    // Files large enough to be shards of their own, so each worker is
+    // handed some of them
+    let dir = temp_dir("distributed-hung");
+    let content = "hay\n".repeat(5 * 1024 * 1024 / 4) + "needle\n";
+    for i in 0..4 {
+        fs::write(dir.join(format!("large{}.txt", i)), &content).unwrap();
+    }
+    let healthy = start_workers(&dir, 1).remove(0);
+    let hung = start_hanging_proxy(start_workers(&dir, 1).remove(0));
+    
+    let local = SearcherBuilder::new("needle")
+        .recursive(true)
+        .build()
+        .unwrap()
+        .search_path(&dir)
+        .unwrap();
+    let distributed = SearcherBuilder::new("needle")
+        .recursive(true)
+        .workers([hung.clone(), healthy.clone()])
+        .worker_timeout(Duration::from_secs(2))
+        .build()
+        .unwrap()
+        .search_path(&dir)
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    
+    let [hung_node, healthy_node] = &distributed.nodes[..] else { panic!("expected two nodes") };
+    assert_eq!(hung_node.address, hung);
+    assert_eq!(hung_node.state, NodeState::Partial);
+    assert_eq!((hung_node.shards, hung_node.files_searched), (0, 0));
+    assert!(hung_node.error.as_deref().unwrap().contains("No heartbeat"), "{:?}", hung_node.error);
+    assert_eq!(healthy_node.state, NodeState::Complete);
+    assert_eq!(healthy_node.files_searched, local.files_searched);
+    
+    // Every file was searched once, by the healthy worker
+    assert!(!distributed.is_partial());
+    assert!(distributed.unsearched_files.is_empty() && distributed.errors.is_empty(), "{:?}", distributed.errors);
+    assert_eq!(distributed.files_searched, local.files_searched);
+    let prefix = format!("{}:", healthy);
+    assert!(distributed.files.iter().all(|file| file.path.as_ref().unwrap().to_string_lossy().starts_with(&prefix)));
+    assert_eq!(lines(&distributed, strip_worker), lines(&local, Path::to_path_buf));
+}
@@ -0,0 +1,74 @@
+//! The HTTP server only serves files under its roots, to clients that name it
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::thread;
+
+use grepx::server::ServerBuilder;
+
+fn start_server(root: &Path) -> SocketAddr {
+    let server = ServerBuilder::new()
+        .roots([root])
+        .hosts(["search.internal"])
+        .bind("127.0.0.1:0")
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.serve());
+    addr
+}
+
+/// Send a request and return the status code and body of the response
+fn request(addr: SocketAddr, host: &str, method: &str, target: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           method, target, host, body.len(), body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body).to_string();
+    (status, body)
+}
+
+#[test]
+fn requests_are_limited_to_the_roots() {
+    let dir = std::env::temp_dir().join(format!("grepx-server-roots-{}", std::process::id()));
+    fs::create_dir_all(dir.join("root")).unwrap();
+    fs::write(dir.join("root/inside.txt"), "needle\n").unwrap();
+    fs::write(dir.join("outside.txt"), "needle\n").unwrap();
+    let addr = start_server(&dir.join("root"));
+    let host = addr.to_string();
+    
+    let inside = dir.join("root/inside.txt").display().to_string();
+    let outside = dir.join("outside.txt").display().to_string();
+    assert_eq!(request(addr, &host, "GET", &format!("/file?path={}", inside), "").0, 200);
+    assert_eq!(request(addr, &host, "GET", &format!("/file?path={}", outside), "").0, 403);
+    let escape = dir.join("root/../outside.txt").display().to_string();
+    assert_eq!(request(addr, &host, "GET", &format!("/file?path={}", escape), "").0, 403);
+    
+    let search = |path: &str| request(addr, &host, "POST", "/search",
+                                      &format!(r#"{{"pattern": "needle", "paths": ["{}"]}}"#, path));
+    let (status, body) = search(&inside);
+    assert_eq!(status, 200);
+    assert!(body.contains("inside.txt"));
+    assert_eq!(search(&outside).0, 403);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unknown_hosts_are_rejected() {
+    let dir = std::env::temp_dir().join(format!("grepx-server-hosts-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let addr = start_server(&dir);
+    let port = addr.port();
+    
+    for host in [addr.to_string(), format!("localhost:{}", port), format!("[::1]:{}", port), "search.internal".to_string()] {
+        assert_eq!(request(addr, &host, "GET", "/health", "").0, 200, "{} was rejected", host);
+    }
+    for host in [format!("attacker.example:{}", port), "10.1.2.3".to_string(), String::new()] {
+        assert_eq!(request(addr, &host, "GET", "/health", "").0, 403, "{} was accepted", host);
+        assert_eq!(request(addr, &host, "GET", "/", "").0, 403, "{} was accepted", host);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}